@@ -0,0 +1,904 @@
+//! An assembler for Octo-style CHIP-8 source.
+//!
+//! Supports labels (`: name`), `:const`, `:alias`, `:macro`, `:org`, `:byte` and `:call`,
+//! the structured `if ... then`, `if ... begin ... else ... end` and `loop ... while ...
+//! again` constructs, and the SUPER-CHIP and XO-CHIP instructions. If the source defines a
+//! `main` label anywhere but at the very start, a jump to it is placed at 0x200 like Octo does.
+
+use crate::chip8::PROGRAM_START_ADDRESS;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+const MAX_ADDRESS: usize = 0xFFFF;
+// Guards against macros that expand themselves forever
+const MAX_MACRO_EXPANSIONS: usize = 10_000;
+
+/// An error in the source, with the 1-based line and column it was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for AssembleError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: impl Into<String>) -> AssembleError {
+        AssembleError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+// An operand that may refer to a label defined further down
+enum Fixup {
+    // NNN in the low 12 bits of the instruction at `position`
+    Address { position: usize, label: Token },
+    // The 16-bit word at `position`, for F000 NNNN
+    Long { position: usize, label: Token },
+}
+
+// An open structured block waiting for its `end`, `else` or `again`
+enum Block {
+    // `position` is the jump to patch when the block ends
+    If {
+        token: Token,
+        position: usize,
+    },
+    Else {
+        token: Token,
+        position: usize,
+    },
+    Loop {
+        token: Token,
+        start: u16,
+        whiles: Vec<usize>,
+    },
+}
+
+// A condition as written after `if` or `while`, as the instruction that skips when it holds
+// and the one that skips when it doesn't
+struct Condition {
+    skip_if_true: u16,
+    skip_if_false: u16,
+}
+
+/// Assembles Octo-style source into a program to load at 0x200.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let tokens = tokenize(source);
+    let mut assembler = Assembler {
+        tokens,
+        position: 0,
+        rom: Vec::new(),
+        written: Vec::new(),
+        here: PROGRAM_START_ADDRESS,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+        expansions: 0,
+    };
+    assembler.run()?;
+    Ok(assembler.rom)
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (line_index, line) in source.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let mut start = None;
+        for (column, c) in code.char_indices().chain([(code.len(), ' ')]) {
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some(column),
+                (true, Some(begin)) => {
+                    tokens.push(Token {
+                        text: code[begin..column].to_string(),
+                        line: line_index + 1,
+                        column: code[..begin].chars().count() + 1,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.chars().all(|c| c.is_ascii_digit()) && !digits.is_empty() {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u16> {
+    let digit = text.strip_prefix('v').or_else(|| text.strip_prefix('V'))?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u16::from_str_radix(digit, 16).ok()
+}
+
+struct Assembler {
+    tokens: Vec<Token>,
+    position: usize,
+    rom: Vec<u8>,
+    // Which bytes of `rom` have been written, so `:org` can't silently overwrite code
+    written: Vec<bool>,
+    here: usize,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u16>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    expansions: usize,
+}
+
+impl Assembler {
+    fn run(&mut self) -> Result<(), AssembleError> {
+        // Octo programs start at `main`; jump there unless it's the first thing in the source
+        let defines_main = self
+            .tokens
+            .windows(2)
+            .any(|pair| pair[0].text == ":" && pair[1].text == "main");
+        let starts_with_main =
+            self.tokens.len() >= 2 && self.tokens[0].text == ":" && self.tokens[1].text == "main";
+        if defines_main && !starts_with_main {
+            let token = self.tokens[0].clone();
+            let main = Token {
+                text: "main".to_string(),
+                ..token.clone()
+            };
+            self.emit_address(&token, 0x1000, &main)?;
+        }
+
+        while let Some(token) = self.next() {
+            self.statement(token)?;
+        }
+
+        if let Some(block) = self.blocks.last() {
+            let (token, expected) = match block {
+                Block::If { token, .. } | Block::Else { token, .. } => (token, "end"),
+                Block::Loop { token, .. } => (token, "again"),
+            };
+            return Err(token.error(format!("'{}' is missing its '{}'", token.text, expected)));
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            match fixup {
+                Fixup::Address { position, label } => {
+                    let address = self.label(&label)?;
+                    if address > 0xFFF {
+                        return Err(label.error(format!(
+                            "label '{}' at 0x{:X} is out of range for a 12-bit address",
+                            label.text, address
+                        )));
+                    }
+                    self.rom[position] |= (address >> 8) as u8;
+                    self.rom[position + 1] = address as u8;
+                }
+                Fixup::Long { position, label } => {
+                    let address = self.label(&label)?;
+                    self.rom[position..position + 2].copy_from_slice(&address.to_be_bytes());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    // Takes the next token, which the statement started by `after` requires
+    fn expect(&mut self, after: &Token) -> Result<Token, AssembleError> {
+        self.next()
+            .ok_or_else(|| after.error(format!("unexpected end of input after '{}'", after.text)))
+    }
+
+    fn expect_text(&mut self, after: &Token, text: &str) -> Result<Token, AssembleError> {
+        let token = self.expect(after)?;
+        if token.text != text {
+            return Err(token.error(format!("expected '{}', found '{}'", text, token.text)));
+        }
+        Ok(token)
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.get(self.position).map(|t| t.text.as_str()) == Some(text)
+    }
+
+    fn label(&self, token: &Token) -> Result<u16, AssembleError> {
+        self.labels
+            .get(&token.text)
+            .copied()
+            .ok_or_else(|| token.error(format!("undefined label '{}'", token.text)))
+    }
+
+    fn register(&mut self, after: &Token) -> Result<u16, AssembleError> {
+        let token = self.expect(after)?;
+        self.register_of(&token)
+    }
+
+    fn register_of(&self, token: &Token) -> Result<u16, AssembleError> {
+        parse_register(&token.text)
+            .or_else(|| self.aliases.get(&token.text).copied())
+            .ok_or_else(|| token.error(format!("expected a register, found '{}'", token.text)))
+    }
+
+    fn value_of(&self, token: &Token) -> Option<i64> {
+        parse_number(&token.text).or_else(|| self.constants.get(&token.text).copied())
+    }
+
+    // A number or constant in `range`
+    fn number(
+        &mut self,
+        after: &Token,
+        range: std::ops::RangeInclusive<i64>,
+    ) -> Result<i64, AssembleError> {
+        let token = self.expect(after)?;
+        let value = self
+            .value_of(&token)
+            .ok_or_else(|| token.error(format!("expected a number, found '{}'", token.text)))?;
+        if !range.contains(&value) {
+            return Err(token.error(format!(
+                "{} is out of range, expected {} to {}",
+                value,
+                range.start(),
+                range.end()
+            )));
+        }
+        Ok(value)
+    }
+
+    fn byte(&mut self, after: &Token) -> Result<u16, AssembleError> {
+        Ok((self.number(after, -128..=255)? as u16) & 0xFF)
+    }
+
+    fn nibble(&mut self, after: &Token) -> Result<u16, AssembleError> {
+        Ok(self.number(after, 0..=15)? as u16)
+    }
+
+    fn write_byte(&mut self, token: &Token, byte: u8) -> Result<(), AssembleError> {
+        if self.here > MAX_ADDRESS {
+            return Err(token.error("program doesn't fit in 64kb of memory"));
+        }
+        let offset = self.here - PROGRAM_START_ADDRESS;
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+            self.written.resize(offset + 1, false);
+        }
+        if self.written[offset] {
+            return Err(token.error(format!("address 0x{:X} is written twice", self.here)));
+        }
+        self.rom[offset] = byte;
+        self.written[offset] = true;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, token: &Token, word: u16) -> Result<(), AssembleError> {
+        self.write_byte(token, (word >> 8) as u8)?;
+        self.write_byte(token, word as u8)
+    }
+
+    // Emits `opcode | NNN` where NNN is a number, constant or (possibly forward) label
+    fn emit_address(
+        &mut self,
+        token: &Token,
+        opcode: u16,
+        target: &Token,
+    ) -> Result<(), AssembleError> {
+        if let Some(value) = self.value_of(target) {
+            if !(0..=0xFFF).contains(&value) {
+                return Err(target.error(format!("address {} is out of range", value)));
+            }
+            return self.emit(token, opcode | value as u16);
+        }
+        if parse_register(&target.text).is_some() || target.text.starts_with(':') {
+            return Err(target.error(format!("expected an address, found '{}'", target.text)));
+        }
+        self.fixups.push(Fixup::Address {
+            position: self.here - PROGRAM_START_ADDRESS,
+            label: target.clone(),
+        });
+        self.emit(token, opcode)
+    }
+
+    // Emits a jump whose target is patched later, returning its position in the ROM
+    fn emit_placeholder_jump(&mut self, token: &Token) -> Result<usize, AssembleError> {
+        let position = self.here - PROGRAM_START_ADDRESS;
+        self.emit(token, 0x1000)?;
+        Ok(position)
+    }
+
+    fn patch_jump(&mut self, token: &Token, position: usize) -> Result<(), AssembleError> {
+        if self.here > 0xFFF {
+            return Err(token.error("structured jump target is out of 12-bit range"));
+        }
+        self.rom[position] = 0x10 | (self.here >> 8) as u8;
+        self.rom[position + 1] = self.here as u8;
+        Ok(())
+    }
+
+    fn define(&mut self, token: &Token, name: &Token) -> Result<(), AssembleError> {
+        let taken = self.labels.contains_key(&name.text)
+            || self.constants.contains_key(&name.text)
+            || self.aliases.contains_key(&name.text)
+            || self.macros.contains_key(&name.text);
+        if taken {
+            return Err(name.error(format!("'{}' is already defined", name.text)));
+        }
+        if parse_number(&name.text).is_some() || parse_register(&name.text).is_some() {
+            return Err(token.error(format!("'{}' can't be used as a name", name.text)));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AssembleError> {
+        match token.text.as_str() {
+            ":" => {
+                let name = self.expect(&token)?;
+                self.define(&token, &name)?;
+                self.labels.insert(name.text, self.here as u16);
+            }
+            ":const" => {
+                let name = self.expect(&token)?;
+                self.define(&token, &name)?;
+                let value = self.number(&name, -0x8000..=0xFFFF)?;
+                self.constants.insert(name.text, value);
+            }
+            ":alias" => {
+                let name = self.expect(&token)?;
+                // Aliases may be redefined, that's how Octo code reuses registers
+                if !self.aliases.contains_key(&name.text) {
+                    self.define(&token, &name)?;
+                }
+                let register = self.register(&name)?;
+                self.aliases.insert(name.text, register);
+            }
+            ":macro" => self.define_macro(&token)?,
+            ":org" => {
+                let address = self.number(&token, PROGRAM_START_ADDRESS as i64..=0xFFFF)?;
+                self.here = address as usize;
+            }
+            ":byte" => {
+                let value = self.byte(&token)?;
+                self.write_byte(&token, value as u8)?;
+            }
+            ":call" => {
+                let target = self.expect(&token)?;
+                self.emit_address(&token, 0x2000, &target)?;
+            }
+            "clear" => self.emit(&token, 0x00E0)?,
+            "return" | ";" => self.emit(&token, 0x00EE)?,
+            "exit" => self.emit(&token, 0x00FD)?,
+            "lores" => self.emit(&token, 0x00FE)?,
+            "hires" => self.emit(&token, 0x00FF)?,
+            "scroll-down" => {
+                let n = self.nibble(&token)?;
+                self.emit(&token, 0x00C0 | n)?;
+            }
+            "scroll-up" => {
+                let n = self.nibble(&token)?;
+                self.emit(&token, 0x00D0 | n)?;
+            }
+            "scroll-right" => self.emit(&token, 0x00FB)?,
+            "scroll-left" => self.emit(&token, 0x00FC)?,
+            "jump" => {
+                let target = self.expect(&token)?;
+                self.emit_address(&token, 0x1000, &target)?;
+            }
+            "jump0" => {
+                let target = self.expect(&token)?;
+                self.emit_address(&token, 0xB000, &target)?;
+            }
+            "sprite" => {
+                let x = self.register(&token)?;
+                let y = self.register(&token)?;
+                let n = self.nibble(&token)?;
+                self.emit(&token, 0xD000 | (x << 8) | (y << 4) | n)?;
+            }
+            "bcd" => {
+                let x = self.register(&token)?;
+                self.emit(&token, 0xF033 | (x << 8))?;
+            }
+            "save" | "load" => {
+                let x = self.register(&token)?;
+                let store = token.text == "save";
+                if self.peek_is("-") {
+                    let dash = self.expect(&token)?;
+                    let y = self.register(&dash)?;
+                    let opcode = if store { 0x5002 } else { 0x5003 };
+                    self.emit(&token, opcode | (x << 8) | (y << 4))?;
+                } else {
+                    let opcode = if store { 0xF055 } else { 0xF065 };
+                    self.emit(&token, opcode | (x << 8))?;
+                }
+            }
+            "saveflags" => {
+                let x = self.register(&token)?;
+                self.emit(&token, 0xF075 | (x << 8))?;
+            }
+            "loadflags" => {
+                let x = self.register(&token)?;
+                self.emit(&token, 0xF085 | (x << 8))?;
+            }
+            "plane" => {
+                let n = self.number(&token, 0..=3)? as u16;
+                self.emit(&token, 0xF001 | (n << 8))?;
+            }
+            "audio" => self.emit(&token, 0xF002)?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect_text(&token, ":=")?;
+                let x = self.register(&token)?;
+                let opcode = match token.text.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.emit(&token, opcode | (x << 8))?;
+            }
+            "i" => self.index_statement(&token)?,
+            "if" => self.if_statement(&token)?,
+            "else" => match self.blocks.pop() {
+                Some(Block::If { position, .. }) => {
+                    let jump = self.emit_placeholder_jump(&token)?;
+                    self.patch_jump(&token, position)?;
+                    self.blocks.push(Block::Else {
+                        token: token.clone(),
+                        position: jump,
+                    });
+                }
+                _ => return Err(token.error("'else' without a matching 'if ... begin'")),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { position, .. } | Block::Else { position, .. }) => {
+                    self.patch_jump(&token, position)?;
+                }
+                _ => return Err(token.error("'end' without a matching 'if ... begin'")),
+            },
+            "loop" => self.blocks.push(Block::Loop {
+                token: token.clone(),
+                start: self.here as u16,
+                whiles: Vec::new(),
+            }),
+            "while" => {
+                if !matches!(self.blocks.last(), Some(Block::Loop { .. })) {
+                    return Err(token.error("'while' outside of a 'loop'"));
+                }
+                let condition = self.condition(&token)?;
+                self.emit(&token, condition.skip_if_true)?;
+                let jump = self.emit_placeholder_jump(&token)?;
+                if let Some(Block::Loop { whiles, .. }) = self.blocks.last_mut() {
+                    whiles.push(jump);
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, whiles, .. }) => {
+                    if start > 0xFFF {
+                        return Err(token.error("loop start is out of 12-bit jump range"));
+                    }
+                    self.emit(&token, 0x1000 | start)?;
+                    for jump in whiles {
+                        self.patch_jump(&token, jump)?;
+                    }
+                }
+                _ => return Err(token.error("'again' without a matching 'loop'")),
+            },
+            _ => {
+                if let Some(x) =
+                    parse_register(&token.text).or_else(|| self.aliases.get(&token.text).copied())
+                {
+                    self.register_statement(&token, x)?;
+                } else if self.macros.contains_key(&token.text) {
+                    self.expand_macro(&token)?;
+                } else if let Some(value) = self.value_of(&token) {
+                    // Bare numbers are data bytes
+                    if !(-128..=255).contains(&value) {
+                        return Err(token.error(format!("byte {} is out of range", value)));
+                    }
+                    self.write_byte(&token, value as u8)?;
+                } else if token.text.starts_with(':') || token.text.contains(":=") {
+                    return Err(token.error(format!("unknown directive '{}'", token.text)));
+                } else {
+                    // Any other name calls a subroutine, possibly one defined later
+                    self.emit_address(&token, 0x2000, &token)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn index_statement(&mut self, token: &Token) -> Result<(), AssembleError> {
+        let op = self.expect(token)?;
+        match op.text.as_str() {
+            ":=" => {
+                let operand = self.expect(&op)?;
+                match operand.text.as_str() {
+                    "hex" => {
+                        let x = self.register(&operand)?;
+                        self.emit(token, 0xF029 | (x << 8))
+                    }
+                    "bighex" => {
+                        let x = self.register(&operand)?;
+                        self.emit(token, 0xF030 | (x << 8))
+                    }
+                    "long" => {
+                        let target = self.expect(&operand)?;
+                        self.emit(token, 0xF000)?;
+                        match self.value_of(&target) {
+                            Some(value) if (0..=0xFFFF).contains(&value) => {
+                                self.emit(token, value as u16)
+                            }
+                            Some(value) => {
+                                Err(target.error(format!("address {} is out of range", value)))
+                            }
+                            None => {
+                                self.fixups.push(Fixup::Long {
+                                    position: self.here - PROGRAM_START_ADDRESS,
+                                    label: target,
+                                });
+                                self.emit(token, 0)
+                            }
+                        }
+                    }
+                    _ => self.emit_address(token, 0xA000, &operand),
+                }
+            }
+            "+=" => {
+                let x = self.register(&op)?;
+                self.emit(token, 0xF01E | (x << 8))
+            }
+            _ => Err(op.error(format!(
+                "expected ':=' or '+=' after 'i', found '{}'",
+                op.text
+            ))),
+        }
+    }
+
+    fn register_statement(&mut self, token: &Token, x: u16) -> Result<(), AssembleError> {
+        let op = self.expect(token)?;
+        let operand = self.expect(&op)?;
+        let y = parse_register(&operand.text).or_else(|| self.aliases.get(&operand.text).copied());
+        let opcode = match (op.text.as_str(), y) {
+            (":=", Some(y)) => 0x8000 | (x << 8) | (y << 4),
+            ("|=", Some(y)) => 0x8001 | (x << 8) | (y << 4),
+            ("&=", Some(y)) => 0x8002 | (x << 8) | (y << 4),
+            ("^=", Some(y)) => 0x8003 | (x << 8) | (y << 4),
+            ("+=", Some(y)) => 0x8004 | (x << 8) | (y << 4),
+            ("-=", Some(y)) => 0x8005 | (x << 8) | (y << 4),
+            (">>=", Some(y)) => 0x8006 | (x << 8) | (y << 4),
+            ("=-", Some(y)) => 0x8007 | (x << 8) | (y << 4),
+            ("<<=", Some(y)) => 0x800E | (x << 8) | (y << 4),
+            (":=", None) => match operand.text.as_str() {
+                "delay" => 0xF007 | (x << 8),
+                "key" => 0xF00A | (x << 8),
+                "random" => 0xC000 | (x << 8) | self.byte(&operand)?,
+                _ => {
+                    self.position -= 1;
+                    0x6000 | (x << 8) | self.byte(&op)?
+                }
+            },
+            ("+=", None) => {
+                self.position -= 1;
+                0x7000 | (x << 8) | self.byte(&op)?
+            }
+            ("-=", None) => {
+                // There's no subtract-immediate, add the two's complement instead
+                self.position -= 1;
+                let value = self.number(&op, -255..=255)?;
+                0x7000 | (x << 8) | ((-value) as u16 & 0xFF)
+            }
+            _ => {
+                return Err(op.error(format!(
+                    "'{}' can't be used with '{}'",
+                    op.text, operand.text
+                )))
+            }
+        };
+        self.emit(token, opcode)
+    }
+
+    // Parses `vX == n`, `vX != vY`, `vX key` and the like
+    fn condition(&mut self, token: &Token) -> Result<Condition, AssembleError> {
+        let x = self.register(token)?;
+        let op = self.expect(token)?;
+        let (skip_if_true, skip_if_false) = match op.text.as_str() {
+            "key" => (0xE09E | (x << 8), 0xE0A1 | (x << 8)),
+            "-key" => (0xE0A1 | (x << 8), 0xE09E | (x << 8)),
+            "==" | "!=" => {
+                let operand = self.expect(&op)?;
+                let y = parse_register(&operand.text)
+                    .or_else(|| self.aliases.get(&operand.text).copied());
+                let (equal, not_equal) = match y {
+                    Some(y) => (0x5000 | (x << 8) | (y << 4), 0x9000 | (x << 8) | (y << 4)),
+                    None => {
+                        self.position -= 1;
+                        let n = self.byte(&op)?;
+                        (0x3000 | (x << 8) | n, 0x4000 | (x << 8) | n)
+                    }
+                };
+                if op.text == "==" {
+                    (equal, not_equal)
+                } else {
+                    (not_equal, equal)
+                }
+            }
+            _ => {
+                return Err(op.error(format!(
+                    "unsupported condition '{}', expected ==, !=, key or -key",
+                    op.text
+                )))
+            }
+        };
+        Ok(Condition {
+            skip_if_true,
+            skip_if_false,
+        })
+    }
+
+    fn if_statement(&mut self, token: &Token) -> Result<(), AssembleError> {
+        let condition = self.condition(token)?;
+        let keyword = self.expect(token)?;
+        match keyword.text.as_str() {
+            // The next statement only runs if the condition holds
+            "then" => self.emit(token, condition.skip_if_false),
+            // Jump past the block unless the condition holds
+            "begin" => {
+                self.emit(token, condition.skip_if_true)?;
+                let position = self.emit_placeholder_jump(token)?;
+                self.blocks.push(Block::If {
+                    token: token.clone(),
+                    position,
+                });
+                Ok(())
+            }
+            _ => Err(keyword.error(format!(
+                "expected 'then' or 'begin', found '{}'",
+                keyword.text
+            ))),
+        }
+    }
+
+    fn define_macro(&mut self, token: &Token) -> Result<(), AssembleError> {
+        let name = self.expect(token)?;
+        self.define(token, &name)?;
+        let mut params = Vec::new();
+        loop {
+            let param = self.expect(&name)?;
+            if param.text == "{" {
+                break;
+            }
+            params.push(param.text);
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let part = self
+                .next()
+                .ok_or_else(|| name.error(format!("macro '{}' is missing its '}}'", name.text)))?;
+            match part.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            body.push(part);
+        }
+        self.macros.insert(name.text, Macro { params, body });
+        Ok(())
+    }
+
+    // Replaces the macro call and its arguments with the macro body
+    fn expand_macro(&mut self, token: &Token) -> Result<(), AssembleError> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return Err(token.error("too many macro expansions, is a macro recursive?"));
+        }
+        let definition = &self.macros[&token.text];
+        let param_count = definition.params.len();
+        let args: Vec<Token> = self
+            .tokens
+            .get(self.position..self.position + param_count)
+            .ok_or_else(|| {
+                token.error(format!(
+                    "macro '{}' takes {} arguments",
+                    token.text, param_count
+                ))
+            })?
+            .to_vec();
+        let expansion: Vec<Token> = definition
+            .body
+            .iter()
+            .map(|part| {
+                match definition
+                    .params
+                    .iter()
+                    .position(|param| *param == part.text)
+                {
+                    // Arguments keep the body's position so errors point into the macro
+                    Some(index) => Token {
+                        text: args[index].text.clone(),
+                        ..part.clone()
+                    },
+                    None => part.clone(),
+                }
+            })
+            .collect();
+        self.tokens
+            .splice(self.position..self.position + param_count, expansion);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        assemble(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn resolves_forward_and_backward_labels() {
+        let source = "
+            : start
+              v0 := 1
+              jump later
+              jump start
+            : later
+              :call start
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x60, 0x01, 0x12, 0x06, 0x12, 0x00, 0x22, 0x00]
+        );
+    }
+
+    #[test]
+    fn expands_constants_aliases_and_macros() {
+        let source = "
+            :const SPEED 3
+            :alias x v4
+            :macro bump reg amount { reg += amount }
+            x := SPEED
+            bump x 2
+            bump v1 SPEED
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x64, 0x03, 0x74, 0x02, 0x71, 0x03]
+        );
+    }
+
+    #[test]
+    fn org_leaves_gaps_but_never_overwrites() {
+        assert_eq!(
+            assemble("v0 := 1\n:org 0x206\nv2 := 3").unwrap(),
+            [0x60, 0x01, 0, 0, 0, 0, 0x62, 0x03]
+        );
+        assert_eq!(
+            error("v0 := 1\nv1 := 2\n:org 0x202\nv2 := 3"),
+            "4:1: address 0x202 is written twice"
+        );
+    }
+
+    #[test]
+    fn assembles_if_then_and_if_else_blocks() {
+        let source = "
+            if v0 == 5 then v1 := 1
+            if v2 != v3 begin
+              v4 := 1
+            else
+              v4 := 2
+            end
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [
+                0x40, 0x05, 0x61, 0x01, // SNE V0, 5; LD V1, 1
+                0x92, 0x30, 0x12, 0x0C, // SNE V2, V3; JP else
+                0x64, 0x01, 0x12, 0x0E, // LD V4, 1; JP end
+                0x64, 0x02,
+            ]
+        );
+    }
+
+    #[test]
+    fn assembles_loops_with_while() {
+        let source = "
+            loop
+              v0 += 1
+              while v0 != 10
+              v1 += 1
+            again
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x70, 0x01, 0x40, 0x0A, 0x12, 0x0A, 0x71, 0x01, 0x12, 0x00]
+        );
+        // The jump back can only reach 12-bit addresses
+        assert_eq!(
+            error(":org 0x1000 loop v0 += 1 again"),
+            "1:26: loop start is out of 12-bit jump range"
+        );
+    }
+
+    #[test]
+    fn jumps_to_main_unless_it_comes_first() {
+        let source = "
+            : helper
+              return
+            : main
+              helper
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x12, 0x04, 0x00, 0xEE, 0x22, 0x02]
+        );
+        assert_eq!(assemble(": main v0 := 1").unwrap(), [0x60, 0x01]);
+    }
+
+    #[test]
+    fn errors_point_at_line_and_column() {
+        assert_eq!(
+            error("v0 := 1\n  v1 += 300"),
+            "2:9: 300 is out of range, expected -128 to 255"
+        );
+        assert_eq!(error("jump nowhere"), "1:6: undefined label 'nowhere'");
+        assert_eq!(
+            error("loop\n  v0 += 1"),
+            "1:1: 'loop' is missing its 'again'"
+        );
+        assert_eq!(
+            error("v0 := 1\nif v0 > 2 then"),
+            "2:7: unsupported condition '>', expected ==, !=, key or -key"
+        );
+    }
+}
@@ -0,0 +1,255 @@
+#[cfg(any(feature = "sdl", test))]
+use chip_8::Chip8;
+#[cfg(feature = "sdl")]
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+#[cfg(feature = "sdl")]
+use sdl2::AudioSubsystem;
+
+// Beeper settings are parsed from the command line even in builds without SDL, only the
+// playback below needs the `sdl` feature
+
+#[cfg(feature = "sdl")]
+const SAMPLE_RATE: i32 = 44100;
+// Length of the fade in/out applied when the tone starts or stops, avoids clicks
+#[cfg(any(feature = "sdl", test))]
+const RAMP_SECONDS: f32 = 0.005;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl Waveform {
+    // Sample of the waveform at `phase` in [0, 1), in [-1, 1]
+    #[cfg(any(feature = "sdl", test))]
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (phase * std::f32::consts::TAU).sin(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioConfig {
+    pub frequency: f32, // tone frequency in Hz
+    pub volume: f32,    // 0.0 - 1.0
+    pub waveform: Waveform,
+    pub muted: bool,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+            muted: false,
+        }
+    }
+}
+
+// Generates the tone on SDL's audio thread. The device is never paused; the emulator only
+// flips `active`, so the tone keeps playing without gaps even if the emulation loop stutters
+#[cfg(any(feature = "sdl", test))]
+struct Beeper {
+    config: AudioConfig,
+    sample_rate: f32,
+    active: bool,
+    phase: f32,
+    gain: f32,
+    // XO-CHIP 1-bit pattern and its playback rate in bits per second, replaces the tone once
+    // F002 has loaded one
+    pattern: Option<([u8; 16], f32)>,
+}
+
+#[cfg(any(feature = "sdl", test))]
+impl Beeper {
+    fn new(config: AudioConfig, sample_rate: f32) -> Self {
+        Beeper {
+            config,
+            sample_rate,
+            active: false,
+            phase: 0.0,
+            gain: 0.0,
+            pattern: None,
+        }
+    }
+
+    // Starts or stops the tone to follow the sound timer, and picks up the audio pattern
+    fn follow(&mut self, chip8: &Chip8) {
+        self.active = chip8.sound_timer > 0;
+        self.pattern = chip8
+            .audio_pattern
+            .filter(|_| chip8.quirks.xo_chip)
+            .map(|pattern| (pattern, chip8.audio_playback_rate()));
+    }
+
+    fn fill(&mut self, out: &mut [f32]) {
+        let target = if self.active && !self.config.muted {
+            1.0
+        } else {
+            0.0
+        };
+        let ramp_step = 1.0 / (self.sample_rate * RAMP_SECONDS);
+
+        for sample in out.iter_mut() {
+            if self.gain < target {
+                self.gain = (self.gain + ramp_step).min(target);
+            } else if self.gain > target {
+                self.gain = (self.gain - ramp_step).max(target);
+            }
+
+            let (value, step) = match self.pattern {
+                Some((pattern, rate)) => {
+                    let bit = (self.phase * 128.0) as usize % 128;
+                    let on = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+                    (if on { 1.0 } else { -1.0 }, rate / 128.0 / self.sample_rate)
+                }
+                None => (
+                    self.config.waveform.sample(self.phase),
+                    self.config.frequency / self.sample_rate,
+                ),
+            };
+            *sample = value * self.config.volume * self.gain;
+            self.phase = (self.phase + step).fract();
+        }
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl AudioCallback for Beeper {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.fill(out);
+    }
+}
+
+#[cfg(feature = "sdl")]
+pub struct Audio {
+    device: AudioDevice<Beeper>,
+}
+
+#[cfg(feature = "sdl")]
+impl Audio {
+    pub fn new(audio_subsystem: &AudioSubsystem, config: AudioConfig) -> Result<Self, String> {
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: Some(512),
+        };
+        let device = audio_subsystem
+            .open_playback(None, &desired, |spec| Beeper::new(config, spec.freq as f32))?;
+        device.resume();
+        Ok(Audio { device })
+    }
+
+    // Starts or stops the tone to follow the sound timer, called once per frame
+    pub fn update(&mut self, chip8: &Chip8) {
+        self.device.lock().follow(chip8);
+    }
+
+    // Returns whether audio is muted after toggling
+    pub fn toggle_mute(&mut self) -> bool {
+        let mut beeper = self.device.lock();
+        beeper.config.muted = !beeper.config.muted;
+        beeper.config.muted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip_8::Quirks;
+
+    // One sample per bit of the pattern at the default 4000 bits per second
+    const SAMPLE_RATE: f32 = 4000.0;
+
+    fn beeper() -> Beeper {
+        let config = AudioConfig {
+            volume: 1.0,
+            ..AudioConfig::default()
+        };
+        let mut beeper = Beeper::new(config, SAMPLE_RATE);
+        // Skip the fade in
+        beeper.gain = 1.0;
+        beeper
+    }
+
+    fn samples(beeper: &mut Beeper, count: usize) -> Vec<f32> {
+        let mut out = vec![0.0; count];
+        beeper.fill(&mut out);
+        out
+    }
+
+    #[test]
+    fn plays_the_pattern_only_once_one_is_loaded() {
+        let mut chip8 = Chip8::new(Quirks::XO_CHIP);
+        chip8.sound_timer = 10;
+        let mut beeper = beeper();
+
+        // Without F002 the configured 440 Hz square wave plays, not a silent pattern
+        beeper.follow(&chip8);
+        assert!(beeper.active);
+        assert_eq!(beeper.pattern, None);
+        let tone = samples(&mut beeper, 10);
+        assert_eq!(&tone[..5], &[1.0; 5]);
+        assert_eq!(&tone[5..], &[-1.0; 5]);
+
+        let mut pattern = [0; 16];
+        pattern[0] = 0b1010_0000;
+        chip8.audio_pattern = Some(pattern);
+        beeper.follow(&chip8);
+        beeper.phase = 0.0;
+        assert_eq!(
+            samples(&mut beeper, 4),
+            [1.0, -1.0, 1.0, -1.0],
+            "one sample per bit"
+        );
+
+        // Other platforms have no pattern buffer, even if one is set
+        chip8.quirks = Quirks::COSMAC_VIP;
+        beeper.follow(&chip8);
+        assert_eq!(beeper.pattern, None);
+    }
+
+    #[test]
+    fn ramps_the_gain_instead_of_cutting_the_tone() {
+        let mut beeper = beeper();
+        beeper.gain = 0.0;
+        beeper.active = true;
+        let ramp = (SAMPLE_RATE * RAMP_SECONDS).round() as usize;
+
+        let fade_in = samples(&mut beeper, ramp + 1);
+        // The square wave flips sign along the way, its level only rises
+        assert!(fade_in[0] > 0.0 && fade_in[0] < 1.0);
+        assert!(fade_in
+            .windows(2)
+            .all(|pair| pair[1].abs() >= pair[0].abs()));
+        assert_eq!(beeper.gain, 1.0);
+
+        beeper.active = false;
+        let fade_out = samples(&mut beeper, ramp + 1);
+        assert!(fade_out[0].abs() > 0.0);
+        assert_eq!(beeper.gain, 0.0);
+        assert_eq!(*fade_out.last().unwrap(), 0.0);
+
+        // Muting fades out the same way
+        beeper.active = true;
+        beeper.config.muted = true;
+        samples(&mut beeper, ramp + 1);
+        assert_eq!(beeper.gain, 0.0);
+    }
+}
@@ -1,49 +1,101 @@
-use std::usize;
+use crate::error::{Chip8Error, Fault};
+use crate::hash;
+use crate::instruction::Instruction;
+use crate::quirks::{IndexIncrement, Quirks};
+use crate::random::{FixedSequence, RandomSource, Xorshift};
+use crate::trace::{TraceState, Tracer};
+use std::ops::Range;
 
 const MEMORY_SIZE: usize = 4096;
+const XO_CHIP_MEMORY_SIZE: usize = 0x10000;
 const REGISTER_COUNT: usize = 16;
 const STACK_SIZE: usize = 16;
+/// Width of the lo-res display in pixels.
 pub const SCREEN_WIDTH: usize = 64;
+/// Height of the lo-res display in pixels.
 pub const SCREEN_HEIGHT: usize = 32;
+/// Width of the SUPER-CHIP hi-res display in pixels.
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+/// Height of the SUPER-CHIP hi-res display in pixels.
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
 const FONTSET_SIZE: usize = 80;
 const FONTSET_START_ADDRESS: usize = 0x50;
-const PROGRAM_START_ADDRESS: usize = 0x200;
-
-type OpcodeHandler = fn(&mut Chip8, u16);
+const BIG_FONTSET_SIZE: usize = 160;
+const BIG_FONTSET_START_ADDRESS: usize = FONTSET_START_ADDRESS + FONTSET_SIZE;
+const RPL_FLAG_COUNT: usize = 16;
+const AUDIO_PATTERN_SIZE: usize = 16;
+/// Address programs are loaded at and start executing from.
+pub const PROGRAM_START_ADDRESS: usize = 0x200;
 
+/// A complete CHIP-8 machine: memory, registers, display, timers and keypad.
+///
+/// Fields are public so frontends and tools can inspect and poke machine state directly.
 pub struct Chip8 {
-    pub memory: [u8; MEMORY_SIZE],       // 4kb memory
+    pub memory: Vec<u8>,                 // 4kb memory, 64kb with XO-CHIP
     pub registers: [u8; REGISTER_COUNT], // 16 general purpose registers
     pub index_register: u16,
     pub program_counter: u16,
-    pub screen: [u8; SCREEN_WIDTH * SCREEN_HEIGHT], // 64x32 pixel display
+    pub screen: Vec<u8>, // 64x32 pixel display, 128x64 in hi-res mode; one bit per plane
+    pub hires: bool,
+    pub selected_planes: u8, // bitmask of the planes drawn to, only plane 1 unless XO-CHIP
     pub delay_timer: u8,
     pub sound_timer: u8,
     pub stack: [u16; STACK_SIZE], // stack with 16 levels
     pub stack_pointer: u8,        // stack pointer
     pub keys: [u8; REGISTER_COUNT],
-    pub jump_table: [OpcodeHandler; 16],
+    pub rpl_flags: [u8; RPL_FLAG_COUNT], // SUPER-CHIP user flags, persisted by FX75/FX85
+    pub exited: bool,                    // set by 00FD, the machine stops executing afterwards
+    pub audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>, // XO-CHIP 1-bit sample buffer, None until F002 loads one
+    pub pitch: u8,                                       // XO-CHIP playback pitch set by FX3A
+    pub quirks: Quirks,
+    pub rom_hash: u64, // FNV-1a hash of the loaded program, identifies it in save states
+    pub(crate) vblank: bool, // set on every timer tick, consumed by DXYN when the display wait quirk is on
+    pub seed: u64, // seed of the default random source, recorded in save states and replays
+    pub(crate) random: Box<dyn RandomSource>,
+    pub(crate) tracer: Option<Tracer>,
 }
 
 impl Chip8 {
-    pub fn new() -> Self {
+    /// Creates a machine with the fonts loaded and PC at [`PROGRAM_START_ADDRESS`].
+    ///
+    /// Memory is 64kb instead of 4kb when `quirks.xo_chip` is set. RND is seeded randomly, see
+    /// [`Chip8::set_seed`] for reproducible runs.
+    pub fn new(quirks: Quirks) -> Self {
+        let seed = rand::random();
+        let memory_size = if quirks.xo_chip {
+            XO_CHIP_MEMORY_SIZE
+        } else {
+            MEMORY_SIZE
+        };
         let mut chip8 = Chip8 {
-            memory: [0; MEMORY_SIZE], //figure it retard
+            memory: vec![0; memory_size], //figure it retard
             registers: [0; REGISTER_COUNT],
             index_register: 0,
             program_counter: PROGRAM_START_ADDRESS as u16, // Programs start at 0x200
-            screen: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            screen: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            hires: false,
+            selected_planes: 0b01,
             delay_timer: 0,
             sound_timer: 0,
             stack: [0; STACK_SIZE],
             stack_pointer: 0,
             keys: [0; REGISTER_COUNT],
-            jump_table: Chip8::create_jump_table(),
+            rpl_flags: [0; RPL_FLAG_COUNT],
+            exited: false,
+            audio_pattern: None,
+            pitch: 64,
+            quirks,
+            rom_hash: hash::fnv1a(&[]),
+            vblank: true,
+            seed,
+            random: Box::new(Xorshift::new(seed)),
+            tracer: None,
         };
         chip8.load_fonts();
         chip8
     }
 
+    /// Writes the small and large hex digit sprites into low memory.
     pub fn load_fonts(&mut self) {
         let fontset: [u8; FONTSET_SIZE] = [
             0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -66,386 +118,1527 @@ impl Chip8 {
         for (i, &byte) in fontset.iter().enumerate() {
             self.memory[FONTSET_START_ADDRESS + i] = byte;
         }
+
+        // SUPER-CHIP 8x10 digits used by FX30
+        let big_fontset: [u8; BIG_FONTSET_SIZE] = [
+            0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+            0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+            0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+            0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+            0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+            0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+            0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+            0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+            0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+            0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+            0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+            0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+            0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+        ];
+        for (i, &byte) in big_fontset.iter().enumerate() {
+            self.memory[BIG_FONTSET_START_ADDRESS + i] = byte;
+        }
+    }
+
+    /// Restarts the default random source from `seed`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.random = Box::new(Xorshift::new(seed));
     }
 
-    pub fn load_program(&mut self, program: &[u8]) {
+    /// Replaces the random source RND draws from, e.g. with a
+    /// [`FixedSequence`](crate::random::FixedSequence) in tests.
+    pub fn set_random_source(&mut self, source: Box<dyn RandomSource>) {
+        self.random = source;
+    }
+
+    /// Attaches a tracer that records every instruction [`Chip8::emulate_cycle`] runs, or
+    /// detaches the current one with None.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    /// Detaches the tracer, e.g. to [`finish`](Tracer::finish) it.
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Sample rate of the XO-CHIP audio pattern in bits per second, 4000 at the default pitch
+    pub fn audio_playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    /// Current display width in pixels, 64 in lo-res and 128 in hi-res
+    pub fn screen_width(&self) -> usize {
+        if self.hires {
+            HIRES_SCREEN_WIDTH
+        } else {
+            SCREEN_WIDTH
+        }
+    }
+
+    /// Current display height in pixels, 32 in lo-res and 64 in hi-res
+    pub fn screen_height(&self) -> usize {
+        if self.hires {
+            HIRES_SCREEN_HEIGHT
+        } else {
+            SCREEN_HEIGHT
+        }
+    }
+
+    /// Copies `program` into memory at [`PROGRAM_START_ADDRESS`].
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), Chip8Error> {
+        let capacity = self.memory.len() - PROGRAM_START_ADDRESS;
+        if program.len() > capacity {
+            return Err(Chip8Error::ProgramTooLarge {
+                size: program.len(),
+                capacity,
+            });
+        }
         for (i, &byte) in program.iter().enumerate() {
-            self.memory[0x200 + i] = byte;
-        }
-    }
-
-    fn create_jump_table() -> [OpcodeHandler; 16] {
-        [
-            Chip8::op_0xxx,          // 0x0XXX group
-            Chip8::jp,               // 0x1XXX
-            Chip8::call,             // 0x2XXX
-            Chip8::se_vx_byte,       // 0x3XXX
-            Chip8::sne_vx_byte,      // 0x4XXX
-            Chip8::se_vx_vy,         // 0x5XXX
-            Chip8::ld_vx_byte,       // 0x6XXX
-            Chip8::add_vx_byte,      // 0x7XXX
-            Chip8::op_8xxx,          // 0x8XXX group
-            Chip8::sne_vx_vy,        // 0x9XXX
-            Chip8::ld_i_addr,        // 0xAXXX
-            Chip8::jp_v0_addr,       // 0xBXXX
-            Chip8::rnd_vx_byte,      // 0xCXXX
-            Chip8::drw_vx_vy_nibble, // 0xDXXX
-            Chip8::op_exxx,          // 0xEXXX group
-            Chip8::op_fxxx,          // 0xFXXX group
-        ]
-    }
-
-    pub fn emulate_cycle(&mut self) {
-        let opcode = self.fetch_opcode();
-        self.program_counter += 2;
-        let index = (opcode & 0xF000) >> 12;
-        let handler = self.jump_table[index as usize];
-        handler(self, opcode);
+            self.memory[PROGRAM_START_ADDRESS + i] = byte;
+        }
+        self.rom_hash = hash::fnv1a(program);
+        Ok(())
+    }
+
+    /// Fetches, decodes and executes a single instruction. Does nothing once the program has
+    /// exited; timers are left alone, see [`Chip8::tick_timers`].
+    pub fn emulate_cycle(&mut self) -> Result<(), Chip8Error> {
+        if self.exited {
+            return Ok(());
+        }
+        let pc = self.program_counter;
+        let opcode = self.fetch_opcode()?;
+        let traced = self
+            .tracer
+            .as_mut()
+            .is_some_and(|tracer| tracer.begin(pc, opcode));
+        let before = traced.then(|| TraceState::capture(self));
+
+        self.program_counter = self.program_counter.wrapping_add(2);
+        let result = self.execute(opcode).map_err(|fault| fault.at(pc, opcode));
+
+        if let Some(before) = before {
+            // The tracer reads the machine it's part of, so it's lent out while it records
+            if let Some(mut tracer) = self.tracer.take() {
+                tracer.record(self, opcode, before, &result);
+                self.tracer = Some(tracer);
+            }
+        }
+        result
+    }
 
+    /// Decrements the delay and sound timers and signals vblank. Meant to be called at 60 Hz,
+    /// independently of how many instructions run in between
+    pub fn tick_timers(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
         if self.sound_timer > 0 {
             self.sound_timer -= 1;
         }
+        self.vblank = true;
     }
 
-    pub fn fetch_opcode(&self) -> u16 {
-        let high_byte = self.memory[self.program_counter as usize] as u16;
-        let low_byte = self.memory[(self.program_counter + 1) as usize] as u16;
-        (high_byte << 8) | low_byte
+    /// Runs one 60 Hz frame: `instructions_per_frame` instructions followed by a timer tick.
+    /// Stops early if the program exits or faults
+    pub fn run_frame(&mut self, instructions_per_frame: usize) -> Result<(), Chip8Error> {
+        for _ in 0..instructions_per_frame {
+            if self.exited {
+                break;
+            }
+            self.emulate_cycle()?;
+        }
+        self.tick_timers();
+        Ok(())
     }
 
-    fn op_0xxx(&mut self, opcode: u16) {
-        match opcode & 0x00FF {
-            0x00E0 => self.cls(),
-            0x00EE => self.ret(),
-            _ => unimplemented!("opcode {:04x} not implemented", opcode),
+    /// Reads the 16-bit opcode at PC without executing it.
+    pub fn fetch_opcode(&self) -> Result<u16, Chip8Error> {
+        let pc = self.program_counter as usize;
+        if pc + 1 >= self.memory.len() {
+            return Err(Chip8Error::PcOutOfRange {
+                pc: self.program_counter,
+            });
         }
+        let high_byte = self.memory[pc] as u16;
+        let low_byte = self.memory[pc + 1] as u16;
+        Ok((high_byte << 8) | low_byte)
     }
 
-    fn op_8xxx(&mut self, opcode: u16) {
-        match opcode & 0x000F {
-            0x0000 => self.ld_vx_vy(opcode),
-            0x0001 => self.or_vx_vy(opcode),
-            0x0002 => self.and_vx_vy(opcode),
-            0x0003 => self.xor_vx_vy(opcode),
-            0x0004 => self.add_vx_vy(opcode),
-            0x0005 => self.sub_vx_vy(opcode),
-            0x0006 => self.shr_vx(opcode),
-            0x0007 => self.subn_vx_vy(opcode),
-            0x000E => self.shl_vx(opcode),
-            _ => unimplemented!("Opcode {:04x} not implemented", opcode),
+    // Returns the memory range [start, start + len), or the first address past the end of memory
+    fn memory_range(&self, start: usize, len: usize) -> Result<Range<usize>, Fault> {
+        if start + len > self.memory.len() {
+            return Err(Fault::MemoryOutOfBounds(start.max(self.memory.len())));
         }
+        Ok(start..start + len)
     }
 
-    fn op_exxx(&mut self, opcode: u16) {
-        match opcode & 0x00FF {
-            0x009E => self.skp_vx(opcode),
-            0x00A1 => self.sknp_vx(opcode),
-            _ => unimplemented!("Opcode {:04x} not implemented", opcode),
-        }
+    // Skips the instruction at PC, which is four bytes long for XO-CHIP's F000 NNNN
+    fn skip_next_instruction(&mut self) {
+        let pc = self.program_counter as usize;
+        let long_load = self.quirks.xo_chip
+            && self.memory.get(pc) == Some(&0xF0)
+            && self.memory.get(pc + 1) == Some(&0x00);
+        let length = if long_load { 4 } else { 2 };
+        self.program_counter = self.program_counter.wrapping_add(length);
     }
 
-    fn op_fxxx(&mut self, opcode: u16) {
-        match opcode & 0x00FF {
-            0x0007 => self.ld_vx_dt(opcode),
-            0x000A => self.ld_vx_k(opcode),
-            0x0015 => self.ld_dt_vx(opcode),
-            0x0018 => self.ld_st_vx(opcode),
-            0x001E => self.add_i_vx(opcode),
-            0x0029 => self.ld_f_vx(opcode),
-            0x0033 => self.ld_b_vx(opcode),
-            0x0055 => self.ld_i_vx(opcode),
-            0x0065 => self.ld_vx_i(opcode),
-            _ => unimplemented!("Opcode {:04x} not implemented", opcode),
+    // Decodes an opcode and runs its handler, rejecting XO-CHIP instructions unless the
+    // extensions are enabled
+    fn execute(&mut self, opcode: u16) -> Result<(), Fault> {
+        let instruction = match Instruction::decode(opcode) {
+            Ok(instruction) if self.quirks.xo_chip || !instruction.is_xo_chip() => instruction,
+            _ => return Err(Fault::UnknownOpcode),
+        };
+        match instruction {
+            Instruction::Cls => self.cls(),
+            Instruction::Ret => self.ret(),
+            Instruction::Jp { addr } => self.jp(addr),
+            Instruction::Call { addr } => self.call(addr),
+            Instruction::SeVxByte { x, byte } => self.se_vx_byte(x.into(), byte),
+            Instruction::SneVxByte { x, byte } => self.sne_vx_byte(x.into(), byte),
+            Instruction::SeVxVy { x, y } => self.se_vx_vy(x.into(), y.into()),
+            Instruction::LdVxByte { x, byte } => self.ld_vx_byte(x.into(), byte),
+            Instruction::AddVxByte { x, byte } => self.add_vx_byte(x.into(), byte),
+            Instruction::LdVxVy { x, y } => self.ld_vx_vy(x.into(), y.into()),
+            Instruction::OrVxVy { x, y } => self.or_vx_vy(x.into(), y.into()),
+            Instruction::AndVxVy { x, y } => self.and_vx_vy(x.into(), y.into()),
+            Instruction::XorVxVy { x, y } => self.xor_vx_vy(x.into(), y.into()),
+            Instruction::AddVxVy { x, y } => self.add_vx_vy(x.into(), y.into()),
+            Instruction::SubVxVy { x, y } => self.sub_vx_vy(x.into(), y.into()),
+            Instruction::ShrVx { x, y } => self.shr_vx(x.into(), y.into()),
+            Instruction::SubnVxVy { x, y } => self.subn_vx_vy(x.into(), y.into()),
+            Instruction::ShlVx { x, y } => self.shl_vx(x.into(), y.into()),
+            Instruction::SneVxVy { x, y } => self.sne_vx_vy(x.into(), y.into()),
+            Instruction::LdIAddr { addr } => self.ld_i_addr(addr),
+            Instruction::JpV0Addr { addr } => self.jp_v0_addr(addr),
+            Instruction::RndVxByte { x, byte } => self.rnd_vx_byte(x.into(), byte),
+            Instruction::DrwVxVyNibble { x, y, nibble } => {
+                self.drw_vx_vy_nibble(x.into(), y.into(), nibble.into())
+            }
+            Instruction::SkpVx { x } => self.skp_vx(x.into()),
+            Instruction::SknpVx { x } => self.sknp_vx(x.into()),
+            Instruction::LdVxDt { x } => self.ld_vx_dt(x.into()),
+            Instruction::LdVxK { x } => self.ld_vx_k(x.into()),
+            Instruction::LdDtVx { x } => self.ld_dt_vx(x.into()),
+            Instruction::LdStVx { x } => self.ld_st_vx(x.into()),
+            Instruction::AddIVx { x } => self.add_i_vx(x.into()),
+            Instruction::LdFVx { x } => self.ld_f_vx(x.into()),
+            Instruction::LdHfVx { x } => self.ld_hf_vx(x.into()),
+            Instruction::LdBVx { x } => self.ld_b_vx(x.into()),
+            Instruction::LdIVx { x } => self.ld_i_vx(x.into()),
+            Instruction::LdVxI { x } => self.ld_vx_i(x.into()),
+            Instruction::LdRVx { x } => self.ld_r_vx(x.into()),
+            Instruction::LdVxR { x } => self.ld_vx_r(x.into()),
+            Instruction::ScdNibble { nibble } => self.scd_nibble(nibble),
+            Instruction::ScuNibble { nibble } => self.scu_nibble(nibble),
+            Instruction::Scr => self.scr(),
+            Instruction::Scl => self.scl(),
+            Instruction::Exit => self.exit(),
+            Instruction::Low => self.low(),
+            Instruction::High => self.high(),
+            Instruction::LdILong => self.ld_i_long(),
+            Instruction::LdIVxVy { x, y } => self.ld_i_vx_vy(x.into(), y.into()),
+            Instruction::LdVxVyI { x, y } => self.ld_vx_vy_i(x.into(), y.into()),
+            Instruction::PlaneN { n } => self.plane_n(n),
+            Instruction::Audio => self.audio(),
+            Instruction::PitchVx { x } => self.pitch_vx(x.into()),
         }
     }
+
     // instruction implementation
     // CLS - 00E0
     // Instruction: clear the display
-    fn cls(&mut self) {
-        self.screen = [0; SCREEN_WIDTH * SCREEN_HEIGHT]
+    fn cls(&mut self) -> Result<(), Fault> {
+        let planes = self.selected_planes;
+        self.screen.iter_mut().for_each(|pixel| *pixel &= !planes);
+        Ok(())
     }
     // RET - 00EE
     // Instruction: return from a subroutine
-    fn ret(&mut self) {
+    fn ret(&mut self) -> Result<(), Fault> {
+        if self.stack_pointer == 0 {
+            return Err(Fault::StackUnderflow);
+        }
         self.stack_pointer -= 1;
         self.program_counter = self.stack[self.stack_pointer as usize];
+        Ok(())
     }
     // JP - 1NNN
     // Instruction: jump to address NNN
-    fn jp(&mut self, opcode: u16) {
-        let address = opcode & 0x0FFF;
+    fn jp(&mut self, address: u16) -> Result<(), Fault> {
         self.program_counter = address;
+        Ok(())
     }
     // CALL - 2NNN
     // Instruction: call subroutine at NNN
-    fn call(&mut self, opcode: u16) {
-        let address = opcode & 0x0FFF;
+    fn call(&mut self, address: u16) -> Result<(), Fault> {
+        if self.stack_pointer as usize >= STACK_SIZE {
+            return Err(Fault::StackOverflow);
+        }
         self.stack[self.stack_pointer as usize] = self.program_counter;
         self.stack_pointer += 1;
         self.program_counter = address;
+        Ok(())
     }
     // SE Vx, byte - 3XNN
     // Instruction: skip next instruction if Vx equals NN
-    fn se_vx_byte(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let byte = (opcode & 0x00FF) as u8;
+    fn se_vx_byte(&mut self, x: usize, byte: u8) -> Result<(), Fault> {
         if self.registers[x] == byte {
-            self.program_counter += 2;
+            self.skip_next_instruction();
         }
+        Ok(())
     }
     // SNE Vx, byte - 4XNN
     // Instruction: skip next instruction if Vx doesn't equal NN
-    fn sne_vx_byte(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let byte = (opcode & 0x00FF) as u8;
+    fn sne_vx_byte(&mut self, x: usize, byte: u8) -> Result<(), Fault> {
         if self.registers[x] != byte {
-            self.program_counter += 2;
+            self.skip_next_instruction();
         }
+        Ok(())
     }
     // SE Vx, Vy - 5XY0
     // Instruction: skip next instruction if Vx equals Vy
-    fn se_vx_vy(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn se_vx_vy(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         if self.registers[x] == self.registers[y] {
-            self.program_counter += 2;
+            self.skip_next_instruction();
         }
+        Ok(())
     }
     // LD Vx, byte - 6XNN
     // Instruction: set Vx to NN
-    fn ld_vx_byte(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let byte = (opcode & 0x00FF) as u8;
+    fn ld_vx_byte(&mut self, x: usize, byte: u8) -> Result<(), Fault> {
         self.registers[x] = byte;
+        Ok(())
     }
     // ADD Vx, byte - 7XNN
     // Instruction: add NN to Vx
-    fn add_vx_byte(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let byte = (opcode & 0x00FF) as u8;
+    fn add_vx_byte(&mut self, x: usize, byte: u8) -> Result<(), Fault> {
         self.registers[x] = self.registers[x].wrapping_add(byte);
+        Ok(())
     }
     // LD Vx, Vy - 8XY0
     // Instruction: set Vx to the value of Vy
-    fn ld_vx_vy(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn ld_vx_vy(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         self.registers[x] = self.registers[y];
+        Ok(())
     }
     // OR Vx, Vy - 8XY1
     // Instruction: set Vx to Vx OR Vy
-    fn or_vx_vy(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn or_vx_vy(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         self.registers[x] |= self.registers[y];
+        if self.quirks.logic_resets_vf {
+            self.registers[0xF] = 0;
+        }
+        Ok(())
     }
     // AND Vx, Vy - 8XY2
     // Instruction: set Vx to Vx AND Vy
-    fn and_vx_vy(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn and_vx_vy(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         self.registers[x] &= self.registers[y];
+        if self.quirks.logic_resets_vf {
+            self.registers[0xF] = 0;
+        }
+        Ok(())
     }
     // XOR Vx, Vy - 8XY3
     // Instruction: set Vx to Vx XOR Vy
-    fn xor_vx_vy(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn xor_vx_vy(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         self.registers[x] ^= self.registers[y];
+        if self.quirks.logic_resets_vf {
+            self.registers[0xF] = 0;
+        }
+        Ok(())
     }
     // ADD Vx, Vy - 8XY4
     // Instruction: Add Vy to Vx, set VF = carry
-    fn add_vx_vy(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn add_vx_vy(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         let (result, carry) = self.registers[x].overflowing_add(self.registers[y]);
         self.registers[x] = result;
         self.registers[0xF] = if carry { 1 } else { 0 };
+        Ok(())
     }
     // SUB Vx, Vy - 8XY5
     // Instruction: subtract Vy from Vx, set VF = NOT borrow
-    fn sub_vx_vy(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn sub_vx_vy(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         let (result, borrow) = self.registers[x].overflowing_sub(self.registers[y]);
         self.registers[x] = result;
         self.registers[0xF] = if borrow { 0 } else { 1 };
+        Ok(())
     }
-    // SHR Vx - 8XY6
-    // Instruction: set Vx = Vx SHR 1
-    fn shr_vx(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        self.registers[0xF] = self.registers[x] & 0x1;
-        self.registers[x] >>= 1;
+    // SHR Vx {, Vy} - 8XY6
+    // Instruction: set Vx = Vx SHR 1 (Vy SHR 1 with the shift quirk), set VF = shifted out bit
+    fn shr_vx(&mut self, x: usize, y: usize) -> Result<(), Fault> {
+        let value = if self.quirks.shift_uses_vy {
+            self.registers[y]
+        } else {
+            self.registers[x]
+        };
+        self.registers[x] = value >> 1;
+        self.registers[0xF] = value & 0x1;
+        Ok(())
     }
     // SUBN Vx, Vy - 8XY7
     // Instruction: set Vx = Vy - Vx, set VF = NOT borrow
-    fn subn_vx_vy(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
-        self.registers[0xF] = if self.registers[y] > self.registers[x] {
-            1
+    fn subn_vx_vy(&mut self, x: usize, y: usize) -> Result<(), Fault> {
+        let (result, borrow) = self.registers[y].overflowing_sub(self.registers[x]);
+        self.registers[x] = result;
+        self.registers[0xF] = if borrow { 0 } else { 1 };
+        Ok(())
+    }
+    // SHL Vx {, Vy} - 8XYE
+    // Instruction: set Vx = Vx SHL 1 (Vy SHL 1 with the shift quirk), set VF = shifted out bit
+    fn shl_vx(&mut self, x: usize, y: usize) -> Result<(), Fault> {
+        let value = if self.quirks.shift_uses_vy {
+            self.registers[y]
         } else {
-            0
+            self.registers[x]
         };
-        self.registers[x] = self.registers[y].wrapping_sub(self.registers[x]);
-    }
-    // SHL Vx - 8XYE
-    // Instruction: set Vx = Vx SHL 1
-    fn shl_vx(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        self.registers[0xF] = (self.registers[x] & 0x80) >> 7;
-        self.registers[x] <<= 1;
+        self.registers[x] = value << 1;
+        self.registers[0xF] = (value & 0x80) >> 7;
+        Ok(())
     }
     // SNE Vx, Vy - 9XY0
     // Instruction: skip the next instruction if Vx != Vy
-    fn sne_vx_vy(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
+    fn sne_vx_vy(&mut self, x: usize, y: usize) -> Result<(), Fault> {
         if self.registers[x] != self.registers[y] {
-            self.program_counter += 2;
+            self.skip_next_instruction();
         }
+        Ok(())
     }
     // LD I, addr - ANNN
     // Instruction: set I = NNN
-    fn ld_i_addr(&mut self, opcode: u16) {
-        let address = opcode & 0x0FFF;
+    fn ld_i_addr(&mut self, address: u16) -> Result<(), Fault> {
         self.index_register = address;
+        Ok(())
     }
     // JP V0, addr - BNNN
-    // Instruction: jump to location nnn + V0
-    fn jp_v0_addr(&mut self, opcode: u16) {
-        let address = opcode & 0x0FFF;
-        self.program_counter = (self.registers[0] as u16) + address;
+    // Instruction: jump to location nnn + V0 (XNN + Vx with the jump quirk)
+    fn jp_v0_addr(&mut self, address: u16) -> Result<(), Fault> {
+        let offset = if self.quirks.jump_uses_vx {
+            self.registers[(address >> 8) as usize]
+        } else {
+            self.registers[0]
+        };
+        self.program_counter = (offset as u16) + address;
+        Ok(())
     }
     // RND Vx, byte
     // Instruction: set Vx = random byte and passed in byte
-    fn rnd_vx_byte(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let byte = (opcode & 0x00FF) as u8;
-        let random_byte: u8 = rand::random(); // Generate a random byte
-        self.registers[x] = random_byte & byte;
+    fn rnd_vx_byte(&mut self, x: usize, byte: u8) -> Result<(), Fault> {
+        self.registers[x] = self.random.next_byte() & byte;
+        Ok(())
     }
-    // DRW Vx, Vy, nibble
+    // DRW Vx, Vy, nibble - DXYN
     // Instruction: display n-byte sprite starting at memory location I at (Vx, Vy), set VF =
-    // collision
-    fn drw_vx_vy_nibble(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
-        let height = (opcode & 0x000F) as usize;
+    // collision. With n = 0 a 16x16 sprite of 32 bytes is drawn instead (SUPER-CHIP)
+    fn drw_vx_vy_nibble(&mut self, x: usize, y: usize, height: usize) -> Result<(), Fault> {
+        // With the display wait quirk only one sprite is drawn per frame; the instruction
+        // repeats until the next vblank
+        if self.quirks.display_wait {
+            if !self.vblank {
+                self.program_counter = self.program_counter.wrapping_sub(2);
+                return Ok(());
+            }
+            self.vblank = false;
+        }
+
+        let (width, height) = if height == 0 { (16, 16) } else { (8, height) };
+        let bytes_per_row = width / 8;
+        let planes = self.selected_planes;
+        // XO-CHIP reads one sprite per selected plane, back to back
+        let plane_count = planes.count_ones() as usize;
+        let sprite_len = height * bytes_per_row;
+        let sprite = self.memory_range(self.index_register as usize, sprite_len * plane_count)?;
 
-        let vx = self.registers[x] as usize;
-        let vy = self.registers[y] as usize;
+        let screen_width = self.screen_width();
+        let screen_height = self.screen_height();
+        // The start position always wraps, only the sprite itself is clipped or wrapped
+        let vx = self.registers[x] as usize % screen_width;
+        let vy = self.registers[y] as usize % screen_height;
 
         self.registers[0xF] = 0;
 
-        for row in 0..height {
-            let sprite_byte = self.memory[self.index_register as usize + row];
-            for col in 0..8 {
-                let sprite_pixel = sprite_byte & (0x80 >> col);
-                let screen_index = (vy + row) * SCREEN_WIDTH + (vx + col);
+        let mut plane_start = sprite.start;
+        for plane in [0b01u8, 0b10u8] {
+            if planes & plane == 0 {
+                continue;
+            }
+            for row in 0..height {
+                let py = vy + row;
+                if py >= screen_height && !self.quirks.wrap_sprites {
+                    break;
+                }
+                let offset = plane_start + row * bytes_per_row;
+                let sprite_row = if bytes_per_row == 2 {
+                    u16::from_be_bytes([self.memory[offset], self.memory[offset + 1]])
+                } else {
+                    (self.memory[offset] as u16) << 8
+                };
+                for col in 0..width {
+                    let px = vx + col;
+                    if px >= screen_width && !self.quirks.wrap_sprites {
+                        break;
+                    }
+                    let sprite_pixel = sprite_row & (0x8000 >> col);
+                    let screen_index = (py % screen_height) * screen_width + (px % screen_width);
 
-                if screen_index < self.screen.len() {
                     let screen_pixel = &mut self.screen[screen_index];
                     if sprite_pixel != 0 {
-                        if *screen_pixel == 1 {
+                        if *screen_pixel & plane != 0 {
                             self.registers[0xF] = 1;
                         }
-                        *screen_pixel ^= 1;
+                        *screen_pixel ^= plane;
                     }
                 }
             }
+            plane_start += sprite_len;
         }
+        Ok(())
     }
     // SKP Vx - EX9E
     // Instruction: skip the next instruction if the key with the value of Vx is pressed
-    fn skp_vx(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let key = self.registers[x];
+    fn skp_vx(&mut self, x: usize) -> Result<(), Fault> {
+        let key = self.registers[x] & 0xF;
         if self.keys[key as usize] != 0 {
-            self.program_counter += 2;
+            self.skip_next_instruction();
         }
+        Ok(())
     }
     // SKNP Vx - EXA1
     // Instruction: skip the next instruction if the key with the value of Vx is not pressed
-    fn sknp_vx(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let key = self.registers[x];
+    fn sknp_vx(&mut self, x: usize) -> Result<(), Fault> {
+        let key = self.registers[x] & 0xF;
         if self.keys[key as usize] == 0 {
-            self.program_counter += 2;
+            self.skip_next_instruction();
         }
+        Ok(())
     }
     // LD Vx, DT - FX07
     // Instruction: set Vx = delay timer value
-    fn ld_vx_dt(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn ld_vx_dt(&mut self, x: usize) -> Result<(), Fault> {
         self.registers[x] = self.delay_timer;
+        Ok(())
     }
     // LD Vx, K - FX0A
     // Instruction: wait for a key press, store the value of the key in Vx
-    fn ld_vx_k(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn ld_vx_k(&mut self, x: usize) -> Result<(), Fault> {
         for i in 0..self.keys.len() {
             if self.keys[i] != 0 {
                 self.registers[x] = i as u8;
-                return;
+                return Ok(());
             }
         }
         // If no key is pressed, decrement PC to repeat the instruction
-        self.program_counter -= 2;
+        self.program_counter = self.program_counter.wrapping_sub(2);
+        Ok(())
     }
     // LD DT, Vx - FX15
     // Instruction: set delay timer = Vx
-    fn ld_dt_vx(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn ld_dt_vx(&mut self, x: usize) -> Result<(), Fault> {
         self.delay_timer = self.registers[x];
+        Ok(())
     }
     // LD ST, Vx - FX18
     // Instruction: set sound timer = Vx
-    fn ld_st_vx(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn ld_st_vx(&mut self, x: usize) -> Result<(), Fault> {
         self.sound_timer = self.registers[x];
+        Ok(())
     }
     // ADD I, Vx - FX1E
     // Instruction: Set I = I + Vx
-    fn add_i_vx(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn add_i_vx(&mut self, x: usize) -> Result<(), Fault> {
         self.index_register = self.index_register.wrapping_add(self.registers[x] as u16);
+        Ok(())
     }
     // LD F, Vx - FX29
     // Instruction: set I = location of sprite for digit Vx
-    fn ld_f_vx(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let digit = self.registers[x] as u16;
+    fn ld_f_vx(&mut self, x: usize) -> Result<(), Fault> {
+        let digit = (self.registers[x] & 0xF) as u16;
         self.index_register = FONTSET_START_ADDRESS as u16 + digit * 5;
+        Ok(())
     }
     // LD B, Vx
     // Instruction: store BCD representation of Vx in memory locations I, I+1, and I+2
-    fn ld_b_vx(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
+    fn ld_b_vx(&mut self, x: usize) -> Result<(), Fault> {
         let value = self.registers[x];
+        let digits = self.memory_range(self.index_register as usize, 3)?;
 
-        self.memory[self.index_register as usize] = value / 100;
-        self.memory[(self.index_register + 1) as usize] = (value / 10) % 10;
-        self.memory[(self.index_register + 2) as usize] = value % 10;
+        self.memory[digits.start] = value / 100;
+        self.memory[digits.start + 1] = (value / 10) % 10;
+        self.memory[digits.start + 2] = value % 10;
+        Ok(())
     }
     // LD [I], Vx
     // Instruction: store registers V0 through Vx in memory starting at location I
-    fn ld_i_vx(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        for i in 0..=x {
-            self.memory[self.index_register as usize + i] = self.registers[i];
-        }
+    fn ld_i_vx(&mut self, x: usize) -> Result<(), Fault> {
+        let range = self.memory_range(self.index_register as usize, x + 1)?;
+        self.memory[range].copy_from_slice(&self.registers[..=x]);
+        self.increment_index(x);
+        Ok(())
     }
     // LD Vx, I
     // Instruction: read registers V0 through Vx from memory starting at location I
-    fn ld_vx_i(&mut self, opcode: u16) {
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        for i in 0..=x {
-            self.registers[i] = self.memory[self.index_register as usize + i];
+    fn ld_vx_i(&mut self, x: usize) -> Result<(), Fault> {
+        let range = self.memory_range(self.index_register as usize, x + 1)?;
+        self.registers[..=x].copy_from_slice(&self.memory[range]);
+        self.increment_index(x);
+        Ok(())
+    }
+    // LD HF, Vx - FX30
+    // Instruction: set I = location of the large sprite for digit Vx (SUPER-CHIP)
+    fn ld_hf_vx(&mut self, x: usize) -> Result<(), Fault> {
+        let digit = (self.registers[x] & 0xF) as u16;
+        self.index_register = BIG_FONTSET_START_ADDRESS as u16 + digit * 10;
+        Ok(())
+    }
+    // LD R, Vx - FX75
+    // Instruction: store registers V0 through Vx in the RPL user flags (SUPER-CHIP)
+    fn ld_r_vx(&mut self, x: usize) -> Result<(), Fault> {
+        self.rpl_flags[..=x].copy_from_slice(&self.registers[..=x]);
+        Ok(())
+    }
+    // LD Vx, R - FX85
+    // Instruction: read registers V0 through Vx from the RPL user flags (SUPER-CHIP)
+    fn ld_vx_r(&mut self, x: usize) -> Result<(), Fault> {
+        self.registers[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+        Ok(())
+    }
+    // SCD nibble - 00CN
+    // Instruction: scroll the display down by N pixels (SUPER-CHIP)
+    fn scd_nibble(&mut self, rows: u8) -> Result<(), Fault> {
+        self.scroll(0, rows as isize);
+        Ok(())
+    }
+    // SCU nibble - 00DN
+    // Instruction: scroll the display up by N pixels (XO-CHIP)
+    fn scu_nibble(&mut self, rows: u8) -> Result<(), Fault> {
+        self.scroll(0, -(rows as isize));
+        Ok(())
+    }
+    // SCR - 00FB
+    // Instruction: scroll the display right by 4 pixels (SUPER-CHIP)
+    fn scr(&mut self) -> Result<(), Fault> {
+        self.scroll(4, 0);
+        Ok(())
+    }
+    // SCL - 00FC
+    // Instruction: scroll the display left by 4 pixels (SUPER-CHIP)
+    fn scl(&mut self) -> Result<(), Fault> {
+        self.scroll(-4, 0);
+        Ok(())
+    }
+    // Moves the selected planes by (dx, dy) pixels, filling the uncovered area with 0
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let width = self.screen_width() as isize;
+        let height = self.screen_height() as isize;
+        let planes = self.selected_planes;
+        let previous = self.screen.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = (x - dx, y - dy);
+                let shifted = if (0..width).contains(&sx) && (0..height).contains(&sy) {
+                    previous[(sy * width + sx) as usize] & planes
+                } else {
+                    0
+                };
+                let pixel = &mut self.screen[(y * width + x) as usize];
+                *pixel = (*pixel & !planes) | shifted;
+            }
+        }
+    }
+    // EXIT - 00FD
+    // Instruction: stop the interpreter (SUPER-CHIP)
+    fn exit(&mut self) -> Result<(), Fault> {
+        self.exited = true;
+        Ok(())
+    }
+    // LOW - 00FE
+    // Instruction: switch to the 64x32 lo-res display (SUPER-CHIP)
+    fn low(&mut self) -> Result<(), Fault> {
+        self.set_resolution(false);
+        Ok(())
+    }
+    // HIGH - 00FF
+    // Instruction: switch to the 128x64 hi-res display (SUPER-CHIP)
+    fn high(&mut self) -> Result<(), Fault> {
+        self.set_resolution(true);
+        Ok(())
+    }
+    // LD I, long - F000 NNNN
+    // Instruction: set I = the 16-bit address following the instruction (XO-CHIP)
+    fn ld_i_long(&mut self) -> Result<(), Fault> {
+        let operand = self.memory_range(self.program_counter as usize, 2)?;
+        self.index_register =
+            u16::from_be_bytes([self.memory[operand.start], self.memory[operand.start + 1]]);
+        self.program_counter = self.program_counter.wrapping_add(2);
+        Ok(())
+    }
+    // LD [I], Vx - Vy - 5XY2
+    // Instruction: store registers Vx through Vy in memory starting at I, I is unchanged
+    // (XO-CHIP). Registers are stored in descending order when X > Y
+    fn ld_i_vx_vy(&mut self, x: usize, y: usize) -> Result<(), Fault> {
+        let count = x.abs_diff(y) + 1;
+        let range = self.memory_range(self.index_register as usize, count)?;
+        for (i, address) in range.enumerate() {
+            let register = if x <= y { x + i } else { x - i };
+            self.memory[address] = self.registers[register];
         }
+        Ok(())
+    }
+    // LD Vx - Vy, [I] - 5XY3
+    // Instruction: read registers Vx through Vy from memory starting at I, I is unchanged
+    // (XO-CHIP). Registers are loaded in descending order when X > Y
+    fn ld_vx_vy_i(&mut self, x: usize, y: usize) -> Result<(), Fault> {
+        let count = x.abs_diff(y) + 1;
+        let range = self.memory_range(self.index_register as usize, count)?;
+        for (i, address) in range.enumerate() {
+            let register = if x <= y { x + i } else { x - i };
+            self.registers[register] = self.memory[address];
+        }
+        Ok(())
+    }
+    // PLANE n - FN01
+    // Instruction: select the bitplanes drawn to by CLS, DRW and the scroll instructions (XO-CHIP)
+    fn plane_n(&mut self, n: u8) -> Result<(), Fault> {
+        self.selected_planes = n & 0b11;
+        Ok(())
+    }
+    // AUDIO - F002
+    // Instruction: load the 16-byte audio pattern buffer from memory starting at I (XO-CHIP)
+    fn audio(&mut self) -> Result<(), Fault> {
+        let range = self.memory_range(self.index_register as usize, AUDIO_PATTERN_SIZE)?;
+        let mut pattern = [0; AUDIO_PATTERN_SIZE];
+        pattern.copy_from_slice(&self.memory[range]);
+        self.audio_pattern = Some(pattern);
+        Ok(())
+    }
+    // PITCH Vx - FX3A
+    // Instruction: set the audio pattern playback pitch = Vx (XO-CHIP)
+    fn pitch_vx(&mut self, x: usize) -> Result<(), Fault> {
+        self.pitch = self.registers[x];
+        Ok(())
+    }
+    // Resizes the framebuffer for the given mode, clearing it
+    fn set_resolution(&mut self, hires: bool) {
+        self.hires = hires;
+        self.screen = vec![0; self.screen_width() * self.screen_height()];
+    }
+    // Advances I after FX55/FX65 according to the memory quirk
+    fn increment_index(&mut self, x: usize) {
+        let step = match self.quirks.index_increment {
+            IndexIncrement::Unchanged => 0,
+            IndexIncrement::X => x as u16,
+            IndexIncrement::XPlusOne => x as u16 + 1,
+        };
+        self.index_register = self.index_register.wrapping_add(step);
+    }
+}
+
+impl Chip8 {
+    /// Starts a [`Chip8Builder`] for setting up machine state, mostly in tests.
+    pub fn builder() -> Chip8Builder {
+        Chip8Builder::default()
+    }
+}
+
+/// Builds a [`Chip8`] in a given state without running a program to get there.
+///
+/// ```
+/// use chip_8::Chip8;
+///
+/// let mut chip8 = Chip8::builder()
+///     .register(0x1, 200)
+///     .register(0x2, 100)
+///     .instructions(&[0x8124]) // ADD V1, V2
+///     .build();
+/// chip8.emulate_cycle()?;
+/// assert_eq!((chip8.registers[0x1], chip8.registers[0xF]), (44, 1));
+/// # Ok::<(), chip_8::Chip8Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Chip8Builder {
+    quirks: Quirks,
+    registers: Vec<(usize, u8)>,
+    index_register: Option<u16>,
+    program_counter: Option<u16>,
+    memory: Vec<(usize, Vec<u8>)>,
+    stack: Vec<u16>,
+    keys: Vec<u8>,
+    delay_timer: u8,
+    sound_timer: u8,
+    hires: bool,
+    seed: Option<u64>,
+    random_bytes: Option<Vec<u8>>,
+}
+
+impl Chip8Builder {
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Sets register Vx.
+    pub fn register(mut self, x: usize, value: u8) -> Self {
+        self.registers.push((x, value));
+        self
+    }
+
+    pub fn index_register(mut self, address: u16) -> Self {
+        self.index_register = Some(address);
+        self
+    }
+
+    pub fn program_counter(mut self, address: u16) -> Self {
+        self.program_counter = Some(address);
+        self
+    }
+
+    /// Writes `bytes` into memory starting at `address`.
+    pub fn memory(mut self, address: usize, bytes: &[u8]) -> Self {
+        self.memory.push((address, bytes.to_vec()));
+        self
+    }
+
+    /// Writes opcodes at [`PROGRAM_START_ADDRESS`], where PC starts.
+    pub fn instructions(self, opcodes: &[u16]) -> Self {
+        let bytes: Vec<u8> = opcodes
+            .iter()
+            .flat_map(|opcode| opcode.to_be_bytes())
+            .collect();
+        self.memory(PROGRAM_START_ADDRESS, &bytes)
+    }
+
+    /// Pushes a return address onto the stack.
+    pub fn call_stack(mut self, return_address: u16) -> Self {
+        self.stack.push(return_address);
+        self
+    }
+
+    /// Holds down a key, 0-F.
+    pub fn key(mut self, key: u8) -> Self {
+        self.keys.push(key);
+        self
+    }
+
+    pub fn delay_timer(mut self, value: u8) -> Self {
+        self.delay_timer = value;
+        self
+    }
+
+    pub fn sound_timer(mut self, value: u8) -> Self {
+        self.sound_timer = value;
+        self
+    }
+
+    /// Seeds the default random source.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Makes RND return these bytes in order, over and over.
+    pub fn random_bytes(mut self, bytes: &[u8]) -> Self {
+        self.random_bytes = Some(bytes.to_vec());
+        self
+    }
+
+    /// Starts in the 128x64 SUPER-CHIP mode.
+    pub fn hires(mut self) -> Self {
+        self.hires = true;
+        self
+    }
+
+    /// Creates the machine. Panics if memory writes or the stack don't fit.
+    pub fn build(self) -> Chip8 {
+        let mut chip8 = Chip8::new(self.quirks);
+        if self.hires {
+            chip8.set_resolution(true);
+        }
+        for (x, value) in self.registers {
+            chip8.registers[x] = value;
+        }
+        if let Some(address) = self.index_register {
+            chip8.index_register = address;
+        }
+        if let Some(address) = self.program_counter {
+            chip8.program_counter = address;
+        }
+        for (address, bytes) in self.memory {
+            chip8.memory[address..address + bytes.len()].copy_from_slice(&bytes);
+        }
+        for return_address in self.stack {
+            chip8.stack[chip8.stack_pointer as usize] = return_address;
+            chip8.stack_pointer += 1;
+        }
+        for key in self.keys {
+            chip8.keys[key as usize] = 1;
+        }
+        chip8.delay_timer = self.delay_timer;
+        chip8.sound_timer = self.sound_timer;
+        if let Some(seed) = self.seed {
+            chip8.set_seed(seed);
+        }
+        if let Some(bytes) = self.random_bytes {
+            chip8.set_random_source(Box::new(FixedSequence::new(bytes)));
+        }
+        chip8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VF: usize = 0xF;
+
+    // Builds the machine with `opcodes` at the start address and executes them in order
+    fn run(builder: Chip8Builder, opcodes: &[u16]) -> Chip8 {
+        let mut chip8 = builder.instructions(opcodes).build();
+        for _ in opcodes {
+            chip8.emulate_cycle().expect("instruction executes");
+        }
+        chip8
+    }
+
+    fn run_err(builder: Chip8Builder, opcode: u16) -> Chip8Error {
+        let mut chip8 = builder.instructions(&[opcode]).build();
+        chip8.emulate_cycle().expect_err("instruction faults")
+    }
+
+    fn pc_after(builder: Chip8Builder, opcode: u16) -> u16 {
+        run(builder, &[opcode]).program_counter
+    }
+
+    #[test]
+    fn builder_sets_up_state() {
+        let chip8 = Chip8::builder()
+            .quirks(Quirks::SUPER_CHIP)
+            .register(0x3, 7)
+            .index_register(0x345)
+            .program_counter(0x250)
+            .memory(0x300, &[1, 2, 3])
+            .call_stack(0x222)
+            .key(0xA)
+            .delay_timer(5)
+            .sound_timer(6)
+            .hires()
+            .build();
+        assert_eq!(chip8.quirks, Quirks::SUPER_CHIP);
+        assert_eq!(chip8.registers[3], 7);
+        assert_eq!(chip8.index_register, 0x345);
+        assert_eq!(chip8.program_counter, 0x250);
+        assert_eq!(&chip8.memory[0x300..0x303], &[1, 2, 3]);
+        assert_eq!((chip8.stack[0], chip8.stack_pointer), (0x222, 1));
+        assert_eq!(chip8.keys[0xA], 1);
+        assert_eq!((chip8.delay_timer, chip8.sound_timer), (5, 6));
+        assert_eq!(chip8.screen.len(), HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT);
+    }
+
+    #[test]
+    fn cls_clears_only_selected_planes() {
+        let builder = Chip8::builder()
+            .quirks(Quirks::XO_CHIP)
+            .memory(0x300, &[0x80, 0x80])
+            .index_register(0x300);
+        // PLANE 3, DRW V0 V0 1, PLANE 1, CLS
+        let chip8 = run(builder, &[0xF301, 0xD001, 0xF101, 0x00E0]);
+        assert_eq!(chip8.screen[0], 0b10);
+        assert!(chip8.screen[1..].iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn call_and_ret() {
+        let chip8 = run(Chip8::builder(), &[0x2300]);
+        assert_eq!(chip8.program_counter, 0x300);
+        assert_eq!((chip8.stack[0], chip8.stack_pointer), (0x202, 1));
+
+        let chip8 = run(Chip8::builder().call_stack(0x246), &[0x00EE]);
+        assert_eq!((chip8.program_counter, chip8.stack_pointer), (0x246, 0));
+    }
+
+    #[test]
+    fn stack_overflow_and_underflow_fault() {
+        let mut builder = Chip8::builder();
+        for _ in 0..STACK_SIZE {
+            builder = builder.call_stack(0x200);
+        }
+        let overflow = run_err(builder, 0x2300);
+        assert_eq!(
+            overflow,
+            Chip8Error::StackOverflow {
+                pc: 0x200,
+                opcode: 0x2300
+            }
+        );
+        let underflow = run_err(Chip8::builder(), 0x00EE);
+        assert_eq!(
+            underflow,
+            Chip8Error::StackUnderflow {
+                pc: 0x200,
+                opcode: 0x00EE
+            }
+        );
+    }
+
+    #[test]
+    fn jp() {
+        assert_eq!(pc_after(Chip8::builder(), 0x1ABC), 0xABC);
+    }
+
+    #[test]
+    fn skips() {
+        let builder = || Chip8::builder().register(0x1, 0x42).register(0x2, 0x42);
+        assert_eq!(pc_after(builder(), 0x3142), 0x204);
+        assert_eq!(pc_after(builder(), 0x3143), 0x202);
+        assert_eq!(pc_after(builder(), 0x4142), 0x202);
+        assert_eq!(pc_after(builder(), 0x4143), 0x204);
+        assert_eq!(pc_after(builder(), 0x5120), 0x204);
+        assert_eq!(pc_after(builder(), 0x5130), 0x202);
+        assert_eq!(pc_after(builder(), 0x9120), 0x202);
+        assert_eq!(pc_after(builder(), 0x9130), 0x204);
+    }
+
+    #[test]
+    fn skip_steps_over_long_load_on_xo_chip() {
+        let xo = Chip8::builder().quirks(Quirks::XO_CHIP);
+        let chip8 = run(xo.memory(0x202, &[0xF0, 0x00, 0x12, 0x34]), &[0x3000]);
+        assert_eq!(chip8.program_counter, 0x206);
+
+        // Without the extensions F000 is just an unknown two byte opcode
+        let chip8 = run(Chip8::builder().memory(0x202, &[0xF0, 0x00]), &[0x3000]);
+        assert_eq!(chip8.program_counter, 0x204);
+    }
+
+    #[test]
+    fn ld_and_add_byte() {
+        let chip8 = run(Chip8::builder(), &[0x6A42]);
+        assert_eq!(chip8.registers[0xA], 0x42);
+
+        // 7XNN wraps and never touches VF
+        let chip8 = run(
+            Chip8::builder().register(0x1, 0xFF).register(VF, 9),
+            &[0x7102],
+        );
+        assert_eq!((chip8.registers[1], chip8.registers[VF]), (1, 9));
+    }
+
+    #[test]
+    fn ld_vx_vy() {
+        let chip8 = run(Chip8::builder().register(0x2, 0x99), &[0x8120]);
+        assert_eq!(chip8.registers[1], 0x99);
+    }
+
+    #[test]
+    fn logic_ops_and_vf_reset_quirk() {
+        for (opcode, expected) in [(0x8121, 0b1110), (0x8122, 0b1000), (0x8123, 0b0110)] {
+            for (quirks, vf) in [(Quirks::COSMAC_VIP, 0), (Quirks::SUPER_CHIP, 5)] {
+                let builder = Chip8::builder()
+                    .quirks(quirks)
+                    .register(0x1, 0b1100)
+                    .register(0x2, 0b1010)
+                    .register(VF, 5);
+                let chip8 = run(builder, &[opcode]);
+                assert_eq!(chip8.registers[1], expected, "{:04X}", opcode);
+                assert_eq!(chip8.registers[VF], vf, "{:04X}", opcode);
+            }
+        }
+    }
+
+    #[test]
+    fn add_vx_vy_carry() {
+        let add = |vx, vy| {
+            let chip8 = run(
+                Chip8::builder().register(0x1, vx).register(0x2, vy),
+                &[0x8124],
+            );
+            (chip8.registers[1], chip8.registers[VF])
+        };
+        assert_eq!(add(1, 2), (3, 0));
+        assert_eq!(add(0xFF, 0), (0xFF, 0));
+        assert_eq!(add(0xFF, 1), (0, 1));
+        assert_eq!(add(200, 100), (44, 1));
+        assert_eq!(add(0xFF, 0xFF), (0xFE, 1));
+    }
+
+    #[test]
+    fn sub_vx_vy_borrow() {
+        let sub = |vx, vy| {
+            let chip8 = run(
+                Chip8::builder().register(0x1, vx).register(0x2, vy),
+                &[0x8125],
+            );
+            (chip8.registers[1], chip8.registers[VF])
+        };
+        assert_eq!(sub(5, 3), (2, 1));
+        assert_eq!(sub(5, 5), (0, 1));
+        assert_eq!(sub(3, 5), (0xFE, 0));
+        assert_eq!(sub(0, 1), (0xFF, 0));
+        assert_eq!(sub(0xFF, 0), (0xFF, 1));
+    }
+
+    #[test]
+    fn subn_vx_vy_borrow() {
+        let subn = |vx, vy| {
+            let chip8 = run(
+                Chip8::builder().register(0x1, vx).register(0x2, vy),
+                &[0x8127],
+            );
+            (chip8.registers[1], chip8.registers[VF])
+        };
+        assert_eq!(subn(3, 5), (2, 1));
+        assert_eq!(subn(5, 5), (0, 1));
+        assert_eq!(subn(5, 3), (0xFE, 0));
+        assert_eq!(subn(1, 0), (0xFF, 0));
+        assert_eq!(subn(0, 0xFF), (0xFF, 1));
+    }
+
+    #[test]
+    fn shifts_set_vf_to_the_shifted_out_bit() {
+        let shift = |opcode, value| {
+            let builder = Chip8::builder()
+                .quirks(Quirks::SUPER_CHIP)
+                .register(0x1, value);
+            let chip8 = run(builder, &[opcode]);
+            (chip8.registers[1], chip8.registers[VF])
+        };
+        assert_eq!(shift(0x8126, 0b0000_0101), (0b0000_0010, 1));
+        assert_eq!(shift(0x8126, 0b0000_0100), (0b0000_0010, 0));
+        assert_eq!(shift(0x812E, 0b1000_0001), (0b0000_0010, 1));
+        assert_eq!(shift(0x812E, 0b0100_0001), (0b1000_0010, 0));
+    }
+
+    #[test]
+    fn shift_quirk_reads_vy() {
+        let builder = |quirks| {
+            Chip8::builder()
+                .quirks(quirks)
+                .register(0x1, 0x10)
+                .register(0x2, 0x03)
+        };
+        let chip8 = run(builder(Quirks::COSMAC_VIP), &[0x8126]);
+        assert_eq!((chip8.registers[1], chip8.registers[VF]), (0x01, 1));
+        let chip8 = run(builder(Quirks::SUPER_CHIP), &[0x8126]);
+        assert_eq!((chip8.registers[1], chip8.registers[VF]), (0x08, 0));
+        let chip8 = run(builder(Quirks::COSMAC_VIP), &[0x812E]);
+        assert_eq!((chip8.registers[1], chip8.registers[VF]), (0x06, 0));
+    }
+
+    // With VF as Vx the flag is written after the result and wins
+    #[test]
+    fn vf_as_destination_holds_the_flag() {
+        let flag = |opcode, vf, vy| {
+            let builder = Chip8::builder()
+                .quirks(Quirks::SUPER_CHIP)
+                .register(VF, vf)
+                .register(0x2, vy);
+            run(builder, &[opcode]).registers[VF]
+        };
+        assert_eq!(flag(0x8F24, 200, 100), 1);
+        assert_eq!(flag(0x8F24, 1, 2), 0);
+        assert_eq!(flag(0x8F25, 5, 3), 1);
+        assert_eq!(flag(0x8F25, 3, 5), 0);
+        assert_eq!(flag(0x8F27, 3, 5), 1);
+        assert_eq!(flag(0x8F27, 5, 5), 1);
+        assert_eq!(flag(0x8F27, 5, 3), 0);
+        assert_eq!(flag(0x8F26, 0b10, 0), 0);
+        assert_eq!(flag(0x8F26, 0b11, 0), 1);
+        assert_eq!(flag(0x8F2E, 0x80, 0), 1);
+        assert_eq!(flag(0x8F2E, 0x7F, 0), 0);
+    }
+
+    // With VF as Vy the operand is read before the flag overwrites it
+    #[test]
+    fn vf_as_source_is_read_before_the_flag() {
+        let run_with_vf = |opcode, vx, vf| {
+            let builder = Chip8::builder().register(0x1, vx).register(VF, vf);
+            let chip8 = run(builder, &[opcode]);
+            (chip8.registers[1], chip8.registers[VF])
+        };
+        assert_eq!(run_with_vf(0x81F4, 10, 20), (30, 0));
+        assert_eq!(run_with_vf(0x81F5, 10, 20), (0xF6, 0));
+        assert_eq!(run_with_vf(0x81F7, 10, 20), (10, 1));
+    }
+
+    #[test]
+    fn ld_i_addr() {
+        assert_eq!(run(Chip8::builder(), &[0xA123]).index_register, 0x123);
+    }
+
+    #[test]
+    fn jp_v0_addr_and_jump_quirk() {
+        let builder = |quirks| {
+            Chip8::builder()
+                .quirks(quirks)
+                .register(0x0, 0x02)
+                .register(0x3, 0x04)
+        };
+        assert_eq!(pc_after(builder(Quirks::COSMAC_VIP), 0xB300), 0x302);
+        assert_eq!(pc_after(builder(Quirks::SUPER_CHIP), 0xB300), 0x304);
+    }
+
+    #[test]
+    fn rnd_is_masked() {
+        let builder = Chip8::builder().random_bytes(&[0xA5, 0xFF]);
+        let chip8 = run(builder, &[0xC10F, 0xC2F0]);
+        assert_eq!((chip8.registers[1], chip8.registers[2]), (0x05, 0xF0));
+    }
+
+    #[test]
+    fn rnd_is_reproducible_with_a_seed() {
+        let opcodes = [0xC0FF, 0xC1FF, 0xC2FF, 0xC3FF];
+        let first = run(Chip8::builder().seed(1234), &opcodes);
+        let second = run(Chip8::builder().seed(1234), &opcodes);
+        assert_eq!(first.registers, second.registers);
+    }
+
+    #[test]
+    fn drw_draws_and_reports_collisions() {
+        let builder = Chip8::builder()
+            .quirks(Quirks::SUPER_CHIP)
+            .register(0x1, 2)
+            .register(0x2, 3)
+            .register(VF, 1)
+            .memory(0x300, &[0b1100_0000])
+            .index_register(0x300);
+        let chip8 = run(builder.clone(), &[0xD121]);
+        assert_eq!((chip8.pixel(2, 3), chip8.pixel(3, 3)), (1, 1));
+        assert_eq!(chip8.screen.iter().filter(|&&pixel| pixel != 0).count(), 2);
+        assert_eq!(chip8.registers[VF], 0);
+
+        // Drawing the same sprite again erases it and reports the collision
+        let chip8 = run(builder, &[0xD121, 0xD121]);
+        assert!(chip8.screen.iter().all(|&pixel| pixel == 0));
+        assert_eq!(chip8.registers[VF], 1);
+    }
+
+    #[test]
+    fn drw_with_vf_as_coordinate() {
+        let builder = Chip8::builder()
+            .quirks(Quirks::SUPER_CHIP)
+            .register(VF, 5)
+            .memory(0x300, &[0x80])
+            .index_register(0x300);
+        let chip8 = run(builder, &[0xDFF1]);
+        assert_eq!(chip8.pixel(5, 5), 1);
+        assert_eq!(chip8.registers[VF], 0);
+    }
+
+    #[test]
+    fn drw_clips_or_wraps_at_the_edges() {
+        let builder = |quirks| {
+            Chip8::builder()
+                .quirks(quirks)
+                .register(0x1, 62)
+                .register(0x2, 31)
+                .memory(0x300, &[0xFF, 0xFF])
+                .index_register(0x300)
+        };
+        let clipped = run(builder(Quirks::SUPER_CHIP), &[0xD122]);
+        assert_eq!((clipped.pixel(62, 31), clipped.pixel(63, 31)), (1, 1));
+        assert_eq!(
+            clipped.screen.iter().filter(|&&pixel| pixel != 0).count(),
+            2
+        );
+
+        let wrapped = run(builder(Quirks::XO_CHIP), &[0xD122]);
+        assert_eq!((wrapped.pixel(0, 31), wrapped.pixel(5, 0)), (1, 1));
+        assert_eq!(
+            wrapped.screen.iter().filter(|&&pixel| pixel != 0).count(),
+            16
+        );
+    }
+
+    #[test]
+    fn drw_start_position_always_wraps() {
+        let builder = Chip8::builder()
+            .quirks(Quirks::SUPER_CHIP)
+            .register(0x1, 64 + 3)
+            .register(0x2, 32 + 4)
+            .memory(0x300, &[0x80])
+            .index_register(0x300);
+        assert_eq!(run(builder, &[0xD121]).pixel(3, 4), 1);
+    }
+
+    #[test]
+    fn drw_16x16_sprite() {
+        let builder = Chip8::builder()
+            .quirks(Quirks::SUPER_CHIP)
+            .hires()
+            .memory(0x300, &[0xFF; 32])
+            .index_register(0x300);
+        let chip8 = run(builder, &[0xD000]);
+        assert_eq!(
+            chip8.screen.iter().filter(|&&pixel| pixel != 0).count(),
+            256
+        );
+        assert_eq!((chip8.pixel(15, 15), chip8.pixel(16, 0)), (1, 0));
+    }
+
+    #[test]
+    fn drw_waits_for_vblank() {
+        let mut chip8 = Chip8::builder()
+            .quirks(Quirks::COSMAC_VIP)
+            .memory(0x300, &[0x80])
+            .index_register(0x300)
+            .instructions(&[0xD001, 0xD001])
+            .build();
+        chip8.emulate_cycle().unwrap();
+        // The second DRW repeats until the next vblank
+        chip8.emulate_cycle().unwrap();
+        assert_eq!(chip8.program_counter, 0x202);
+        chip8.tick_timers();
+        chip8.emulate_cycle().unwrap();
+        assert_eq!(chip8.program_counter, 0x204);
+    }
+
+    #[test]
+    fn drw_out_of_memory_faults() {
+        let builder = Chip8::builder().index_register(MEMORY_SIZE as u16 - 1);
+        assert!(matches!(
+            run_err(builder, 0xD002),
+            Chip8Error::MemoryOutOfBounds { .. }
+        ));
+    }
+
+    #[test]
+    fn key_skips() {
+        let builder = || Chip8::builder().register(0x1, 0x5);
+        assert_eq!(pc_after(builder().key(0x5), 0xE19E), 0x204);
+        assert_eq!(pc_after(builder(), 0xE19E), 0x202);
+        assert_eq!(pc_after(builder().key(0x5), 0xE1A1), 0x202);
+        assert_eq!(pc_after(builder().key(0x6), 0xE1A1), 0x204);
+    }
+
+    #[test]
+    fn ld_vx_k_blocks_until_a_key_is_pressed() {
+        let mut chip8 = run(Chip8::builder(), &[0xF10A]);
+        assert_eq!(chip8.program_counter, 0x200);
+        chip8.emulate_cycle().unwrap();
+        assert_eq!(chip8.program_counter, 0x200);
+
+        chip8.keys[0xC] = 1;
+        chip8.emulate_cycle().unwrap();
+        assert_eq!((chip8.program_counter, chip8.registers[1]), (0x202, 0xC));
+    }
+
+    #[test]
+    fn waiting_instructions_at_the_end_of_memory_repeat() {
+        // Fetching the last word wraps PC to 0, repeating has to wrap it back
+        for opcode in [0xF10Au16, 0xD001] {
+            let mut chip8 = Chip8::builder()
+                .quirks(Quirks {
+                    display_wait: true,
+                    ..Quirks::XO_CHIP
+                })
+                .memory(0xFFFE, &opcode.to_be_bytes())
+                .program_counter(0xFFFE)
+                .build();
+            // No key is held, and DRW has to wait for a vblank
+            chip8.vblank = false;
+            chip8.emulate_cycle().unwrap();
+            assert_eq!(chip8.program_counter, 0xFFFE, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn timers() {
+        let chip8 = run(Chip8::builder().delay_timer(9), &[0xF107]);
+        assert_eq!(chip8.registers[1], 9);
+        let chip8 = run(Chip8::builder().register(0x1, 7), &[0xF115, 0xF118]);
+        assert_eq!((chip8.delay_timer, chip8.sound_timer), (7, 7));
+
+        let mut chip8 = Chip8::builder().delay_timer(1).sound_timer(2).build();
+        chip8.tick_timers();
+        chip8.tick_timers();
+        assert_eq!((chip8.delay_timer, chip8.sound_timer), (0, 0));
+    }
+
+    #[test]
+    fn add_i_vx() {
+        let builder = Chip8::builder().index_register(0x300).register(0x1, 0x10);
+        let chip8 = run(builder, &[0xF11E]);
+        assert_eq!(chip8.index_register, 0x310);
+    }
+
+    #[test]
+    fn font_sprites() {
+        let chip8 = run(Chip8::builder().register(0x1, 0xA), &[0xF129]);
+        assert_eq!(chip8.index_register as usize, FONTSET_START_ADDRESS + 50);
+        assert_eq!(chip8.memory[chip8.index_register as usize], 0xF0);
+
+        let chip8 = run(Chip8::builder().register(0x1, 0x2), &[0xF130]);
+        assert_eq!(
+            chip8.index_register as usize,
+            BIG_FONTSET_START_ADDRESS + 20
+        );
+
+        // Only the low digit picks the sprite, so I stays inside the font
+        let chip8 = run(Chip8::builder().register(0x1, 0x1A), &[0xF129]);
+        assert_eq!(chip8.index_register as usize, FONTSET_START_ADDRESS + 50);
+        let chip8 = run(Chip8::builder().register(0x1, 0x12), &[0xF130]);
+        assert_eq!(
+            chip8.index_register as usize,
+            BIG_FONTSET_START_ADDRESS + 20
+        );
+    }
+
+    #[test]
+    fn ld_b_vx_bcd() {
+        let bcd = |value| {
+            let builder = Chip8::builder().register(0x1, value).index_register(0x300);
+            let chip8 = run(builder, &[0xF133]);
+            [
+                chip8.memory[0x300],
+                chip8.memory[0x301],
+                chip8.memory[0x302],
+            ]
+        };
+        assert_eq!(bcd(0), [0, 0, 0]);
+        assert_eq!(bcd(7), [0, 0, 7]);
+        assert_eq!(bcd(42), [0, 4, 2]);
+        assert_eq!(bcd(100), [1, 0, 0]);
+        assert_eq!(bcd(255), [2, 5, 5]);
+
+        let builder = Chip8::builder().index_register(MEMORY_SIZE as u16 - 2);
+        assert!(matches!(
+            run_err(builder, 0xF133),
+            Chip8Error::MemoryOutOfBounds { .. }
+        ));
+    }
+
+    #[test]
+    fn store_and_load_registers() {
+        for (quirks, index) in [
+            (Quirks::COSMAC_VIP, 0x303),
+            (Quirks::CHIP_48, 0x302),
+            (Quirks::SUPER_CHIP, 0x300),
+        ] {
+            let builder = Chip8::builder()
+                .quirks(quirks)
+                .register(0x0, 1)
+                .register(0x1, 2)
+                .register(0x2, 3)
+                .register(0x3, 4)
+                .index_register(0x300);
+            let chip8 = run(builder, &[0xF255]);
+            assert_eq!(&chip8.memory[0x300..0x304], &[1, 2, 3, 0]);
+            assert_eq!(chip8.index_register, index);
+
+            let builder = Chip8::builder()
+                .quirks(quirks)
+                .memory(0x300, &[9, 8, 7, 6])
+                .index_register(0x300);
+            let chip8 = run(builder, &[0xF265]);
+            assert_eq!(&chip8.registers[..4], &[9, 8, 7, 0]);
+            assert_eq!(chip8.index_register, index);
+        }
+    }
+
+    #[test]
+    fn rpl_flags() {
+        let builder = Chip8::builder().register(0x0, 1).register(0x1, 2);
+        let mut chip8 = run(builder, &[0xF175]);
+        assert_eq!(&chip8.rpl_flags[..3], &[1, 2, 0]);
+        chip8.registers = [0; REGISTER_COUNT];
+        chip8.memory[0x202..0x204].copy_from_slice(&[0xF1, 0x85]);
+        chip8.emulate_cycle().unwrap();
+        assert_eq!(&chip8.registers[..2], &[1, 2]);
+    }
+
+    #[test]
+    fn scrolling() {
+        let builder = || {
+            Chip8::builder()
+                .quirks(Quirks::XO_CHIP)
+                .register(0x1, 8)
+                .register(0x2, 8)
+                .memory(0x300, &[0x80])
+                .index_register(0x300)
+        };
+        let lit = |chip8: &Chip8| {
+            let width = chip8.screen_width();
+            let index = chip8.screen.iter().position(|&pixel| pixel != 0).unwrap();
+            (index % width, index / width)
+        };
+        assert_eq!(lit(&run(builder(), &[0xD121, 0x00C3])), (8, 11));
+        assert_eq!(lit(&run(builder(), &[0xD121, 0x00D3])), (8, 5));
+        assert_eq!(lit(&run(builder(), &[0xD121, 0x00FB])), (12, 8));
+        assert_eq!(lit(&run(builder(), &[0xD121, 0x00FC])), (4, 8));
+
+        // Pixels scrolled off the screen are gone
+        let chip8 = run(builder(), &[0xD121, 0x00FC, 0x00FC, 0x00FC]);
+        assert!(chip8.screen.iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn exit_stops_execution() {
+        let mut chip8 = run(Chip8::builder(), &[0x00FD]);
+        assert!(chip8.exited);
+        chip8.emulate_cycle().unwrap();
+        assert_eq!(chip8.program_counter, 0x202);
+    }
+
+    #[test]
+    fn resolution_switches_clear_the_screen() {
+        let chip8 = run(Chip8::builder(), &[0x00FF]);
+        assert!(chip8.hires);
+        assert_eq!(chip8.screen.len(), HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT);
+        let chip8 = run(Chip8::builder().hires(), &[0x00FE]);
+        assert!(!chip8.hires);
+        assert_eq!(chip8.screen.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+    }
+
+    #[test]
+    fn xo_chip_instructions() {
+        let xo = || Chip8::builder().quirks(Quirks::XO_CHIP);
+
+        let mut chip8 = xo().instructions(&[0xF000, 0xABCD]).build();
+        chip8.emulate_cycle().unwrap();
+        assert_eq!(
+            (chip8.index_register, chip8.program_counter),
+            (0xABCD, 0x204)
+        );
+
+        let builder = xo()
+            .register(0x1, 1)
+            .register(0x2, 2)
+            .register(0x3, 3)
+            .index_register(0x300);
+        let chip8 = run(builder, &[0x5312]);
+        assert_eq!(&chip8.memory[0x300..0x303], &[3, 2, 1]);
+        assert_eq!(chip8.index_register, 0x300);
+
+        let builder = xo().memory(0x300, &[4, 5]).index_register(0x300);
+        let chip8 = run(builder, &[0x5123]);
+        assert_eq!(&chip8.registers[1..3], &[4, 5]);
+
+        let chip8 = run(xo(), &[0xF201]);
+        assert_eq!(chip8.selected_planes, 0b10);
+
+        assert_eq!(xo().build().audio_pattern, None);
+        let builder = xo().memory(0x300, &[0xAA; 16]).index_register(0x300);
+        let chip8 = run(builder, &[0xF002]);
+        assert_eq!(chip8.audio_pattern, Some([0xAA; 16]));
+
+        let chip8 = run(xo().register(0x1, 112), &[0xF13A]);
+        assert_eq!(chip8.pitch, 112);
+        assert_eq!(chip8.audio_playback_rate(), 8000.0);
+    }
+
+    #[test]
+    fn xo_chip_instructions_need_the_extensions() {
+        assert_eq!(
+            run_err(Chip8::builder(), 0xF201),
+            Chip8Error::UnknownOpcode {
+                pc: 0x200,
+                opcode: 0xF201
+            }
+        );
     }
 }
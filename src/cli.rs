@@ -0,0 +1,670 @@
+use crate::audio::{AudioConfig, Waveform};
+use chip_8::trace::{TraceFilter, TraceFormat};
+use chip_8::Quirks;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: chip_8 [OPTIONS] <ROM>
+       chip_8 disasm [--quirks <PRESET>] <ROM>
+       chip_8 asm [-o <OUT>] <SOURCE>
+       chip_8 trace-diff [DIFF OPTIONS] <ROM> <TRACE>
+
+Options:
+  --ipf <N>             instructions executed per 60 Hz frame [default: 10]
+  --scale <N>           window pixels per lo-res CHIP-8 pixel, 2 to 128 [default: 10]
+  --fg <RRGGBB>         foreground color [default: FFFFFF]
+  --bg <RRGGBB>         background color [default: 000000]
+  --quirks <PRESET>     legacy, vip, chip48, schip or xochip [default: legacy, this
+                        interpreter's behavior before the presets existed]
+  --seed <N>            seed for the random number generator [default: random]
+  --mute                start with sound muted
+  --tone <HZ>           beeper frequency [default: 440]
+  --volume <0-1>        beeper volume [default: 0.25]
+  --waveform <WAVE>     square, triangle, sawtooth or sine [default: square]
+  --headless            run without a window and dump the final screen
+  --frames <N>          frames to run in headless mode [default: 600, or the movie's length]
+  --cycles <N>          run N instructions instead of a number of frames (headless)
+  --keys <FILE>         scripted key input, 'FRAME down|up KEY' per line (headless)
+  --dump <FORMAT>       ascii, pbm, png or hash [default: ascii] (headless)
+  --output <FILE>       write the dump to FILE instead of stdout (headless)
+  --rewind <MB>         memory for the history Backspace rewinds through, 0 disables it
+                        [default: 16]
+  --keymap <FILE>       key bindings file, F5 in the window rebinds and saves to it
+                        [default: $XDG_CONFIG_HOME/chip_8/keys.toml]
+  --record <FILE>       record the session's input to a movie file
+  --play <FILE>         replay a movie; its quirks, seed and --ipf replace the options
+  --debug               start paused with a debugger prompt on stdin
+  --gdb <PORT>          serve the GDB remote protocol on 127.0.0.1:PORT; headless runs go on
+                        in real time until the debugger kills them
+  --trace <FILE>        log every instruction with the state around it to FILE
+  --trace-format <FMT>  text or json [default: text]
+  --trace-range <A-B>   only trace instructions at addresses A to B (hex, inclusive)
+  --trace-ops <LIST>    only trace these opcode classes, e.g. 8,D,F (first hex digit)
+  --trace-rotate <MB>   start a new trace file at this size, keeping 3 old ones
+  -h, --help            print this help
+
+Trace diff options, for finding where a run departs from a reference trace:
+  --quirks, --seed, --ipf, --keys and --play as above
+  --context <N>         records to show around the divergence [default: 5]";
+
+// How the final screen of a headless run is written out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Ascii,
+    Pbm,
+    Png,
+    Hash,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub rom_path: PathBuf,
+    pub instructions_per_frame: usize,
+    pub scale: u32,
+    pub foreground: [u8; 3],
+    pub background: [u8; 3],
+    pub quirks: Quirks,
+    pub seed: Option<u64>,
+    pub audio: AudioConfig,
+    pub headless: bool,
+    pub frames: Option<u64>,
+    pub cycles: Option<u64>,
+    pub key_script: Option<PathBuf>,
+    pub dump: DumpFormat,
+    pub output: Option<PathBuf>,
+    // Bytes of rewind history to keep in the SDL frontend
+    pub rewind_budget: usize,
+    // Overrides the default key bindings file
+    pub keymap: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub play: Option<PathBuf>,
+    pub debug: bool,
+    pub gdb: Option<u16>,
+    pub trace: Option<PathBuf>,
+    pub trace_format: TraceFormat,
+    pub trace_filter: TraceFilter,
+    // Bytes a trace file may grow to before it's rotated
+    pub trace_rotate: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisasmOptions {
+    pub rom_path: PathBuf,
+    pub quirks: Quirks,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmOptions {
+    pub source_path: PathBuf,
+    // Defaults to the source path with a .ch8 extension
+    pub output_path: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceDiffOptions {
+    pub rom_path: PathBuf,
+    pub trace_path: PathBuf,
+    pub quirks: Quirks,
+    pub seed: Option<u64>,
+    pub instructions_per_frame: usize,
+    pub key_script: Option<PathBuf>,
+    pub play: Option<PathBuf>,
+    pub context: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run(Box<Options>),
+    Disasm(DisasmOptions),
+    Asm(AsmOptions),
+    TraceDiff(TraceDiffOptions),
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CliError(String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for CliError {}
+
+// Parses the arguments following the program name
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, CliError> {
+    let mut args = args.into_iter().peekable();
+    match args.peek().map(String::as_str) {
+        Some("disasm") => {
+            args.next();
+            return parse_disasm(args);
+        }
+        Some("asm") => {
+            args.next();
+            return parse_asm(args);
+        }
+        Some("trace-diff") => {
+            args.next();
+            return parse_trace_diff(args);
+        }
+        _ => {}
+    }
+    let mut rom_path = None;
+    // Only headless runs dump the screen, so --dump is checked like the other headless options
+    let mut dump = None;
+    let mut options = Options {
+        rom_path: PathBuf::new(),
+        instructions_per_frame: 10,
+        scale: 10,
+        foreground: [0xFF, 0xFF, 0xFF],
+        background: [0x00, 0x00, 0x00],
+        quirks: Quirks::default(),
+        seed: None,
+        audio: AudioConfig::default(),
+        headless: false,
+        frames: None,
+        cycles: None,
+        key_script: None,
+        dump: DumpFormat::Ascii,
+        output: None,
+        rewind_budget: 16 << 20,
+        keymap: None,
+        record: None,
+        play: None,
+        debug: false,
+        gdb: None,
+        trace: None,
+        trace_format: TraceFormat::Text,
+        trace_filter: TraceFilter::default(),
+        trace_rotate: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--ipf" => {
+                options.instructions_per_frame = parse_number(&arg, value(&arg, &mut args)?)?;
+                if options.instructions_per_frame == 0 {
+                    return Err(CliError("--ipf must be at least 1".to_string()));
+                }
+            }
+            "--scale" => {
+                options.scale = parse_number(&arg, value(&arg, &mut args)?)?;
+                // Hi-res pixels are half the size, so they need 2 to cover a window pixel each
+                if !(2..=128).contains(&options.scale) {
+                    return Err(CliError("--scale must be between 2 and 128".to_string()));
+                }
+            }
+            "--fg" => options.foreground = parse_color(&arg, value(&arg, &mut args)?)?,
+            "--bg" => options.background = parse_color(&arg, value(&arg, &mut args)?)?,
+            "--quirks" => options.quirks = parse_quirks(value(&arg, &mut args)?)?,
+            "--seed" => options.seed = Some(parse_number(&arg, value(&arg, &mut args)?)?),
+            "--mute" => options.audio.muted = true,
+            "--tone" => {
+                options.audio.frequency = parse_number(&arg, value(&arg, &mut args)?)?;
+                if !options.audio.frequency.is_finite() || options.audio.frequency <= 0.0 {
+                    return Err(CliError("--tone must be positive".to_string()));
+                }
+            }
+            "--volume" => {
+                options.audio.volume = parse_number(&arg, value(&arg, &mut args)?)?;
+                if !(0.0..=1.0).contains(&options.audio.volume) {
+                    return Err(CliError("--volume must be between 0 and 1".to_string()));
+                }
+            }
+            "--waveform" => {
+                let name = value(&arg, &mut args)?;
+                options.audio.waveform = match name.as_str() {
+                    "square" => Waveform::Square,
+                    "triangle" => Waveform::Triangle,
+                    "sawtooth" => Waveform::Sawtooth,
+                    "sine" => Waveform::Sine,
+                    _ => {
+                        return Err(CliError(format!(
+                            "unknown waveform '{}', expected square, triangle, sawtooth or sine",
+                            name
+                        )))
+                    }
+                };
+            }
+            "--headless" => options.headless = true,
+            "--frames" => options.frames = Some(parse_number(&arg, value(&arg, &mut args)?)?),
+            "--cycles" => options.cycles = Some(parse_number(&arg, value(&arg, &mut args)?)?),
+            "--keys" => options.key_script = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--dump" => {
+                let name = value(&arg, &mut args)?;
+                dump = Some(match name.as_str() {
+                    "ascii" => DumpFormat::Ascii,
+                    "pbm" => DumpFormat::Pbm,
+                    "png" => DumpFormat::Png,
+                    "hash" => DumpFormat::Hash,
+                    _ => {
+                        return Err(CliError(format!(
+                            "unknown dump format '{}', expected ascii, pbm, png or hash",
+                            name
+                        )))
+                    }
+                });
+            }
+            "--output" => options.output = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--rewind" => options.rewind_budget = parse_megabytes(&arg, value(&arg, &mut args)?)?,
+            "--keymap" => options.keymap = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--record" => options.record = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--play" => options.play = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--debug" => options.debug = true,
+            "--gdb" => options.gdb = Some(parse_number(&arg, value(&arg, &mut args)?)?),
+            "--trace" => options.trace = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--trace-format" => {
+                let name = value(&arg, &mut args)?;
+                options.trace_format = match name.as_str() {
+                    "text" => TraceFormat::Text,
+                    "json" => TraceFormat::Json,
+                    _ => {
+                        return Err(CliError(format!(
+                            "unknown trace format '{}', expected text or json",
+                            name
+                        )))
+                    }
+                };
+            }
+            "--trace-range" => {
+                options.trace_filter.addresses =
+                    Some(parse_address_range(&arg, value(&arg, &mut args)?)?)
+            }
+            "--trace-ops" => {
+                options.trace_filter.opcode_classes =
+                    parse_opcode_classes(&arg, value(&arg, &mut args)?)?
+            }
+            "--trace-rotate" => {
+                let bytes: u64 = parse_megabytes(&arg, value(&arg, &mut args)?)?;
+                if bytes == 0 {
+                    return Err(CliError("--trace-rotate must be at least 1".to_string()));
+                }
+                options.trace_rotate = Some(bytes);
+            }
+            _ if arg.starts_with('-') => {
+                return Err(CliError(format!("unknown option '{}'", arg)));
+            }
+            _ => {
+                if rom_path.is_some() {
+                    return Err(CliError(format!("unexpected argument '{}'", arg)));
+                }
+                rom_path = Some(PathBuf::from(arg));
+            }
+        }
+    }
+
+    let headless_only = options.cycles.is_some()
+        || options.key_script.is_some()
+        || dump.is_some()
+        || options.output.is_some();
+    if headless_only && !options.headless {
+        return Err(CliError(
+            "--cycles, --keys, --dump and --output need --headless".to_string(),
+        ));
+    }
+    if options.debug && options.headless {
+        return Err(CliError(
+            "--debug needs a window, it can't be used with --headless".to_string(),
+        ));
+    }
+    // Movies only stay in sync when nothing but their own input drives the machine
+    if options.debug && (options.record.is_some() || options.play.is_some()) {
+        return Err(CliError(
+            "--debug can't be used with --record or --play".to_string(),
+        ));
+    }
+    // A remote debugger stopping and poking the machine would desync a movie just the same
+    if options.gdb.is_some()
+        && (options.debug || options.record.is_some() || options.play.is_some())
+    {
+        return Err(CliError(
+            "--gdb can't be used with --debug, --record or --play".to_string(),
+        ));
+    }
+    if options.gdb.is_some() && options.cycles.is_some() {
+        return Err(CliError(
+            "--gdb runs whole frames, it can't be used with --cycles".to_string(),
+        ));
+    }
+    if options.play.is_some() && (options.key_script.is_some() || options.record.is_some()) {
+        return Err(CliError(
+            "--play can't be used with --keys or --record".to_string(),
+        ));
+    }
+    if options.record.is_some() && options.cycles.is_some() {
+        return Err(CliError(
+            "--record can only record whole frames, not --cycles".to_string(),
+        ));
+    }
+    let trace_options = options.trace_format != TraceFormat::Text
+        || options.trace_filter != TraceFilter::default()
+        || options.trace_rotate.is_some();
+    if trace_options && options.trace.is_none() {
+        return Err(CliError(
+            "--trace-format, --trace-range, --trace-ops and --trace-rotate need --trace"
+                .to_string(),
+        ));
+    }
+    options.dump = dump.unwrap_or(DumpFormat::Ascii);
+    options.rom_path = rom_path.ok_or_else(|| CliError("missing ROM path".to_string()))?;
+    Ok(Command::Run(Box::new(options)))
+}
+
+fn parse_disasm<I: Iterator<Item = String>>(mut args: I) -> Result<Command, CliError> {
+    let mut rom_path = None;
+    let mut quirks = Quirks::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--quirks" => quirks = parse_quirks(value(&arg, &mut args)?)?,
+            _ if arg.starts_with('-') => {
+                return Err(CliError(format!("unknown option '{}'", arg)));
+            }
+            _ => {
+                if rom_path.is_some() {
+                    return Err(CliError(format!("unexpected argument '{}'", arg)));
+                }
+                rom_path = Some(PathBuf::from(arg));
+            }
+        }
+    }
+    let rom_path = rom_path.ok_or_else(|| CliError("missing ROM path".to_string()))?;
+    Ok(Command::Disasm(DisasmOptions { rom_path, quirks }))
+}
+
+fn parse_asm<I: Iterator<Item = String>>(mut args: I) -> Result<Command, CliError> {
+    let mut source_path = None;
+    let mut output_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-o" | "--output" => output_path = Some(PathBuf::from(value(&arg, &mut args)?)),
+            _ if arg.starts_with('-') => {
+                return Err(CliError(format!("unknown option '{}'", arg)));
+            }
+            _ => {
+                if source_path.is_some() {
+                    return Err(CliError(format!("unexpected argument '{}'", arg)));
+                }
+                source_path = Some(PathBuf::from(arg));
+            }
+        }
+    }
+    let source_path: PathBuf =
+        source_path.ok_or_else(|| CliError("missing source path".to_string()))?;
+    let output_path = output_path.unwrap_or_else(|| source_path.with_extension("ch8"));
+    Ok(Command::Asm(AsmOptions {
+        source_path,
+        output_path,
+    }))
+}
+
+fn parse_trace_diff<I: Iterator<Item = String>>(mut args: I) -> Result<Command, CliError> {
+    let mut paths = Vec::new();
+    let mut options = TraceDiffOptions {
+        rom_path: PathBuf::new(),
+        trace_path: PathBuf::new(),
+        quirks: Quirks::default(),
+        seed: None,
+        instructions_per_frame: 10,
+        key_script: None,
+        play: None,
+        context: 5,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--quirks" => options.quirks = parse_quirks(value(&arg, &mut args)?)?,
+            "--seed" => options.seed = Some(parse_number(&arg, value(&arg, &mut args)?)?),
+            "--ipf" => {
+                options.instructions_per_frame = parse_number(&arg, value(&arg, &mut args)?)?;
+                if options.instructions_per_frame == 0 {
+                    return Err(CliError("--ipf must be at least 1".to_string()));
+                }
+            }
+            "--keys" => options.key_script = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--play" => options.play = Some(PathBuf::from(value(&arg, &mut args)?)),
+            "--context" => options.context = parse_number(&arg, value(&arg, &mut args)?)?,
+            _ if arg.starts_with('-') => {
+                return Err(CliError(format!("unknown option '{}'", arg)));
+            }
+            _ => {
+                if paths.len() == 2 {
+                    return Err(CliError(format!("unexpected argument '{}'", arg)));
+                }
+                paths.push(PathBuf::from(arg));
+            }
+        }
+    }
+    if options.play.is_some() && options.key_script.is_some() {
+        return Err(CliError("--play can't be used with --keys".to_string()));
+    }
+    let mut paths = paths.into_iter();
+    options.rom_path = paths
+        .next()
+        .ok_or_else(|| CliError("missing ROM path".to_string()))?;
+    options.trace_path = paths
+        .next()
+        .ok_or_else(|| CliError("missing trace path".to_string()))?;
+    Ok(Command::TraceDiff(options))
+}
+
+// Takes the value following `option`
+fn value<I: Iterator<Item = String>>(option: &str, args: &mut I) -> Result<String, CliError> {
+    args.next()
+        .ok_or_else(|| CliError(format!("{} needs a value", option)))
+}
+
+fn parse_quirks(name: String) -> Result<Quirks, CliError> {
+    Quirks::from_preset_name(&name).ok_or_else(|| {
+        CliError(format!(
+            "unknown quirk preset '{}', expected legacy, vip, chip48, schip or xochip",
+            name
+        ))
+    })
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: String) -> Result<T, CliError> {
+    value
+        .parse()
+        .map_err(|_| CliError(format!("invalid value '{}' for {}", value, option)))
+}
+
+// Parses a size in megabytes into bytes, rejecting sizes that don't fit in a T
+fn parse_megabytes<T: TryFrom<u64>>(option: &str, value: String) -> Result<T, CliError> {
+    let megabytes: u64 = parse_number(option, value.clone())?;
+    megabytes
+        .checked_mul(1 << 20)
+        .and_then(|bytes| T::try_from(bytes).ok())
+        .ok_or_else(|| CliError(format!("{} MB is too large for {}", value, option)))
+}
+
+// Parses START-END in hex, e.g. 200-2FF
+fn parse_address_range(
+    option: &str,
+    value: String,
+) -> Result<std::ops::RangeInclusive<u16>, CliError> {
+    let range = value.split_once('-').and_then(|(start, end)| {
+        let start = u16::from_str_radix(start.trim(), 16).ok()?;
+        let end = u16::from_str_radix(end.trim(), 16).ok()?;
+        (start <= end).then_some(start..=end)
+    });
+    range.ok_or_else(|| {
+        CliError(format!(
+            "invalid range '{}' for {}, expected START-END in hex",
+            value, option
+        ))
+    })
+}
+
+// Parses a comma-separated list of hex digits into a mask with bit N set for digit N
+fn parse_opcode_classes(option: &str, value: String) -> Result<u16, CliError> {
+    let mut classes = 0;
+    for digit in value.split(',') {
+        match u8::from_str_radix(digit.trim(), 16) {
+            Ok(class) if class < 16 => classes |= 1 << class,
+            _ => {
+                return Err(CliError(format!(
+                    "invalid opcode class '{}' for {}, expected hex digits like 8,D,F",
+                    digit, option
+                )))
+            }
+        }
+    }
+    Ok(classes)
+}
+
+// Parses RRGGBB, with or without a leading '#'
+fn parse_color(option: &str, value: String) -> Result<[u8; 3], CliError> {
+    let hex = value.strip_prefix('#').unwrap_or(&value);
+    let valid = hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit());
+    let rgb = u32::from_str_radix(hex, 16).ok().filter(|_| valid);
+    match rgb {
+        Some(rgb) => Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8]),
+        None => Err(CliError(format!(
+            "invalid color '{}' for {}, expected RRGGBB",
+            value, option
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_options(args: &[&str]) -> Result<Options, CliError> {
+        let args = args.iter().chain(&["game.ch8"]).map(|arg| arg.to_string());
+        match parse(args)? {
+            Command::Run(options) => Ok(*options),
+            command => panic!("expected a run command, got {:?}", command),
+        }
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse(args.iter().map(|arg| arg.to_string())) {
+            Err(CliError(message)) => message,
+            Ok(command) => panic!("expected an error, got {:?}", command),
+        }
+    }
+
+    #[test]
+    fn parses_run_options() {
+        let options = run_options(&[
+            "--ipf", "20", "--scale", "3", "--fg", "#FF8000", "--quirks", "schip",
+        ])
+        .unwrap();
+        assert_eq!(options.rom_path, PathBuf::from("game.ch8"));
+        assert_eq!(options.instructions_per_frame, 20);
+        assert_eq!(options.scale, 3);
+        assert_eq!(options.foreground, [0xFF, 0x80, 0x00]);
+        assert_eq!(options.quirks, Quirks::SUPER_CHIP);
+        assert_eq!(run_options(&[]).unwrap().quirks, Quirks::LEGACY);
+
+        let options = run_options(&["--headless", "--dump", "png"]).unwrap();
+        assert_eq!(options.dump, DumpFormat::Png);
+    }
+
+    #[test]
+    fn reports_bad_arguments() {
+        assert_eq!(error(&[]), "missing ROM path");
+        assert_eq!(error(&["--mute"]), "missing ROM path");
+        assert_eq!(error(&["a.ch8", "b.ch8"]), "unexpected argument 'b.ch8'");
+        assert_eq!(error(&["--fast", "game.ch8"]), "unknown option '--fast'");
+        assert_eq!(error(&["game.ch8", "--ipf"]), "--ipf needs a value");
+        assert_eq!(
+            error(&["--ipf", "ten", "game.ch8"]),
+            "invalid value 'ten' for --ipf"
+        );
+        assert_eq!(
+            error(&["--ipf", "0", "game.ch8"]),
+            "--ipf must be at least 1"
+        );
+        assert_eq!(
+            error(&["--volume", "1.5", "game.ch8"]),
+            "--volume must be between 0 and 1"
+        );
+        assert_eq!(
+            error(&["--quirks", "vipp", "game.ch8"]),
+            "unknown quirk preset 'vipp', expected legacy, vip, chip48, schip or xochip"
+        );
+        assert_eq!(
+            error(&["--fg", "12345G", "game.ch8"]),
+            "invalid color '12345G' for --fg, expected RRGGBB"
+        );
+        assert_eq!(
+            error(&["--bg", "FFF", "game.ch8"]),
+            "invalid color 'FFF' for --bg, expected RRGGBB"
+        );
+        for scale in ["0", "1", "129"] {
+            assert_eq!(
+                error(&["--scale", scale, "game.ch8"]),
+                "--scale must be between 2 and 128"
+            );
+        }
+    }
+
+    #[test]
+    fn headless_options_need_headless() {
+        // Even the default format is refused, rather than silently ignored in the window
+        for args in [
+            ["--dump", "ascii"],
+            ["--cycles", "10"],
+            ["--output", "out.txt"],
+        ] {
+            assert_eq!(
+                run_options(&args),
+                Err(CliError(
+                    "--cycles, --keys, --dump and --output need --headless".to_string()
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn rewind_takes_megabytes() {
+        assert_eq!(run_options(&[]).unwrap().rewind_budget, 16 << 20);
+        assert_eq!(
+            run_options(&["--rewind", "3"]).unwrap().rewind_budget,
+            3 << 20
+        );
+        assert_eq!(run_options(&["--rewind", "0"]).unwrap().rewind_budget, 0);
+        assert_eq!(
+            run_options(&["--rewind", "-1"]),
+            Err(CliError("invalid value '-1' for --rewind".to_string()))
+        );
+        // Large enough to wrap around when shifted into bytes
+        assert_eq!(
+            run_options(&["--rewind", "17592186044416"]),
+            Err(CliError(
+                "17592186044416 MB is too large for --rewind".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn trace_rotate_takes_megabytes_and_needs_trace() {
+        let options = run_options(&["--trace", "out.log", "--trace-rotate", "2"]).unwrap();
+        assert_eq!(options.trace_rotate, Some(2 << 20));
+        assert_eq!(
+            run_options(&["--trace", "out.log", "--trace-rotate", "0"]),
+            Err(CliError("--trace-rotate must be at least 1".to_string()))
+        );
+        assert_eq!(
+            run_options(&["--trace", "out.log", "--trace-rotate", "17592186044416"]),
+            Err(CliError(
+                "17592186044416 MB is too large for --trace-rotate".to_string()
+            ))
+        );
+        assert_eq!(
+            run_options(&["--trace-rotate", "2"]),
+            Err(CliError(
+                "--trace-format, --trace-range, --trace-ops and --trace-rotate need --trace"
+                    .to_string()
+            ))
+        );
+    }
+}
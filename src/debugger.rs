@@ -0,0 +1,793 @@
+//! An interactive debugger layered over [`Chip8`]: PC breakpoints, stepping, run-to-address,
+//! and watchpoints on memory and registers.
+//!
+//! Every instruction run under the debugger is logged with [`undo`](crate::undo), so execution
+//! can also go backwards: stepping back, and reverse-continuing to the last breakpoint or
+//! watchpoint hit.
+//!
+//! The debugger never changes how instructions execute; it decides when to stop. Frontends
+//! call [`Debugger::run_frame`] instead of [`Chip8::run_frame`] and feed user input through
+//! [`Command::parse`] and [`Debugger::execute`].
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use crate::instruction::Instruction;
+use crate::undo::{self, memory_writes, UndoEntry};
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt::{self, Write};
+
+// Instructions kept for stepping back, older ones are forgotten
+const HISTORY_LIMIT: usize = 200_000;
+
+pub const HELP: &str = "\
+Commands (addresses are hex):
+  c, continue          resume execution
+  p, pause             stop execution
+  s, step [N]          execute N instructions (default 1)
+  sb, back [N]         step back N instructions (default 1)
+  rc, rcontinue        run backwards to the previous breakpoint or watchpoint hit
+  n, next              step over a CALL
+  finish               run until the current subroutine returns
+  u, until ADDR        run until PC reaches ADDR
+  b, break ADDR        set a breakpoint
+  d, delete ADDR       remove a breakpoint
+  watch mem ADDR       stop when memory at ADDR is written
+  watch reg REG        stop when REG (v0-vf or i) changes
+  unwatch mem ADDR     remove a memory watchpoint
+  unwatch reg REG      remove a register watchpoint
+  l, list              list breakpoints and watchpoints
+  r, regs              show registers, timers and the stack
+  x, mem ADDR [LEN]    dump LEN bytes of memory (default 16)
+  h, help              show this help
+  q, quit              close the emulator";
+
+/// A register the debugger can watch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Register {
+    V(u8),
+    I,
+}
+
+impl Register {
+    fn read(self, chip8: &Chip8) -> u16 {
+        match self {
+            Register::V(x) => chip8.registers[x as usize] as u16,
+            Register::I => chip8.index_register,
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Continue,
+    Pause,
+    Step(usize),
+    StepBack(usize),
+    ReverseContinue,
+    StepOver,
+    StepOut,
+    RunTo(u16),
+    Break(u16),
+    Delete(u16),
+    WatchMemory(u16),
+    WatchRegister(Register),
+    UnwatchMemory(u16),
+    UnwatchRegister(Register),
+    List,
+    Registers,
+    Memory(u16, u16),
+    Help,
+}
+
+impl Command {
+    /// Parses one line of debugger input.
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let command = match words.as_slice() {
+            ["c" | "continue"] => Command::Continue,
+            ["p" | "pause"] => Command::Pause,
+            ["s" | "step"] => Command::Step(1),
+            ["s" | "step", count] => Command::Step(
+                count
+                    .parse()
+                    .map_err(|_| format!("invalid step count '{}'", count))?,
+            ),
+            ["sb" | "back"] => Command::StepBack(1),
+            ["sb" | "back", count] => Command::StepBack(
+                count
+                    .parse()
+                    .map_err(|_| format!("invalid step count '{}'", count))?,
+            ),
+            ["rc" | "rcontinue"] => Command::ReverseContinue,
+            ["n" | "next"] => Command::StepOver,
+            ["finish"] => Command::StepOut,
+            ["u" | "until", address] => Command::RunTo(parse_address(address)?),
+            ["b" | "break", address] => Command::Break(parse_address(address)?),
+            ["d" | "delete", address] => Command::Delete(parse_address(address)?),
+            ["watch", "mem", address] => Command::WatchMemory(parse_address(address)?),
+            ["watch", "reg", register] => Command::WatchRegister(parse_register(register)?),
+            ["unwatch", "mem", address] => Command::UnwatchMemory(parse_address(address)?),
+            ["unwatch", "reg", register] => Command::UnwatchRegister(parse_register(register)?),
+            ["l" | "list"] => Command::List,
+            ["r" | "regs"] => Command::Registers,
+            ["x" | "mem", address] => Command::Memory(parse_address(address)?, 16),
+            ["x" | "mem", address, length] => {
+                Command::Memory(parse_address(address)?, parse_address(length)?)
+            }
+            ["h" | "help"] => Command::Help,
+            [] => return Err("empty command".to_string()),
+            _ => return Err(format!("unknown command '{}', try 'help'", line.trim())),
+        };
+        Ok(command)
+    }
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address '{}'", text))
+}
+
+fn parse_register(text: &str) -> Result<Register, String> {
+    let lower = text.to_ascii_lowercase();
+    match lower.strip_prefix('v') {
+        _ if lower == "i" => Ok(Register::I),
+        Some(digit) if digit.len() == 1 => u8::from_str_radix(digit, 16)
+            .map(Register::V)
+            .map_err(|_| format!("invalid register '{}'", text)),
+        _ => Err(format!("invalid register '{}'", text)),
+    }
+}
+
+/// Why execution stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(u16),
+    MemoryWrite {
+        address: u16,
+        old: u8,
+        new: u8,
+    },
+    RegisterChange {
+        register: Register,
+        old: u16,
+        new: u16,
+    },
+    Step,
+    TargetReached(u16),
+    SubroutineReturned,
+    Exited,
+    Fault(Chip8Error),
+    /// Stepping back reached the oldest recorded instruction
+    HistoryStart,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            StopReason::Breakpoint(pc) => write!(f, "breakpoint at {:04X}", pc),
+            StopReason::MemoryWrite { address, old, new } => write!(
+                f,
+                "memory {:04X} written: {:02X} -> {:02X}",
+                address, old, new
+            ),
+            StopReason::RegisterChange { register, old, new } => {
+                write!(f, "{} changed: {:02X} -> {:02X}", register, old, new)
+            }
+            StopReason::Step => write!(f, "step"),
+            StopReason::TargetReached(pc) => write!(f, "reached {:04X}", pc),
+            StopReason::SubroutineReturned => write!(f, "returned from subroutine"),
+            StopReason::Exited => write!(f, "program exited"),
+            StopReason::Fault(err) => write!(f, "fault: {}", err),
+            StopReason::HistoryStart => write!(f, "reached the start of the recorded history"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Paused,
+    Running,
+    // Running until PC reaches `pc` with the stack back at `stack_pointer` (step over)
+    UntilReturnTo { pc: u16, stack_pointer: u8 },
+    // Running until the stack drops below `stack_pointer` (step out)
+    UntilStackBelow(u8),
+    UntilPc(u16),
+}
+
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    memory_watches: BTreeSet<u16>,
+    register_watches: BTreeSet<Register>,
+    mode: Mode,
+    // Lets execution resume from a breakpoint without stopping on it again immediately
+    skip_breakpoint: bool,
+    // Undo entries for executed instructions and timer ticks, newest last
+    history: VecDeque<UndoEntry>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    /// Creates a debugger with no breakpoints, paused before the first instruction.
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            memory_watches: BTreeSet::new(),
+            register_watches: BTreeSet::new(),
+            mode: Mode::Paused,
+            skip_breakpoint: false,
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == Mode::Paused
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn pause(&mut self) {
+        self.mode = Mode::Paused;
+    }
+
+    pub fn resume(&mut self) {
+        self.set_mode(Mode::Running);
+    }
+
+    /// Forgets the recorded history, for when the machine state was replaced from outside,
+    /// e.g. by loading a save state.
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.skip_breakpoint = true;
+    }
+
+    /// Runs one 60 Hz frame the way [`Chip8::run_frame`] does, stopping early when a
+    /// breakpoint, watchpoint or stepping target is hit. Timers only tick for frames that ran
+    /// to completion, so a paused machine is completely frozen.
+    pub fn run_frame(
+        &mut self,
+        chip8: &mut Chip8,
+        instructions_per_frame: usize,
+    ) -> Option<StopReason> {
+        if self.is_paused() {
+            return None;
+        }
+        for _ in 0..instructions_per_frame {
+            if let Some(reason) = self.step_instruction(chip8) {
+                self.mode = Mode::Paused;
+                return Some(reason);
+            }
+        }
+        let tick = undo::tick_timers(chip8);
+        self.log(tick);
+        None
+    }
+
+    fn log(&mut self, entry: UndoEntry) {
+        if self.history.len() == HISTORY_LIMIT {
+            self.history.pop_front();
+        }
+        self.history.push_back(entry);
+    }
+
+    /// Executes a single instruction and reports whether it should stop execution.
+    fn step_instruction(&mut self, chip8: &mut Chip8) -> Option<StopReason> {
+        if chip8.exited {
+            return Some(StopReason::Exited);
+        }
+        let pc = chip8.program_counter;
+        if !std::mem::take(&mut self.skip_breakpoint) && self.breakpoints.contains(&pc) {
+            return Some(StopReason::Breakpoint(pc));
+        }
+
+        // Watched bytes the next instruction is about to store to, with their current values
+        let written =
+            next_instruction(chip8).and_then(|instruction| memory_writes(chip8, instruction));
+        let memory_before: Vec<(u16, u8)> = match written {
+            Some(range) => self
+                .memory_watches
+                .iter()
+                .filter(|&&address| range.contains(&(address as usize)))
+                .map(|&address| (address, chip8.memory[address as usize]))
+                .collect(),
+            None => Vec::new(),
+        };
+        let registers_before: Vec<(Register, u16)> = self
+            .register_watches
+            .iter()
+            .map(|&register| (register, register.read(chip8)))
+            .collect();
+
+        let (entry, result) = undo::emulate_cycle(chip8);
+        self.log(entry);
+        if let Err(err) = result {
+            return Some(StopReason::Fault(err));
+        }
+
+        if let Some(&(address, old)) = memory_before.first() {
+            let new = chip8.memory[address as usize];
+            return Some(StopReason::MemoryWrite { address, old, new });
+        }
+        for (register, old) in registers_before {
+            let new = register.read(chip8);
+            if new != old {
+                return Some(StopReason::RegisterChange { register, old, new });
+            }
+        }
+
+        match self.mode {
+            Mode::UntilReturnTo { pc, stack_pointer }
+                if chip8.program_counter == pc && chip8.stack_pointer == stack_pointer =>
+            {
+                Some(StopReason::TargetReached(pc))
+            }
+            Mode::UntilStackBelow(stack_pointer) if chip8.stack_pointer < stack_pointer => {
+                Some(StopReason::SubroutineReturned)
+            }
+            Mode::UntilPc(target) if chip8.program_counter == target => {
+                Some(StopReason::TargetReached(target))
+            }
+            _ => None,
+        }
+    }
+
+    /// Undoes the newest instruction along with the timer ticks after it, and reports whether
+    /// reverse execution should stop there: the instruction wrote a watched byte or register,
+    /// or it sits on a breakpoint. Watch hits are reported as the forward write.
+    fn step_back_instruction(&mut self, chip8: &mut Chip8) -> Option<StopReason> {
+        let memory_after: Vec<(u16, u8)> = self
+            .memory_watches
+            .iter()
+            .map(|&address| (address, chip8.memory[address as usize]))
+            .collect();
+        let registers_after: Vec<(Register, u16)> = self
+            .register_watches
+            .iter()
+            .map(|&register| (register, register.read(chip8)))
+            .collect();
+
+        loop {
+            let Some(entry) = self.history.pop_back() else {
+                return Some(StopReason::HistoryStart);
+            };
+            entry.undo(chip8);
+            if !entry.is_timer_tick() {
+                break;
+            }
+        }
+
+        for (address, new) in memory_after {
+            let old = chip8.memory[address as usize];
+            if old != new {
+                return Some(StopReason::MemoryWrite { address, old, new });
+            }
+        }
+        for (register, new) in registers_after {
+            let old = register.read(chip8);
+            if old != new {
+                return Some(StopReason::RegisterChange { register, old, new });
+            }
+        }
+        let pc = chip8.program_counter;
+        self.breakpoints
+            .contains(&pc)
+            .then_some(StopReason::Breakpoint(pc))
+    }
+
+    /// Carries out a debugger command and returns the text to show the user.
+    pub fn execute(&mut self, command: Command, chip8: &mut Chip8) -> String {
+        match command {
+            Command::Continue => {
+                self.resume();
+                "running".to_string()
+            }
+            Command::Pause => {
+                self.pause();
+                self.state(chip8)
+            }
+            Command::Step(count) => {
+                self.mode = Mode::Paused;
+                self.skip_breakpoint = true;
+                let mut stop = None;
+                for _ in 0..count {
+                    stop = self.step_instruction(chip8);
+                    if stop.is_some() {
+                        break;
+                    }
+                }
+                let reason = stop.unwrap_or(StopReason::Step);
+                format!("{}\n{}", reason, self.state(chip8))
+            }
+            Command::StepBack(count) => {
+                self.mode = Mode::Paused;
+                // Continuing forward shouldn't stop on the breakpoint we just went back to
+                self.skip_breakpoint = true;
+                let mut stop = None;
+                for _ in 0..count {
+                    stop = self.step_back_instruction(chip8);
+                    if stop.is_some() {
+                        break;
+                    }
+                }
+                let reason = stop.unwrap_or(StopReason::Step);
+                format!("{}\n{}", reason, self.state(chip8))
+            }
+            Command::ReverseContinue => {
+                self.mode = Mode::Paused;
+                self.skip_breakpoint = true;
+                let reason = loop {
+                    if let Some(reason) = self.step_back_instruction(chip8) {
+                        break reason;
+                    }
+                };
+                format!("{}\n{}", reason, self.state(chip8))
+            }
+            Command::StepOver => match next_instruction(chip8) {
+                // CALL: run until the instruction after it with the stack back where it was
+                Some(Instruction::Call { .. }) => {
+                    self.set_mode(Mode::UntilReturnTo {
+                        pc: chip8.program_counter.wrapping_add(2),
+                        stack_pointer: chip8.stack_pointer,
+                    });
+                    "running to the next instruction".to_string()
+                }
+                _ => self.execute(Command::Step(1), chip8),
+            },
+            Command::StepOut => {
+                if chip8.stack_pointer == 0 {
+                    "not inside a subroutine".to_string()
+                } else {
+                    self.set_mode(Mode::UntilStackBelow(chip8.stack_pointer));
+                    "running until the subroutine returns".to_string()
+                }
+            }
+            Command::RunTo(address) => {
+                self.set_mode(Mode::UntilPc(address));
+                format!("running to {:04X}", address)
+            }
+            Command::Break(address) => {
+                self.add_breakpoint(address);
+                format!("breakpoint set at {:04X}", address)
+            }
+            Command::Delete(address) => {
+                if self.remove_breakpoint(address) {
+                    format!("breakpoint at {:04X} removed", address)
+                } else {
+                    format!("no breakpoint at {:04X}", address)
+                }
+            }
+            Command::WatchMemory(address) => {
+                if address as usize >= chip8.memory.len() {
+                    return format!("address {:04X} is outside memory", address);
+                }
+                self.memory_watches.insert(address);
+                format!("watching memory {:04X}", address)
+            }
+            Command::WatchRegister(register) => {
+                self.register_watches.insert(register);
+                format!("watching {}", register)
+            }
+            Command::UnwatchMemory(address) => {
+                self.memory_watches.remove(&address);
+                format!("stopped watching memory {:04X}", address)
+            }
+            Command::UnwatchRegister(register) => {
+                self.register_watches.remove(&register);
+                format!("stopped watching {}", register)
+            }
+            Command::List => self.list(),
+            Command::Registers => self.state(chip8),
+            Command::Memory(address, length) => dump_memory(chip8, address, length),
+            Command::Help => HELP.to_string(),
+        }
+    }
+
+    fn list(&self) -> String {
+        let mut out = String::new();
+        let _ = write!(out, "breakpoints:");
+        for address in &self.breakpoints {
+            let _ = write!(out, " {:04X}", address);
+        }
+        let _ = write!(out, "\nmemory watches:");
+        for address in &self.memory_watches {
+            let _ = write!(out, " {:04X}", address);
+        }
+        let _ = write!(out, "\nregister watches:");
+        for register in &self.register_watches {
+            let _ = write!(out, " {}", register);
+        }
+        out
+    }
+
+    /// Registers, timers, the stack and the next instruction, one block of text.
+    pub fn state(&self, chip8: &Chip8) -> String {
+        let mut out = String::new();
+        let next = match chip8.fetch_opcode() {
+            Ok(opcode) => match next_instruction(chip8) {
+                Some(instruction) => format!("{:04X} {}", opcode, instruction),
+                None => format!("{:04X} ???", opcode),
+            },
+            Err(_) => "----".to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "PC {:04X} [{}]  I {:04X}  SP {:X}  DT {:02X}  ST {:02X}",
+            chip8.program_counter,
+            next,
+            chip8.index_register,
+            chip8.stack_pointer,
+            chip8.delay_timer,
+            chip8.sound_timer
+        );
+        for (x, value) in chip8.registers.iter().enumerate() {
+            let separator = if x % 8 == 7 { "\n" } else { "  " };
+            let _ = write!(out, "V{:X} {:02X}{}", x, value, separator);
+        }
+        let _ = write!(out, "stack:");
+        for entry in &chip8.stack[..chip8.stack_pointer as usize] {
+            let _ = write!(out, " {:04X}", entry);
+        }
+        out
+    }
+}
+
+// The instruction at PC, if it's one this machine can execute
+fn next_instruction(chip8: &Chip8) -> Option<Instruction> {
+    let instruction = Instruction::decode(chip8.fetch_opcode().ok()?).ok()?;
+    (chip8.quirks.xo_chip || !instruction.is_xo_chip()).then_some(instruction)
+}
+
+fn dump_memory(chip8: &Chip8, address: u16, length: u16) -> String {
+    let start = address as usize;
+    let end = (start + length as usize).min(chip8.memory.len());
+    if start >= end {
+        return format!("address {:04X} is outside memory", address);
+    }
+    let mut out = String::new();
+    for (row, chunk) in chip8.memory[start..end].chunks(16).enumerate() {
+        if row > 0 {
+            out.push('\n');
+        }
+        let _ = write!(out, "{:04X}:", start + row * 16);
+        for byte in chunk {
+            let _ = write!(out, " {:02X}", byte);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    // Counts V0 up, storing each count at 0x300
+    const PROGRAM: [u8; 10] = [
+        0xA3, 0x00, // LD I, 0x300
+        0x70, 0x01, // ADD V0, 1
+        0xF0, 0x55, // LD [I], V0
+        0x12, 0x02, // JP 0x202
+        0x00, 0x00,
+    ];
+
+    fn run(debugger: &mut Debugger, line: &str, chip8: &mut Chip8) -> String {
+        debugger.execute(Command::parse(line).unwrap(), chip8)
+    }
+
+    fn machine() -> (Debugger, Chip8) {
+        let mut chip8 = Chip8::new(Quirks::CHIP_48);
+        chip8.load_program(&PROGRAM).unwrap();
+        (Debugger::new(), chip8)
+    }
+
+    #[test]
+    fn step_back_restores_every_instruction() {
+        let (mut debugger, mut chip8) = machine();
+        let start = chip8.save_state();
+        debugger.resume();
+        for _ in 0..5 {
+            debugger.run_frame(&mut chip8, 7);
+        }
+        assert_eq!(chip8.registers[0], 12);
+
+        run(&mut debugger, "back 4", &mut chip8);
+        assert_eq!(chip8.program_counter, 0x202);
+        assert_eq!((chip8.registers[0], chip8.memory[0x300]), (10, 10));
+        assert!(run(&mut debugger, "back 1000", &mut chip8).starts_with("reached the start"));
+        assert_eq!(chip8.save_state(), start);
+    }
+
+    #[test]
+    fn reverse_continue_stops_at_watchpoints_and_breakpoints() {
+        let (mut debugger, mut chip8) = machine();
+        run(&mut debugger, "s 9", &mut chip8);
+        assert_eq!(chip8.registers[0], 3);
+
+        run(&mut debugger, "watch mem 300", &mut chip8);
+        let out = run(&mut debugger, "rc", &mut chip8);
+        assert!(out.starts_with("memory 0300 written: 02 -> 03"), "{}", out);
+        assert_eq!(chip8.program_counter, 0x204);
+
+        run(&mut debugger, "unwatch mem 300", &mut chip8);
+        run(&mut debugger, "b 202", &mut chip8);
+        run(&mut debugger, "rc", &mut chip8);
+        assert_eq!((chip8.program_counter, chip8.registers[0]), (0x202, 2));
+        // Going forward again doesn't stop on the breakpoint just reached
+        run(&mut debugger, "s", &mut chip8);
+        assert_eq!(chip8.registers[0], 3);
+    }
+
+    // Calls a subroutine that stores V0-V2 at 0x300, then counts V1 up once and spins
+    const SUBROUTINE: [u8; 18] = [
+        0x60, 0x05, // LD V0, 5
+        0x22, 0x0A, // CALL 0x20A
+        0x71, 0x01, // ADD V1, 1
+        0x12, 0x06, // JP 0x206
+        0x00, 0x00, // padding
+        0x62, 0x07, // 0x20A: LD V2, 7
+        0xA3, 0x00, // LD I, 0x300
+        0xF2, 0x55, // LD [I], V2
+        0x00, 0xEE, // RET
+    ];
+
+    fn subroutine() -> (Debugger, Chip8) {
+        let mut chip8 = Chip8::new(Quirks::CHIP_48);
+        chip8.load_program(&SUBROUTINE).unwrap();
+        (Debugger::new(), chip8)
+    }
+
+    #[test]
+    fn breakpoints_stop_before_the_instruction() {
+        let (mut debugger, mut chip8) = subroutine();
+        run(&mut debugger, "b 20C", &mut chip8);
+        run(&mut debugger, "c", &mut chip8);
+        assert_eq!(
+            debugger.run_frame(&mut chip8, 100),
+            Some(StopReason::Breakpoint(0x20C))
+        );
+        assert!(debugger.is_paused());
+        assert_eq!((chip8.registers[2], chip8.index_register), (7, 0));
+
+        // Continuing runs past the breakpoint it is stopped on
+        run(&mut debugger, "c", &mut chip8);
+        assert_eq!(debugger.run_frame(&mut chip8, 100), None);
+        assert_eq!(chip8.registers[1], 1);
+        assert_eq!(
+            run(&mut debugger, "d 20C", &mut chip8),
+            "breakpoint at 020C removed"
+        );
+        assert_eq!(debugger.breakpoints().count(), 0);
+    }
+
+    #[test]
+    fn step_runs_single_instructions() {
+        let (mut debugger, mut chip8) = subroutine();
+        assert!(run(&mut debugger, "s", &mut chip8).starts_with("step\n"));
+        assert_eq!(chip8.program_counter, 0x202);
+        run(&mut debugger, "step 3", &mut chip8);
+        assert_eq!((chip8.program_counter, chip8.stack_pointer), (0x20E, 1));
+        assert!(debugger.is_paused());
+        // A paused debugger doesn't run frames
+        assert_eq!(debugger.run_frame(&mut chip8, 100), None);
+        assert_eq!(chip8.program_counter, 0x20E);
+    }
+
+    #[test]
+    fn next_steps_over_calls() {
+        let (mut debugger, mut chip8) = subroutine();
+        run(&mut debugger, "s", &mut chip8);
+        assert_eq!(
+            run(&mut debugger, "n", &mut chip8),
+            "running to the next instruction"
+        );
+        assert_eq!(
+            debugger.run_frame(&mut chip8, 100),
+            Some(StopReason::TargetReached(0x204))
+        );
+        assert_eq!((chip8.registers[2], chip8.stack_pointer), (7, 0));
+
+        // Anything but a CALL is just a step
+        run(&mut debugger, "next", &mut chip8);
+        assert_eq!((chip8.program_counter, chip8.registers[1]), (0x206, 1));
+    }
+
+    #[test]
+    fn finish_runs_until_the_subroutine_returns() {
+        let (mut debugger, mut chip8) = subroutine();
+        assert_eq!(
+            run(&mut debugger, "finish", &mut chip8),
+            "not inside a subroutine"
+        );
+        run(&mut debugger, "s 2", &mut chip8);
+        assert_eq!(chip8.stack_pointer, 1);
+        run(&mut debugger, "finish", &mut chip8);
+        assert_eq!(
+            debugger.run_frame(&mut chip8, 100),
+            Some(StopReason::SubroutineReturned)
+        );
+        assert_eq!((chip8.program_counter, chip8.stack_pointer), (0x204, 0));
+    }
+
+    #[test]
+    fn until_runs_to_an_address() {
+        let (mut debugger, mut chip8) = subroutine();
+        assert_eq!(run(&mut debugger, "u 210", &mut chip8), "running to 0210");
+        assert_eq!(
+            debugger.run_frame(&mut chip8, 100),
+            Some(StopReason::TargetReached(0x210))
+        );
+        assert_eq!(chip8.memory[0x300..0x303], [5, 0, 7]);
+    }
+
+    #[test]
+    fn watchpoints_stop_after_the_write() {
+        let (mut debugger, mut chip8) = subroutine();
+        run(&mut debugger, "watch reg v2", &mut chip8);
+        run(&mut debugger, "c", &mut chip8);
+        assert_eq!(
+            debugger.run_frame(&mut chip8, 100),
+            Some(StopReason::RegisterChange {
+                register: Register::V(2),
+                old: 0,
+                new: 7
+            })
+        );
+        assert_eq!(chip8.program_counter, 0x20C);
+
+        run(&mut debugger, "unwatch reg v2", &mut chip8);
+        run(&mut debugger, "watch reg i", &mut chip8);
+        run(&mut debugger, "c", &mut chip8);
+        assert_eq!(
+            debugger.run_frame(&mut chip8, 100),
+            Some(StopReason::RegisterChange {
+                register: Register::I,
+                old: 0,
+                new: 0x300
+            })
+        );
+
+        run(&mut debugger, "unwatch reg i", &mut chip8);
+        run(&mut debugger, "watch mem 302", &mut chip8);
+        run(&mut debugger, "c", &mut chip8);
+        assert_eq!(
+            debugger.run_frame(&mut chip8, 100),
+            Some(StopReason::MemoryWrite {
+                address: 0x302,
+                old: 0,
+                new: 7
+            })
+        );
+        assert_eq!(chip8.program_counter, 0x210);
+        assert_eq!(
+            run(&mut debugger, "watch mem 1000", &mut chip8),
+            "address 1000 is outside memory"
+        );
+    }
+}
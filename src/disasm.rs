@@ -0,0 +1,200 @@
+//! A disassembler that turns a ROM back into mnemonics.
+//!
+//! Control flow is followed from [`PROGRAM_START_ADDRESS`] to tell instructions apart from the
+//! sprites and tables stored between them. Jump and call targets get labels, and bytes that
+//! are never executed are printed as sprite art instead of being decoded.
+
+use crate::chip8::PROGRAM_START_ADDRESS;
+use crate::instruction::Instruction;
+use crate::quirks::Quirks;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    // Ordered by priority, an address that is both called and loaded into I is a subroutine
+    Data,
+    Jump,
+    Subroutine,
+}
+
+/// Disassembles a ROM loaded at [`PROGRAM_START_ADDRESS`] into an assembly listing.
+pub fn disassemble(program: &[u8], quirks: Quirks) -> String {
+    let base = PROGRAM_START_ADDRESS;
+    let end = base + program.len();
+    let read = |address: usize| -> Option<u16> {
+        let offset = address.checked_sub(base)?;
+        let bytes = program.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+
+    // Follow every reachable path, recording instruction starts and label targets
+    let mut instructions: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut labels: BTreeMap<u16, LabelKind> = BTreeMap::new();
+    let mut pending = vec![base];
+    while let Some(address) = pending.pop() {
+        if instructions.contains_key(&address) {
+            continue;
+        }
+        let Some(instruction) = read(address).and_then(|opcode| decode(opcode, quirks)) else {
+            continue;
+        };
+        instructions.insert(address, instruction.length() as usize);
+        let next = address + instruction.length() as usize;
+        match instruction {
+            Instruction::LdIAddr { addr } => {
+                add_label(&mut labels, addr, LabelKind::Data);
+                pending.push(next);
+            }
+            Instruction::LdILong => {
+                if let Some(addr) = read(address + 2) {
+                    add_label(&mut labels, addr, LabelKind::Data);
+                }
+                pending.push(next);
+            }
+            Instruction::Jp { addr } | Instruction::JpV0Addr { addr } => {
+                // BNNN's target depends on a register, NNN is only the base of a jump table
+                add_label(&mut labels, addr, LabelKind::Jump);
+                pending.push(addr as usize);
+            }
+            Instruction::Call { addr } => {
+                add_label(&mut labels, addr, LabelKind::Subroutine);
+                pending.push(addr as usize);
+                pending.push(next);
+            }
+            Instruction::Ret | Instruction::Exit => {}
+            _ if instruction.is_skip() => {
+                // The skipped instruction may be a four byte F000 NNNN
+                let skipped = read(next)
+                    .and_then(|opcode| decode(opcode, quirks))
+                    .map_or(2, |skipped| skipped.length() as usize);
+                pending.push(next);
+                pending.push(next + skipped);
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    // Lay out the listing: traced instructions, with every other byte shown as data
+    let mut rows = Vec::new();
+    let mut address = base;
+    while address < end {
+        let length = instructions.get(&address).copied();
+        rows.push((address, length));
+        address += length.unwrap_or(1);
+    }
+
+    // Only addresses that start a row get labels, everything else stays a plain number
+    labels.retain(|&address, _| rows.iter().any(|&(row, _)| row == address as usize));
+    let name = |address: u16| match labels.get(&address) {
+        Some(LabelKind::Subroutine) => format!("sub_{:03X}", address),
+        Some(LabelKind::Jump) => format!("label_{:03X}", address),
+        Some(LabelKind::Data) => format!("data_{:03X}", address),
+        None => format!("0x{:03X}", address),
+    };
+
+    let mut out = String::new();
+    for (address, length) in rows {
+        if labels.contains_key(&(address as u16)) {
+            let _ = writeln!(out, "{}:", name(address as u16));
+        }
+        match length {
+            Some(length) => {
+                let opcode = read(address).unwrap_or_default();
+                let instruction = decode(opcode, quirks).expect("traced instructions decode");
+                let (raw, text) = match (instruction, read(address + 2)) {
+                    (Instruction::LdILong, Some(long)) if length == 4 => (
+                        format!("{:04X} {:04X}", opcode, long),
+                        format!("LD I, long {}", name(long)),
+                    ),
+                    _ => (
+                        format!("{:04X}", opcode),
+                        instruction.format_with(&quirks, &name),
+                    ),
+                };
+                let _ = writeln!(out, "    {:03X}: {:<9}  {}", address, raw, text);
+            }
+            None => {
+                let byte = program[address - base];
+                let art: String = (0..8)
+                    .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                    .collect();
+                let _ = writeln!(out, "    {:03X}: {:02X}         {}", address, byte, art);
+            }
+        }
+    }
+    out
+}
+
+fn add_label(labels: &mut BTreeMap<u16, LabelKind>, address: u16, kind: LabelKind) {
+    let entry = labels.entry(address).or_insert(kind);
+    *entry = (*entry).max(kind);
+}
+
+// Decodes an opcode the way a machine with these quirks would execute it
+fn decode(opcode: u16, quirks: Quirks) -> Option<Instruction> {
+    let instruction = Instruction::decode(opcode).ok()?;
+    (quirks.xo_chip || !instruction.is_xo_chip()).then_some(instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(program: &[u8], quirks: Quirks) -> Vec<String> {
+        disassemble(program, quirks)
+            .lines()
+            .map(|line| line.trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn operands_follow_the_quirks() {
+        // SHR V1, V2; SHL V1, V2; JP V0, 0x234
+        let program = [0x81, 0x26, 0x81, 0x2E, 0xB2, 0x34];
+        assert_eq!(
+            listing(&program, Quirks::COSMAC_VIP),
+            [
+                "200: 8126       SHR V1, V2",
+                "202: 812E       SHL V1, V2",
+                "204: B234       JP V0, 0x234",
+            ]
+        );
+        assert_eq!(
+            listing(&program, Quirks::SUPER_CHIP),
+            [
+                "200: 8126       SHR V1",
+                "202: 812E       SHL V1",
+                "204: B234       JP V2, 0x234",
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_targets_and_prints_unreached_bytes_as_data() {
+        let program = [
+            0xA2, 0x0A, // LD I, 0x20A
+            0x22, 0x08, // CALL 0x208
+            0x12, 0x04, // JP 0x204
+            0x60, 0x01, // never reached, so not LD V0, 0x01
+            0x00, 0xEE, // RET
+            0xF0, 0x90, // sprite rows
+        ];
+        assert_eq!(
+            listing(&program, Quirks::COSMAC_VIP),
+            [
+                "200: A20A       LD I, data_20A",
+                "202: 2208       CALL sub_208",
+                "label_204:",
+                "204: 1204       JP label_204",
+                "206: 60         .##.....",
+                "207: 01         .......#",
+                "sub_208:",
+                "208: 00EE       RET",
+                "data_20A:",
+                "20A: F0         ####....",
+                "20B: 90         #..#....",
+            ]
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use std::error::Error;
+use std::fmt;
+
+/// Faults raised by the CHIP-8 core while loading or executing a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// the opcode at `pc` does not decode to any known instruction
+    UnknownOpcode { pc: u16, opcode: u16 },
+    /// CALL with all stack levels already in use
+    StackOverflow { pc: u16, opcode: u16 },
+    /// RET with an empty stack
+    StackUnderflow { pc: u16, opcode: u16 },
+    /// an instruction tried to read or write memory past the end of the address space
+    MemoryOutOfBounds {
+        pc: u16,
+        opcode: u16,
+        address: usize,
+    },
+    /// the program counter points somewhere an opcode can't be fetched from
+    PcOutOfRange { pc: u16 },
+    /// the program doesn't fit between the program start address and the end of memory
+    ProgramTooLarge { size: usize, capacity: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Chip8Error::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:04X}", opcode, pc)
+            }
+            Chip8Error::StackOverflow { pc, opcode } => {
+                write!(f, "stack overflow executing {:04X} at {:04X}", opcode, pc)
+            }
+            Chip8Error::StackUnderflow { pc, opcode } => {
+                write!(f, "stack underflow executing {:04X} at {:04X}", opcode, pc)
+            }
+            Chip8Error::MemoryOutOfBounds {
+                pc,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "out-of-bounds memory access at {:#X} executing {:04X} at {:04X}",
+                address, opcode, pc
+            ),
+            Chip8Error::PcOutOfRange { pc } => {
+                write!(f, "program counter out of range: {:04X}", pc)
+            }
+            Chip8Error::ProgramTooLarge { size, capacity } => write!(
+                f,
+                "program is {} bytes but only {} bytes are available",
+                size, capacity
+            ),
+        }
+    }
+}
+
+impl Error for Chip8Error {}
+
+// Fault raised inside an opcode handler, before the PC and opcode are attached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fault {
+    UnknownOpcode,
+    StackOverflow,
+    StackUnderflow,
+    MemoryOutOfBounds(usize),
+}
+
+impl Fault {
+    pub(crate) fn at(self, pc: u16, opcode: u16) -> Chip8Error {
+        match self {
+            Fault::UnknownOpcode => Chip8Error::UnknownOpcode { pc, opcode },
+            Fault::StackOverflow => Chip8Error::StackOverflow { pc, opcode },
+            Fault::StackUnderflow => Chip8Error::StackUnderflow { pc, opcode },
+            Fault::MemoryOutOfBounds(address) => Chip8Error::MemoryOutOfBounds {
+                pc,
+                opcode,
+                address,
+            },
+        }
+    }
+}
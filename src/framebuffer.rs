@@ -0,0 +1,229 @@
+//! Read-only views of the display for tools and tests: per-pixel access, ASCII art, PBM and
+//! PNG images, and a hash that identifies a frame.
+
+use crate::chip8::Chip8;
+use crate::hash;
+
+// Gray levels for each combination of the two XO-CHIP bitplanes: off, plane 1, plane 2, both
+const GRAY_LEVELS: [u8; 4] = [0, 255, 170, 85];
+// ASCII art characters for the same combinations
+const ASCII_PIXELS: [char; 4] = ['.', '#', '+', '@'];
+// PNG stored deflate blocks hold at most this many bytes
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+impl Chip8 {
+    /// The planes lit at (x, y) as a bitmask, bit 0 for plane 1 and bit 1 for plane 2.
+    /// Returns 0 outside the current resolution.
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        if x >= self.screen_width() || y >= self.screen_height() {
+            return 0;
+        }
+        self.screen[y * self.screen_width() + x] & 0b11
+    }
+
+    /// The display as one line of text per row: `.` for off, `#` for plane 1, `+` for plane 2
+    /// and `@` for both.
+    pub fn screen_ascii(&self) -> String {
+        let width = self.screen_width();
+        let mut out = String::with_capacity((width + 1) * self.screen_height());
+        for row in self.screen.chunks(width) {
+            out.extend(
+                row.iter()
+                    .map(|&pixel| ASCII_PIXELS[(pixel & 0b11) as usize]),
+            );
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The display as a binary PBM (P4) image, lit pixels black.
+    pub fn screen_pbm(&self) -> Vec<u8> {
+        let width = self.screen_width();
+        let mut out = format!("P4\n{} {}\n", width, self.screen_height()).into_bytes();
+        for row in self.screen.chunks(width) {
+            for byte in row.chunks(8) {
+                let packed = byte.iter().enumerate().fold(0u8, |packed, (bit, &pixel)| {
+                    packed | (((pixel != 0) as u8) << (7 - bit))
+                });
+                out.push(packed);
+            }
+        }
+        out
+    }
+
+    /// The display as an 8-bit grayscale PNG: plane 1 white, plane 2 light gray, both dark
+    /// gray, matching the SDL frontend's default palette.
+    pub fn screen_png(&self) -> Vec<u8> {
+        let width = self.screen_width();
+        let height = self.screen_height();
+
+        // Each scanline starts with filter type 0 (none)
+        let mut raw = Vec::with_capacity((width + 1) * height);
+        for row in self.screen.chunks(width) {
+            raw.push(0);
+            raw.extend(
+                row.iter()
+                    .map(|&pixel| GRAY_LEVELS[(pixel & 0b11) as usize]),
+            );
+        }
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(height as u32).to_be_bytes());
+        header.extend_from_slice(&[8, 0, 0, 0, 0]); // 8-bit grayscale, no interlacing
+
+        let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut out, b"IHDR", &header);
+        png_chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    /// Hash of the current frame, including its resolution. Equal frames hash equal across
+    /// platforms and builds.
+    pub fn screen_hash(&self) -> u64 {
+        let mut bytes = Vec::with_capacity(self.screen.len() + 1);
+        bytes.push(self.hires as u8);
+        bytes.extend_from_slice(&self.screen);
+        hash::fnv1a(&bytes)
+    }
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = hash::crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// Wraps `data` in a zlib stream of uncompressed deflate blocks; frames are tiny, so
+// compressing them isn't worth the code
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(MAX_STORED_BLOCK).collect();
+    for (i, block) in blocks.iter().enumerate() {
+        let last = i + 1 == blocks.len();
+        out.push(last as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    if blocks.is_empty() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    out.extend_from_slice(&hash::adler32(data).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A lo-res screen with plane 1 at (0, 0), plane 2 at (9, 0) and both planes at (63, 31)
+    fn machine() -> Chip8 {
+        let mut chip8 = Chip8::builder().build();
+        chip8.screen[0] = 0b01;
+        chip8.screen[9] = 0b10;
+        chip8.screen[64 * 32 - 1] = 0b11;
+        chip8
+    }
+
+    #[test]
+    fn ascii_has_a_line_per_row_and_a_character_per_plane() {
+        let ascii = machine().screen_ascii();
+        let lines: Vec<&str> = ascii.lines().collect();
+        assert_eq!(lines.len(), 32);
+        assert!(lines.iter().all(|line| line.len() == 64));
+        assert!(lines[0].starts_with("#........+..."));
+        assert!(lines[31].ends_with(".@"));
+        assert!(ascii.ends_with('\n'));
+
+        let hires = Chip8::builder().hires().build().screen_ascii();
+        assert_eq!(hires.lines().count(), 64);
+        assert!(hires.lines().all(|line| line.len() == 128));
+    }
+
+    #[test]
+    fn pbm_packs_rows_most_significant_bit_first() {
+        let pbm = machine().screen_pbm();
+        let header = b"P4\n64 32\n";
+        assert_eq!(&pbm[..header.len()], header);
+        // Every row is 8 whole bytes, and any lit plane is black
+        let rows: Vec<&[u8]> = pbm[header.len()..].chunks(8).collect();
+        assert_eq!(rows.len(), 32);
+        assert_eq!(rows[0], [0x80, 0x40, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(rows[31], [0, 0, 0, 0, 0, 0, 0, 0x01]);
+        assert!(rows[1..31]
+            .iter()
+            .all(|row| row.iter().all(|&byte| byte == 0)));
+
+        let hires = Chip8::builder().hires().build().screen_pbm();
+        assert_eq!(hires.len(), b"P4\n128 64\n".len() + 16 * 64);
+    }
+
+    #[test]
+    fn png_has_a_valid_header_and_chunk_checksums() {
+        let png = machine().screen_png();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+
+        // Walk the chunks, checking each one's CRC over its type and data
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (body, crc) = rest[4..].split_at(4 + len);
+            let crc = u32::from_be_bytes(crc[..4].try_into().unwrap());
+            assert_eq!(crc, hash::crc32(body));
+            chunks.push((&body[..4], &body[4..]));
+            rest = &rest[8 + len + 4..];
+        }
+        let kinds: Vec<&[u8]> = chunks.iter().map(|&(kind, _)| kind).collect();
+        assert_eq!(kinds, [&b"IHDR"[..], b"IDAT", b"IEND"]);
+        // The well-known checksum of every empty IEND chunk
+        assert_eq!(&png[png.len() - 4..], [0xAE, 0x42, 0x60, 0x82]);
+
+        // 64x32, 8-bit grayscale, default compression and filtering, no interlacing
+        assert_eq!(chunks[0].1, [0, 0, 0, 64, 0, 0, 0, 32, 8, 0, 0, 0, 0]);
+
+        // One stored block: zlib header, final block marker, length and its complement, then
+        // the scanlines, each led by filter type 0
+        let idat = chunks[1].1;
+        let raw_len = 65 * 32;
+        assert_eq!(idat[..2], [0x78, 0x01]);
+        assert_eq!(idat[2], 1);
+        assert_eq!(idat[3..5], (raw_len as u16).to_le_bytes());
+        assert_eq!(idat[5..7], (!(raw_len as u16)).to_le_bytes());
+        let raw = &idat[7..7 + raw_len];
+        assert_eq!(raw[..11], [0, 255, 0, 0, 0, 0, 0, 0, 0, 0, 170]);
+        assert_eq!(raw[raw_len - 2..], [0, 85]);
+        assert_eq!(idat[7 + raw_len..], hash::adler32(raw).to_be_bytes());
+    }
+
+    #[test]
+    fn hash_is_stable_and_includes_the_resolution() {
+        // Pinned values, so a change to how frames are hashed shows up here first
+        assert_eq!(
+            Chip8::builder().build().screen_hash(),
+            0x724d_5fe3_3c75_97df
+        );
+        assert_eq!(
+            Chip8::builder().hires().build().screen_hash(),
+            0xac1a_3da2_ee97_b62c
+        );
+
+        let mut chip8 = Chip8::builder().build();
+        chip8.screen[0] = 1;
+        assert_eq!(chip8.screen_hash(), 0x23b4_9731_eced_77de);
+
+        // A blank hi-res screen isn't a blank lo-res one, even with the pixel buffer resized
+        let mut lores = Chip8::builder().build();
+        lores.screen = vec![0; 128 * 64];
+        assert_ne!(
+            lores.screen_hash(),
+            Chip8::builder().hires().build().screen_hash()
+        );
+    }
+}
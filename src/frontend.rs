@@ -0,0 +1,534 @@
+use crate::audio::Audio;
+use crate::cli::Options;
+use crate::overlay;
+use crate::repl::Repl;
+use chip_8::chip8::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use chip_8::debugger::{Debugger, StopReason};
+use chip_8::gdb::GdbServer;
+use chip_8::inspector::{Inspector, PANEL_COLUMNS};
+use chip_8::keymap::{Bindings, KeyConfig, Keymap, KEYPAD};
+use chip_8::movie::{Movie, Recorder};
+use chip_8::rewind::Rewind;
+use chip_8::{Chip8, Chip8Error};
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::time::Instant;
+
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+// Frames between rewind snapshots; one snapshot is restored per frame, so rewinding runs at
+// this multiple of normal speed
+const REWIND_INTERVAL: u32 = 2;
+
+// Runs the program in an SDL window until the user closes it. A movie being played drives the
+// keypad until it ends; the recorder sees the keypad before every frame that runs
+pub fn run(
+    chip8: &mut Chip8,
+    options: &Options,
+    mut playback: Option<&Movie>,
+    mut recorder: Option<&mut Recorder>,
+) -> Result<(), Box<dyn Error>> {
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+
+    let window_width = SCREEN_WIDTH as u32 * options.scale;
+    let window_height = SCREEN_HEIGHT as u32 * options.scale;
+    let window = video_subsystem
+        .window("CHIP-8 Emulator", window_width, window_height)
+        .position_centered()
+        .build()?;
+    let mut canvas = window.into_canvas().build()?;
+    let palette = palette(options);
+    canvas.set_draw_color(palette[0]);
+    canvas.clear();
+    canvas.present();
+
+    // A missing audio device shouldn't stop the emulator, it just runs silent
+    let mut audio = match sdl_context
+        .audio()
+        .and_then(|audio_subsystem| Audio::new(&audio_subsystem, options.audio))
+    {
+        Ok(audio) => Some(audio),
+        Err(err) => {
+            eprintln!("Audio disabled: {}", err);
+            None
+        }
+    };
+
+    let mut event_pump = sdl_context.event_pump()?;
+
+    let mut next_frame = Instant::now();
+    // Set once the core faults; the machine stays halted but the window stays open
+    let mut fault: Option<Chip8Error> = None;
+    // With --debug the debugger decides when instructions run; faults just pause it
+    let mut debugger = options.debug.then(|| (Debugger::new(), Repl::spawn()));
+    // Frames run so far, the clock movies are played and recorded against
+    let mut frame: u64 = 0;
+    let mut gdb = match options.gdb {
+        Some(port) => {
+            let server = GdbServer::bind(("127.0.0.1", port))?;
+            println!("Waiting for GDB on {}", server.local_addr()?);
+            Some(server)
+        }
+        None => None,
+    };
+    // Rewinding would desync a movie, so there's no history while one plays or records. Nor
+    // while a remote debugger might be looking at the machine
+    let mut rewind =
+        (options.rewind_budget > 0 && playback.is_none() && recorder.is_none() && gdb.is_none())
+            .then(|| Rewind::new(options.rewind_budget, REWIND_INTERVAL));
+    // Set while Backspace is held
+    let mut rewinding = false;
+    // The state panel Tab shows to the right of the game, with text at a size that suits the
+    // game's scale
+    let mut inspector = Inspector::new();
+    let mut show_inspector = false;
+    let text_scale = (options.scale / 5).max(1);
+    let (panel_width, panel_height) =
+        overlay::panel_size(PANEL_COLUMNS, inspector.lines(chip8).len(), text_scale);
+    // Bindings are looked up by the ROM's file name; F5 rebinds the global layout and
+    // Shift+F5 just this ROM's
+    let keymap_path = options.keymap.clone().or_else(default_keymap_path);
+    let mut key_config = match &keymap_path {
+        Some(path) => load_key_config(path)?,
+        None => KeyConfig::default(),
+    };
+    let rom_name = options
+        .rom_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut keymap = key_config.keymap(&rom_name);
+    let mut rebind: Option<Rebind> = None;
+
+    // Main emulation loop
+    'running: loop {
+        // Poll for events and handle key presses/releases
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } if rebind.is_some() => {
+                    let Some(current) = rebind.as_mut() else {
+                        continue;
+                    };
+                    match keycode {
+                        Keycode::Escape => {
+                            println!("Rebinding cancelled");
+                            rebind = None;
+                        }
+                        Keycode::Return | Keycode::KpEnter => {
+                            // Once every key has had its turn the bindings are saved
+                            if current.next() {
+                                if let Some(finished) = rebind.take() {
+                                    finished.apply(&mut key_config, keymap_path.as_deref());
+                                    keymap = key_config.keymap(&rom_name);
+                                }
+                            }
+                        }
+                        _ if is_hotkey(keycode) => {
+                            eprintln!("{} is reserved for the emulator", keycode.name())
+                        }
+                        _ => current.press(keycode.name()),
+                    }
+                    let title = match &rebind {
+                        Some(rebind) => format!("CHIP-8 Emulator - {}", rebind.prompt(&keymap)),
+                        None => "CHIP-8 Emulator".to_string(),
+                    };
+                    let _ = canvas.window_mut().set_title(&title);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    keymod,
+                    repeat: false,
+                    ..
+                } => {
+                    let rom = keymod
+                        .intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD)
+                        .then(|| rom_name.clone());
+                    let started = Rebind::new(rom);
+                    let _ = canvas
+                        .window_mut()
+                        .set_title(&format!("CHIP-8 Emulator - {}", started.prompt(&keymap)));
+                    rebind = Some(started);
+                    // Nothing stays held down by a key that's about to be rebound
+                    if playback.is_none() {
+                        chip8.keys = [0; 16];
+                    }
+                }
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => {
+                    break 'running;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(audio) = audio.as_mut() {
+                        let muted = audio.toggle_mute();
+                        println!("Sound {}", if muted { "muted" } else { "unmuted" });
+                    }
+                }
+                // F1-F4 save to slots 1-4, Shift+F1-F4 loads them
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::F1 | Keycode::F2 | Keycode::F3 | Keycode::F4)),
+                    keymod,
+                    repeat: false,
+                    ..
+                } => {
+                    let slot = match keycode {
+                        Keycode::F1 => 1,
+                        Keycode::F2 => 2,
+                        Keycode::F3 => 3,
+                        _ => 4,
+                    };
+                    let path = save_slot_path(options, slot);
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        if playback.is_some() || recorder.is_some() {
+                            eprintln!("Can't load a state while a movie is playing or recording");
+                            continue;
+                        }
+                        match load_state(chip8, &path) {
+                            Ok(()) => {
+                                println!("Loaded state from slot {}", slot);
+                                // The restored machine may not be faulted anymore
+                                fault = None;
+                                let _ = canvas.window_mut().set_title("CHIP-8 Emulator");
+                                // Undo logs from before the load don't apply to the new state
+                                if let Some((debugger, _)) = debugger.as_mut() {
+                                    debugger.clear_history();
+                                }
+                            }
+                            Err(err) => eprintln!("Failed to load slot {}: {}", slot, err),
+                        }
+                    } else {
+                        match fs::write(&path, chip8.save_state()) {
+                            Ok(()) => println!("Saved state to slot {}", slot),
+                            Err(err) => eprintln!("Failed to save slot {}: {}", slot, err),
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = false,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    repeat: false,
+                    ..
+                } => {
+                    show_inspector = !show_inspector;
+                    let size = if show_inspector {
+                        (window_width + panel_width, window_height.max(panel_height))
+                    } else {
+                        (window_width, window_height)
+                    };
+                    let _ = canvas.window_mut().set_size(size.0, size.1);
+                }
+                // PageUp/PageDown scroll the panel's hex dump, Home brings PC back into view
+                Event::KeyDown {
+                    keycode: Some(Keycode::PageUp),
+                    ..
+                } if show_inspector => inspector.scroll(chip8, -(inspector.memory_rows as isize)),
+                Event::KeyDown {
+                    keycode: Some(Keycode::PageDown),
+                    ..
+                } if show_inspector => inspector.scroll(chip8, inspector.memory_rows as isize),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Home),
+                    ..
+                } if show_inspector => inspector.follow_pc(),
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if playback.is_none() => {
+                    if let Some(key) = keymap.key(&keycode.name()) {
+                        chip8.keys[key as usize] = 1;
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } if playback.is_none() => {
+                    if let Some(key) = keymap.key(&keycode.name()) {
+                        chip8.keys[key as usize] = 0;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Run one frame worth of CPU cycles, then tick the timers
+        if let Some((debugger, repl)) = debugger.as_mut() {
+            if !repl.poll(debugger, chip8) {
+                break 'running;
+            }
+            if let Some(reason) = debugger.run_frame(chip8, options.instructions_per_frame) {
+                repl.report(reason, debugger, chip8);
+                if let StopReason::Fault(err) = reason {
+                    let _ = canvas
+                        .window_mut()
+                        .set_title(&format!("CHIP-8 Emulator - halted: {}", err));
+                }
+            }
+        } else if let (true, Some(rewind)) = (rewinding, rewind.as_mut()) {
+            match rewind.step_back(chip8) {
+                Ok(true) => {
+                    // Going back before a fault un-halts the machine
+                    fault = None;
+                    let _ = canvas.window_mut().set_title("CHIP-8 Emulator");
+                }
+                // The oldest snapshot stays on screen until Backspace is released
+                Ok(false) => {}
+                Err(err) => eprintln!("Failed to rewind: {}", err),
+            }
+        } else if fault.is_none() && !chip8.exited {
+            if let Some(rewind) = rewind.as_mut() {
+                rewind.record(chip8);
+            }
+            if let Some(movie) = playback {
+                movie.keys.apply(chip8, frame);
+                if frame + 1 >= movie.frames {
+                    println!("Movie finished after {} frames", movie.frames);
+                    playback = None;
+                }
+            }
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record_frame(chip8);
+            }
+            frame += 1;
+            let result = match gdb.as_mut() {
+                Some(gdb) => gdb.run_frame(chip8, options.instructions_per_frame),
+                None => chip8.run_frame(options.instructions_per_frame),
+            };
+            if gdb.as_ref().is_some_and(GdbServer::is_killed) {
+                break 'running;
+            }
+            if let Err(err) = result {
+                eprintln!("CHIP-8 halted: {}", err);
+                let _ = canvas
+                    .window_mut()
+                    .set_title(&format!("CHIP-8 Emulator - halted: {}", err));
+                fault = Some(err);
+            }
+        }
+
+        if let Some(audio) = audio.as_mut() {
+            audio.update(chip8);
+        }
+        draw_screen(chip8, &mut canvas, &palette, (window_width, window_height));
+        if show_inspector {
+            let area = Rect::new(window_width as i32, 0, panel_width, panel_height);
+            overlay::draw_panel(&mut canvas, area, &inspector.lines(chip8), text_scale);
+        }
+        canvas.present();
+
+        // Sleep until the next 60 Hz frame; if the host fell behind, don't try to catch up
+        next_frame += FRAME_DURATION;
+        let now = Instant::now();
+        if next_frame > now {
+            ::std::thread::sleep(next_frame - now);
+        } else {
+            next_frame = now;
+        }
+    }
+
+    Ok(())
+}
+
+// Save slot N for a ROM lives next to it as <rom>.stateN
+fn save_slot_path(options: &Options, slot: u8) -> PathBuf {
+    let mut path = options.rom_path.clone().into_os_string();
+    path.push(format!(".state{}", slot));
+    PathBuf::from(path)
+}
+
+fn load_state(chip8: &mut Chip8, path: &Path) -> Result<(), Box<dyn Error>> {
+    let data = fs::read(path)?;
+    chip8.load_state(&data)?;
+    Ok(())
+}
+
+// Colors for each combination of the two XO-CHIP bitplanes: off, plane 1, plane 2, both
+fn palette(options: &Options) -> [Color; 4] {
+    let [br, bg, bb] = options.background;
+    let [fr, fg, fb] = options.foreground;
+    [
+        Color::RGB(br, bg, bb),
+        Color::RGB(fr, fg, fb),
+        Color::RGB(170, 170, 170),
+        Color::RGB(85, 85, 85),
+    ]
+}
+
+fn draw_screen(
+    chip8: &Chip8,
+    canvas: &mut Canvas<Window>,
+    palette: &[Color; 4],
+    (window_width, window_height): (u32, u32),
+) {
+    canvas.set_draw_color(palette[0]);
+    canvas.clear();
+
+    // Hi-res frames are drawn into the same window with smaller pixels. Each pixel spans from
+    // its own edge to the next one's, so odd scales give pixels of alternating sizes rather
+    // than leaving part of the window unused
+    let width = chip8.screen_width();
+    let height = chip8.screen_height();
+    let edge =
+        |index: usize, count: usize, size: u32| (index as u64 * size as u64 / count as u64) as i32;
+
+    for (i, &pixel) in chip8.screen.iter().enumerate() {
+        if pixel != 0 {
+            let (column, row) = (i % width, i / width);
+            let x = edge(column, width, window_width);
+            let y = edge(row, height, window_height);
+            let pixel_width = edge(column + 1, width, window_width) - x;
+            let pixel_height = edge(row + 1, height, window_height) - y;
+
+            canvas.set_draw_color(palette[(pixel & 0b11) as usize]);
+            let _ = canvas.fill_rect(Rect::new(x, y, pixel_width as u32, pixel_height as u32));
+        }
+    }
+}
+
+// The in-app rebinding: each CHIP-8 key in keypad order collects the host keys pressed for
+// it until Enter moves on, and Enter alone keeps its bindings
+struct Rebind {
+    // The ROM whose overrides are being set, None for the global layout
+    rom: Option<String>,
+    position: usize,
+    bindings: Bindings,
+}
+
+impl Rebind {
+    fn new(rom: Option<String>) -> Self {
+        Rebind {
+            rom,
+            position: 0,
+            bindings: Bindings::new(),
+        }
+    }
+
+    fn key(&self) -> u8 {
+        KEYPAD[self.position / 4][self.position % 4]
+    }
+
+    fn press(&mut self, host_key: String) {
+        // A host key presses one CHIP-8 key, so a later press takes it from an earlier one
+        for names in self.bindings.values_mut() {
+            names.retain(|name| !name.eq_ignore_ascii_case(&host_key));
+        }
+        self.bindings.entry(self.key()).or_default().push(host_key);
+    }
+
+    // Moves to the next key, returning true once all 16 are done
+    fn next(&mut self) -> bool {
+        self.position += 1;
+        self.position == 16
+    }
+
+    // Adds the new bindings to the config and writes it out
+    fn apply(self, config: &mut KeyConfig, path: Option<&Path>) {
+        let bindings = match self.rom {
+            Some(rom) => config.roms.entry(rom).or_default(),
+            None => &mut config.global,
+        };
+        bindings.extend(self.bindings);
+        let Some(path) = path else {
+            println!("Key bindings changed for this session");
+            return;
+        };
+        match save_key_config(config, path) {
+            Ok(()) => println!("Saved key bindings to {}", path.display()),
+            Err(err) => eprintln!("Failed to save key bindings to {}: {}", path.display(), err),
+        }
+    }
+
+    fn prompt(&self, keymap: &Keymap) -> String {
+        let scope = match &self.rom {
+            Some(rom) => format!("{} ", rom),
+            None => String::new(),
+        };
+        match self.bindings.get(&self.key()) {
+            Some(names) => format!(
+                "{}key {:X}: {} (Enter: next, Esc: cancel)",
+                scope,
+                self.key(),
+                names.join(", ")
+            ),
+            None => format!(
+                "press {}keys for {:X}, Enter keeps {} (Esc: cancel)",
+                scope,
+                self.key(),
+                keymap.host_keys(self.key()).join(", ")
+            ),
+        }
+    }
+}
+
+// Keys the frontend itself responds to, which can't press CHIP-8 keys
+fn is_hotkey(keycode: Keycode) -> bool {
+    matches!(
+        keycode,
+        Keycode::Escape
+            | Keycode::Return
+            | Keycode::KpEnter
+            | Keycode::M
+            | Keycode::Tab
+            | Keycode::Backspace
+            | Keycode::PageUp
+            | Keycode::PageDown
+            | Keycode::Home
+            | Keycode::F1
+            | Keycode::F2
+            | Keycode::F3
+            | Keycode::F4
+            | Keycode::F5
+    )
+}
+
+// $XDG_CONFIG_HOME/chip_8/keys.toml, falling back to ~/.config
+fn default_keymap_path() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config.join("chip_8").join("keys.toml"))
+}
+
+// A missing file just means nothing has been rebound yet
+fn load_key_config(path: &Path) -> Result<KeyConfig, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+        Ok(text) => {
+            KeyConfig::parse(&text).map_err(|e| format!("{}: {}", path.display(), e).into())
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(KeyConfig::default()),
+        Err(err) => Err(format!("Failed to read file: {} - Error: {}", path.display(), err).into()),
+    }
+}
+
+fn save_key_config(config: &KeyConfig, path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, config.to_toml())
+}
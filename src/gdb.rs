@@ -0,0 +1,740 @@
+//! A GDB remote serial protocol server, so standard debuggers can attach to a running machine.
+//!
+//! [`GdbServer`] listens on a TCP port and is driven once per frame through
+//! [`GdbServer::run_frame`] in place of [`Chip8::run_frame`], so it never blocks the frontend.
+//! While no debugger is attached the machine runs as usual. Attaching stops it; the debugger
+//! can then read and write registers and memory, set software breakpoints, single-step and
+//! continue. Breakpoints are checked before each instruction rather than patched into memory,
+//! so ROMs that read their own code see it unchanged.
+//!
+//! Registers, in `g` packet order, are V0-VF (8 bits), I and PC (16 bits), then SP, DT and ST
+//! (8 bits), all little-endian. The target description served through `qXfer:features:read`
+//! names them for the client. The address space is [`Chip8::memory`] from address 0.
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// Register numbers after V0-VF
+const REGISTER_I: usize = 16;
+const REGISTER_PC: usize = 17;
+const REGISTER_SP: usize = 18;
+const REGISTER_DT: usize = 19;
+const REGISTER_ST: usize = 20;
+const REGISTER_COUNT: usize = 21;
+
+/// A GDB server for one [`Chip8`], see the [module docs](self).
+pub struct GdbServer {
+    listener: TcpListener,
+    client: Option<Client>,
+    breakpoints: BTreeSet<u16>,
+    // Set while the attached debugger has the machine stopped
+    halted: bool,
+    // Lets a continue start from a breakpoint without stopping on it again immediately
+    skip_breakpoint: bool,
+    // What the last stop was reported as, for '?'
+    last_stop: String,
+    killed: bool,
+}
+
+impl GdbServer {
+    /// Listens for a debugger on `address`, e.g. `127.0.0.1:1234`.
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(GdbServer {
+            listener,
+            client: None,
+            breakpoints: BTreeSet::new(),
+            halted: false,
+            skip_breakpoint: false,
+            last_stop: stop_signal(SIGTRAP),
+            killed: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Whether the debugger asked for the machine to be killed; the frontend should quit.
+    pub fn is_killed(&self) -> bool {
+        self.killed
+    }
+
+    /// Serves whatever the debugger sent, then runs one 60 Hz frame the way
+    /// [`Chip8::run_frame`] does unless the debugger has the machine stopped. Breakpoint hits
+    /// and faults stop the machine and are reported to the debugger; with no debugger attached
+    /// faults are returned instead.
+    pub fn run_frame(
+        &mut self,
+        chip8: &mut Chip8,
+        instructions_per_frame: usize,
+    ) -> Result<(), Chip8Error> {
+        self.accept();
+        self.serve(chip8);
+        if self.halted || self.killed {
+            return Ok(());
+        }
+        for _ in 0..instructions_per_frame {
+            if chip8.exited {
+                break;
+            }
+            let pc = chip8.program_counter;
+            let skip = std::mem::take(&mut self.skip_breakpoint);
+            if !skip && self.client.is_some() && self.breakpoints.contains(&pc) {
+                self.stop(stop_signal(SIGTRAP));
+                return Ok(());
+            }
+            if let Err(err) = chip8.emulate_cycle() {
+                if self.client.is_none() {
+                    return Err(err);
+                }
+                self.stop(stop_signal(fault_signal(&err)));
+                return Ok(());
+            }
+            if chip8.exited && self.client.is_some() {
+                self.stop("W00".to_string());
+                return Ok(());
+            }
+        }
+        chip8.tick_timers();
+        Ok(())
+    }
+
+    fn accept(&mut self) {
+        if self.client.is_some() {
+            return;
+        }
+        let Ok((stream, _)) = self.listener.accept() else {
+            return;
+        };
+        // Packets are small and latency matters more than throughput
+        let _ = stream.set_nodelay(true);
+        if stream.set_nonblocking(false).is_ok() {
+            self.client = Some(Client {
+                stream,
+                input: Vec::new(),
+                no_ack: false,
+            });
+            self.halted = true;
+            self.last_stop = stop_signal(SIGTRAP);
+        }
+    }
+
+    // Drops the debugger and lets the machine run on
+    fn detach(&mut self) {
+        self.client = None;
+        self.breakpoints.clear();
+        self.halted = false;
+    }
+
+    // Stops the machine and tells the debugger why
+    fn stop(&mut self, reply: String) {
+        self.halted = true;
+        self.last_stop = reply.clone();
+        self.send(&reply);
+    }
+
+    fn send(&mut self, reply: &str) {
+        let sent = match self.client.as_mut() {
+            Some(client) => client.send(reply),
+            None => return,
+        };
+        if sent.is_err() {
+            self.detach();
+        }
+    }
+
+    fn serve(&mut self, chip8: &mut Chip8) {
+        let Some(client) = self.client.as_mut() else {
+            return;
+        };
+        let events = match client.receive() {
+            Ok(events) => events,
+            Err(_) => {
+                self.detach();
+                return;
+            }
+        };
+        for event in events {
+            match event {
+                Event::Interrupt if !self.halted => self.stop(stop_signal(SIGINT)),
+                Event::Interrupt => {}
+                Event::Closed => self.detach(),
+                Event::Packet(packet) => {
+                    let packet = String::from_utf8_lossy(&packet).into_owned();
+                    if let Some(reply) = self.handle(&packet, chip8) {
+                        self.send(&reply);
+                    }
+                    // Acks stop after the reply to the packet that turned them off
+                    if packet == "QStartNoAckMode" {
+                        if let Some(client) = self.client.as_mut() {
+                            client.no_ack = true;
+                        }
+                    }
+                }
+            }
+            if self.client.is_none() {
+                return;
+            }
+        }
+    }
+
+    // Carries out a packet; None when the reply comes later (continue) or never (kill)
+    fn handle(&mut self, packet: &str, chip8: &mut Chip8) -> Option<String> {
+        let reply = match packet {
+            "?" => self.last_stop.clone(),
+            "g" => {
+                let mut hex = String::new();
+                for register in 0..REGISTER_COUNT {
+                    hex.push_str(&encode_hex(&read_register(chip8, register)));
+                }
+                hex
+            }
+            "c" => return self.resume(),
+            "s" => self.step(chip8),
+            "D" => {
+                self.send("OK");
+                self.detach();
+                return None;
+            }
+            "k" | "vKill;1" => {
+                // Only vKill expects a reply
+                if packet != "k" {
+                    self.send("OK");
+                }
+                self.killed = true;
+                self.client = None;
+                return None;
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "QStartNoAckMode" => "OK".to_string(),
+            "vCont?" => "vCont;c;C;s;S".to_string(),
+            _ if packet.starts_with("qSupported") => {
+                "PacketSize=1000;QStartNoAckMode+;qXfer:features:read+".to_string()
+            }
+            _ if packet.starts_with("qXfer:features:read:target.xml:") => {
+                read_chunk(&target_description(), &packet[31..])
+            }
+            _ if packet.starts_with('H') => "OK".to_string(),
+            _ if packet.starts_with("vCont;") => match packet.as_bytes().get(6) {
+                Some(b's' | b'S') => self.step(chip8),
+                Some(b'c' | b'C') => return self.resume(),
+                _ => String::new(),
+            },
+            _ if packet.starts_with('G') => {
+                write_registers(chip8, &packet[1..]).unwrap_or_else(|| "E01".to_string())
+            }
+            _ if packet.starts_with('p') => usize::from_str_radix(&packet[1..], 16)
+                .ok()
+                .filter(|&register| register < REGISTER_COUNT)
+                .map(|register| encode_hex(&read_register(chip8, register)))
+                .unwrap_or_else(|| "E01".to_string()),
+            _ if packet.starts_with('P') => write_register(chip8, &packet[1..])
+                .map(|()| "OK".to_string())
+                .unwrap_or_else(|| "E01".to_string()),
+            _ if packet.starts_with('m') => {
+                read_memory(chip8, &packet[1..]).unwrap_or_else(|| "E01".to_string())
+            }
+            _ if packet.starts_with('M') => write_memory(chip8, &packet[1..])
+                .map(|()| "OK".to_string())
+                .unwrap_or_else(|| "E01".to_string()),
+            _ if packet.starts_with("Z0,") || packet.starts_with("Z1,") => {
+                match breakpoint_address(packet) {
+                    Some(address) => {
+                        self.breakpoints.insert(address);
+                        "OK".to_string()
+                    }
+                    None => "E01".to_string(),
+                }
+            }
+            _ if packet.starts_with("z0,") || packet.starts_with("z1,") => {
+                match breakpoint_address(packet) {
+                    Some(address) => {
+                        self.breakpoints.remove(&address);
+                        "OK".to_string()
+                    }
+                    None => "E01".to_string(),
+                }
+            }
+            // An empty reply tells the debugger the packet isn't supported
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn resume(&mut self) -> Option<String> {
+        self.halted = false;
+        self.skip_breakpoint = true;
+        None
+    }
+
+    // Executes one instruction and returns the stop reply for it. Timers don't tick, so a
+    // stepped machine only changes by what the instruction does
+    fn step(&mut self, chip8: &mut Chip8) -> String {
+        let reply = if chip8.exited {
+            "W00".to_string()
+        } else {
+            match chip8.emulate_cycle() {
+                Ok(()) if chip8.exited => "W00".to_string(),
+                Ok(()) => stop_signal(SIGTRAP),
+                Err(err) => stop_signal(fault_signal(&err)),
+            }
+        };
+        self.halted = true;
+        self.last_stop = reply.clone();
+        reply
+    }
+}
+
+fn stop_signal(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn fault_signal(err: &Chip8Error) -> u8 {
+    match err {
+        Chip8Error::UnknownOpcode { .. } => SIGILL,
+        _ => SIGSEGV,
+    }
+}
+
+enum Event {
+    Packet(Vec<u8>),
+    Interrupt,
+    Closed,
+}
+
+struct Client {
+    stream: TcpStream,
+    // Bytes received but not yet parsed into events
+    input: Vec<u8>,
+    no_ack: bool,
+}
+
+impl Client {
+    // Reads whatever has arrived without waiting and splits it into events. A debugger that
+    // hangs up right after its last packet still has that packet handled; the hangup is the
+    // final Event::Closed
+    fn receive(&mut self) -> io::Result<Vec<Event>> {
+        self.stream.set_nonblocking(true)?;
+        let mut buffer = [0; 4096];
+        let mut closed = false;
+        let read = loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => {
+                    closed = true;
+                    break Ok(());
+                }
+                Ok(count) => self.input.extend_from_slice(&buffer[..count]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => break Err(err),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        read?;
+
+        let mut events = Vec::new();
+        loop {
+            match self.input.first() {
+                None => break,
+                Some(0x03) => {
+                    self.input.remove(0);
+                    events.push(Event::Interrupt);
+                }
+                Some(b'$') => {
+                    let Some(end) = self.input.iter().position(|&byte| byte == b'#') else {
+                        break;
+                    };
+                    if self.input.len() < end + 3 {
+                        break;
+                    }
+                    let frame: Vec<u8> = self.input.drain(..end + 3).collect();
+                    let data = &frame[1..end];
+                    let checksum = std::str::from_utf8(&frame[end + 1..])
+                        .ok()
+                        .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+                    let valid = checksum == Some(checksum_of(data));
+                    if !self.no_ack {
+                        // A lost ack shows up as a failed reply soon enough
+                        let _ = self.stream.write_all(if valid { b"+" } else { b"-" });
+                    }
+                    if valid {
+                        events.push(Event::Packet(unescape(data)));
+                    }
+                }
+                // Acks and line noise
+                Some(_) => {
+                    self.input.remove(0);
+                }
+            }
+        }
+        if closed {
+            events.push(Event::Closed);
+        }
+        Ok(events)
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let mut body = Vec::with_capacity(data.len());
+        for &byte in data.as_bytes() {
+            if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+                body.extend_from_slice(&[b'}', byte ^ 0x20]);
+            } else {
+                body.push(byte);
+            }
+        }
+        let mut packet = Vec::with_capacity(body.len() + 4);
+        packet.push(b'$');
+        packet.extend_from_slice(&body);
+        packet.extend_from_slice(format!("#{:02x}", checksum_of(&body)).as_bytes());
+        self.stream.write_all(&packet)
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+// Undoes the '}' escaping of '$', '#', '}' and '*' in packet data
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'}' => out.extend(bytes.next().map(|&escaped| escaped ^ 0x20)),
+            _ => out.push(byte),
+        }
+    }
+    out
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn register_size(register: usize) -> usize {
+    match register {
+        REGISTER_I | REGISTER_PC => 2,
+        _ => 1,
+    }
+}
+
+fn read_register(chip8: &Chip8, register: usize) -> Vec<u8> {
+    match register {
+        0..=15 => vec![chip8.registers[register]],
+        REGISTER_I => chip8.index_register.to_le_bytes().to_vec(),
+        REGISTER_PC => chip8.program_counter.to_le_bytes().to_vec(),
+        REGISTER_SP => vec![chip8.stack_pointer],
+        REGISTER_DT => vec![chip8.delay_timer],
+        _ => vec![chip8.sound_timer],
+    }
+}
+
+// Fails without changing anything if the value doesn't fit the register
+fn set_register(chip8: &mut Chip8, register: usize, bytes: &[u8]) -> Option<()> {
+    if bytes.len() != register_size(register) {
+        return None;
+    }
+    match register {
+        0..=15 => chip8.registers[register] = bytes[0],
+        REGISTER_I => chip8.index_register = u16::from_le_bytes([bytes[0], bytes[1]]),
+        REGISTER_PC => chip8.program_counter = u16::from_le_bytes([bytes[0], bytes[1]]),
+        REGISTER_SP if bytes[0] as usize <= chip8.stack.len() => chip8.stack_pointer = bytes[0],
+        REGISTER_DT => chip8.delay_timer = bytes[0],
+        REGISTER_ST => chip8.sound_timer = bytes[0],
+        _ => return None,
+    }
+    Some(())
+}
+
+// P n=v
+fn write_register(chip8: &mut Chip8, args: &str) -> Option<()> {
+    let (register, value) = args.split_once('=')?;
+    let register = usize::from_str_radix(register, 16)
+        .ok()
+        .filter(|&register| register < REGISTER_COUNT)?;
+    set_register(chip8, register, &decode_hex(value)?)
+}
+
+// G with every register in order
+fn write_registers(chip8: &mut Chip8, hex: &str) -> Option<String> {
+    let bytes = decode_hex(hex)?;
+    let total: usize = (0..REGISTER_COUNT).map(register_size).sum();
+    // SP is the only register with invalid values; check it first so a bad one changes nothing
+    let sp_offset: usize = (0..REGISTER_SP).map(register_size).sum();
+    if bytes.len() != total || bytes[sp_offset] as usize > chip8.stack.len() {
+        return None;
+    }
+    let mut offset = 0;
+    for register in 0..REGISTER_COUNT {
+        let size = register_size(register);
+        set_register(chip8, register, &bytes[offset..offset + size])?;
+        offset += size;
+    }
+    Some("OK".to_string())
+}
+
+// addr,length
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let (address, length) = args.split_once(',')?;
+    Some((
+        usize::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+// m addr,length; reads stop at the end of memory
+fn read_memory(chip8: &Chip8, args: &str) -> Option<String> {
+    let (address, length) = parse_range(args)?;
+    if address >= chip8.memory.len() {
+        return None;
+    }
+    let end = address.saturating_add(length).min(chip8.memory.len());
+    Some(encode_hex(&chip8.memory[address..end]))
+}
+
+// M addr,length:bytes
+fn write_memory(chip8: &mut Chip8, args: &str) -> Option<()> {
+    let (range, hex) = args.split_once(':')?;
+    let (address, length) = parse_range(range)?;
+    let bytes = decode_hex(hex).filter(|bytes| bytes.len() == length)?;
+    chip8
+        .memory
+        .get_mut(address..address.checked_add(length)?)?
+        .copy_from_slice(&bytes);
+    Some(())
+}
+
+// Z0,addr,kind and friends
+fn breakpoint_address(packet: &str) -> Option<u16> {
+    let address = packet[3..].split(',').next()?;
+    u16::from_str_radix(address, 16).ok()
+}
+
+// offset,length of qXfer reads: 'm' when more follows, 'l' for the last chunk
+fn read_chunk(document: &str, args: &str) -> String {
+    let Some((offset, length)) = parse_range(args) else {
+        return "E01".to_string();
+    };
+    let start = offset.min(document.len());
+    let end = start.saturating_add(length).min(document.len());
+    let marker = if end < document.len() { 'm' } else { 'l' };
+    format!("{}{}", marker, &document[start..end])
+}
+
+fn target_description() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\"><feature name=\"org.chip8.core\">",
+    );
+    for x in 0..16 {
+        let _ = write!(
+            xml,
+            "<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\" regnum=\"{}\"/>",
+            x, x
+        );
+    }
+    xml.push_str(
+        "<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>\
+         <reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\
+         <reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>\
+         <reg name=\"dt\" bitsize=\"8\" type=\"uint8\"/>\
+         <reg name=\"st\" bitsize=\"8\" type=\"uint8\"/>\
+         </feature></target>",
+    );
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    const PROGRAM: [u8; 10] = [
+        0x60, 0x2A, // LD V0, 0x2A
+        0xA3, 0x00, // LD I, 0x300
+        0x70, 0x01, // ADD V0, 1
+        0x12, 0x04, // JP 0x204
+        0x00, 0x00,
+    ];
+
+    // Runs a machine with a server in the background until the debugger kills it
+    fn start() -> (TestClient, JoinHandle<Chip8>) {
+        let mut server = GdbServer::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap();
+        let machine = thread::spawn(move || {
+            let mut chip8 = Chip8::new(Quirks::default());
+            chip8.load_program(&PROGRAM).unwrap();
+            while !server.is_killed() {
+                server.run_frame(&mut chip8, 10).unwrap();
+                thread::sleep(Duration::from_millis(1));
+            }
+            chip8
+        });
+        (TestClient::connect(address), machine)
+    }
+
+    // Speaks the protocol like a debugger would
+    struct TestClient {
+        stream: TcpStream,
+        no_ack: bool,
+    }
+
+    impl TestClient {
+        fn connect(address: SocketAddr) -> TestClient {
+            let stream = TcpStream::connect(address).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            TestClient {
+                stream,
+                no_ack: false,
+            }
+        }
+
+        fn send(&mut self, packet: &str) {
+            let frame = format!("${}#{:02x}", packet, checksum_of(packet.as_bytes()));
+            self.stream.write_all(frame.as_bytes()).unwrap();
+        }
+
+        fn byte(&mut self) -> u8 {
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte).unwrap();
+            byte[0]
+        }
+
+        fn reply(&mut self) -> String {
+            while self.byte() != b'$' {}
+            let mut data = Vec::new();
+            loop {
+                match self.byte() {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let checksum = [self.byte(), self.byte()];
+            let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16);
+            assert_eq!(checksum, Ok(checksum_of(&data)));
+            if !self.no_ack {
+                self.stream.write_all(b"+").unwrap();
+            }
+            String::from_utf8(unescape(&data)).unwrap()
+        }
+
+        fn request(&mut self, packet: &str) -> String {
+            self.send(packet);
+            if !self.no_ack {
+                assert_eq!(self.byte(), b'+');
+            }
+            self.reply()
+        }
+
+        fn pc(&mut self) -> String {
+            self.request("p11")
+        }
+    }
+
+    #[test]
+    fn reads_and_writes_registers_and_memory() {
+        let (mut client, machine) = start();
+        assert!(client
+            .request("qSupported:swbreak+")
+            .contains("qXfer:features:read+"));
+        assert_eq!(client.request("?"), "S05");
+
+        let registers = client.request("g");
+        assert_eq!(registers.len(), REGISTER_COUNT * 2 + 4);
+        assert_eq!(client.request("P10=0003"), "OK");
+        assert_eq!(client.request("p10"), "0003");
+        assert_eq!(client.request("P12=11"), "E01");
+        assert_eq!(client.request("P3=7f"), "OK");
+        assert_eq!(&client.request("g")[6..8], "7f");
+
+        assert_eq!(client.request("m200,4"), "602aa300");
+        assert_eq!(client.request("M300,2:abcd"), "OK");
+        assert_eq!(client.request("m300,2"), "abcd");
+        assert_eq!(client.request("mffe,10"), "0000");
+        assert_eq!(client.request("m1000,1"), "E01");
+        assert_eq!(client.request("Z2,300,1"), "");
+
+        let xml = client.request("qXfer:features:read:target.xml:0,fff");
+        assert!(xml.starts_with("l<?xml"));
+        assert!(xml.contains("<reg name=\"pc\" bitsize=\"16\""));
+
+        client.send("k");
+        let chip8 = machine.join().unwrap();
+        assert_eq!(chip8.registers[3], 0x7F);
+        assert_eq!(chip8.index_register, 0x300);
+        assert_eq!(&chip8.memory[0x300..0x302], &[0xAB, 0xCD]);
+    }
+
+    #[test]
+    fn breakpoints_steps_and_interrupts() {
+        let (mut client, machine) = start();
+        assert_eq!(client.request("QStartNoAckMode"), "OK");
+        client.no_ack = true;
+        assert_eq!(client.request("Z0,204,2"), "OK");
+
+        client.send("c");
+        assert_eq!(client.reply(), "S05");
+        assert_eq!(client.pc(), "0402");
+        assert_eq!(client.request("p0"), "2a");
+        // Continuing from the breakpoint runs the loop once and hits it again
+        client.send("c");
+        assert_eq!(client.reply(), "S05");
+        assert_eq!(client.request("p0"), "2b");
+        assert_eq!(client.request("vCont;s:1"), "S05");
+        assert_eq!(client.pc(), "0602");
+
+        assert_eq!(client.request("z0,204,2"), "OK");
+        client.send("c");
+        thread::sleep(Duration::from_millis(50));
+        client.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(client.reply(), "S02");
+        let v0 = client.request("p0");
+
+        // Detached, the machine runs on until a new debugger attaches
+        assert_eq!(client.request("D"), "OK");
+        let address = client.stream.peer_addr().unwrap();
+        drop(client);
+        let mut client = TestClient::connect(address);
+        assert_eq!(client.request("?"), "S05");
+        client.send("k");
+        let chip8 = machine.join().unwrap();
+        assert_ne!(format!("{:02x}", chip8.registers[0]), v0);
+    }
+}
@@ -0,0 +1,223 @@
+//! Runs a [`Chip8`] without any frontend, for tests and CI.
+//!
+//! Key input comes from a [`KeyScript`], a list of presses and releases at given frames:
+//!
+//! ```text
+//! # frame  action  key
+//! 30       down    5
+//! 34       up      5
+//! ```
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use std::error::Error;
+use std::fmt;
+
+/// How long a headless run lasts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunLimit {
+    /// Run this many 60 Hz frames
+    Frames(u64),
+    /// Run this many instructions; timers still tick once per full frame
+    Cycles(u64),
+}
+
+/// A key press or release applied before the given frame runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// Scripted keypad input, sorted by frame.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyScript {
+    events: Vec<KeyEvent>,
+}
+
+/// A line of a key script that couldn't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for KeyScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for KeyScriptError {}
+
+impl KeyScript {
+    pub fn new(mut events: Vec<KeyEvent>) -> Self {
+        events.sort_by_key(|event| event.frame);
+        KeyScript { events }
+    }
+
+    /// Parses one `FRAME down|up KEY` event per line. Keys are hex digits, `#` starts a
+    /// comment.
+    pub fn parse(text: &str) -> Result<KeyScript, KeyScriptError> {
+        let mut events = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| KeyScriptError {
+                line: index + 1,
+                message,
+            };
+            let code = line.split('#').next().unwrap_or_default();
+            let words: Vec<&str> = code.split_whitespace().collect();
+            let (frame, action, key) = match words.as_slice() {
+                [] => continue,
+                [frame, action, key] => (frame, action, key),
+                _ => return Err(error("expected 'FRAME down|up KEY'".to_string())),
+            };
+            let frame = frame
+                .parse()
+                .map_err(|_| error(format!("invalid frame '{}'", frame)))?;
+            let pressed = match *action {
+                "down" => true,
+                "up" => false,
+                _ => return Err(error(format!("expected down or up, found '{}'", action))),
+            };
+            let key = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|&key| key < 16)
+                .ok_or_else(|| error(format!("invalid key '{}', expected 0-F", key)))?;
+            events.push(KeyEvent {
+                frame,
+                key,
+                pressed,
+            });
+        }
+        Ok(KeyScript::new(events))
+    }
+
+    pub fn events(&self) -> &[KeyEvent] {
+        &self.events
+    }
+
+    /// Applies every event scheduled for `frame` to the keypad.
+    pub fn apply(&self, chip8: &mut Chip8, frame: u64) {
+        for event in self.events.iter().filter(|event| event.frame == frame) {
+            chip8.keys[event.key as usize] = event.pressed as u8;
+        }
+    }
+}
+
+/// Runs `chip8` until `limit` is reached or the program exits, feeding it `keys`.
+pub fn run(
+    chip8: &mut Chip8,
+    instructions_per_frame: usize,
+    limit: RunLimit,
+    keys: &KeyScript,
+) -> Result<(), Chip8Error> {
+    let mut cycles_left = match limit {
+        RunLimit::Frames(_) => u64::MAX,
+        RunLimit::Cycles(cycles) => cycles,
+    };
+    let frames = match limit {
+        RunLimit::Frames(frames) => frames,
+        RunLimit::Cycles(_) => u64::MAX,
+    };
+
+    for frame in 0..frames {
+        if chip8.exited || cycles_left == 0 {
+            break;
+        }
+        keys.apply(chip8, frame);
+        let cycles = (instructions_per_frame as u64).min(cycles_left);
+        for _ in 0..cycles {
+            chip8.emulate_cycle()?;
+        }
+        cycles_left -= cycles;
+        if cycles == instructions_per_frame as u64 {
+            chip8.tick_timers();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_scripts_in_frame_order() {
+        let script = KeyScript::parse(
+            "# frame action key\n\
+             34  up    5\n\
+             \n\
+             30  down  5   # hold 5\n\
+             30  down  f\n",
+        )
+        .unwrap();
+        let event = |frame, key, pressed| KeyEvent {
+            frame,
+            key,
+            pressed,
+        };
+        assert_eq!(
+            script.events(),
+            [
+                event(30, 0x5, true),
+                event(30, 0xF, true),
+                event(34, 0x5, false)
+            ]
+        );
+        assert_eq!(KeyScript::parse("# nothing\n"), Ok(KeyScript::default()));
+    }
+
+    #[test]
+    fn reports_the_bad_line() {
+        let error = |text: &str| KeyScript::parse(text).unwrap_err();
+        assert_eq!(
+            error("1 down 5\n2 down"),
+            KeyScriptError {
+                line: 2,
+                message: "expected 'FRAME down|up KEY'".to_string()
+            }
+        );
+        assert_eq!(error("1 down 5 6").line, 1);
+        assert_eq!(error("-1 down 5").message, "invalid frame '-1'");
+        assert_eq!(
+            error("\n\n1 press 5").to_string(),
+            "line 3: expected down or up, found 'press'"
+        );
+        assert_eq!(error("1 up 10").message, "invalid key '10', expected 0-F");
+        assert_eq!(error("1 up G").message, "invalid key 'G', expected 0-F");
+    }
+
+    #[test]
+    fn runs_to_the_limit_with_scripted_keys() {
+        // Waits for a key, stores it in V1, then adds 1 to V2 forever
+        let program = [0xF10A, 0x7201, 0x1202];
+        let script = KeyScript::parse("3 down 7\n4 up 7").unwrap();
+
+        let mut chip8 = Chip8::builder().instructions(&program).build();
+        run(&mut chip8, 10, RunLimit::Frames(5), &script).unwrap();
+        // The key goes down before frame 3 runs, so its first instruction gets it and the
+        // other 19 of frames 3 and 4 alternate between the add and the jump
+        assert_eq!(chip8.registers[1], 0x7);
+        assert_eq!(chip8.registers[2], 10);
+        assert_eq!(chip8.keys[0x7], 0);
+
+        // Partial frames don't tick the timers
+        let mut chip8 = Chip8::builder()
+            .instructions(&[0x7201, 0x1200])
+            .delay_timer(10)
+            .build();
+        run(&mut chip8, 10, RunLimit::Cycles(25), &KeyScript::default()).unwrap();
+        assert_eq!(chip8.registers[2], 13);
+        assert_eq!(chip8.delay_timer, 8);
+    }
+
+    #[test]
+    fn stops_when_the_program_exits() {
+        let mut chip8 = Chip8::builder().instructions(&[0x7001, 0x00FD]).build();
+        run(&mut chip8, 10, RunLimit::Frames(100), &KeyScript::default()).unwrap();
+        assert!(chip8.exited);
+        assert_eq!(chip8.registers[0], 1);
+    }
+}
@@ -0,0 +1,318 @@
+//! The machine state viewer the SDL frontend shows next to the game.
+//!
+//! [`Inspector::lines`] lays the panel out as styled text: PC, I, SP and the timers, the
+//! registers, the call stack, the keypad, a disassembly around PC and a hex dump of memory
+//! with the bytes at PC and I highlighted. Keeping it text means any font can draw it and it
+//! can be tested without a window.
+
+use crate::chip8::Chip8;
+use crate::instruction::Instruction;
+use crate::keymap::KEYPAD;
+use crate::trace;
+
+/// Width of the panel in characters; no line is longer.
+pub const PANEL_COLUMNS: usize = 32;
+// Bytes per hex dump row
+const ROW_BYTES: usize = 8;
+
+/// How a piece of panel text is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Normal,
+    /// Headings and addresses
+    Label,
+    /// The instruction and memory at PC
+    ProgramCounter,
+    /// The memory at I
+    Index,
+    /// Keys being held
+    Pressed,
+}
+
+/// A run of text in one style.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+/// One line of the panel.
+pub type Line = Vec<Span>;
+
+fn span(text: impl Into<String>, style: Style) -> Span {
+    Span {
+        text: text.into(),
+        style,
+    }
+}
+
+/// Settings for the panel, and where its hex dump is scrolled to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inspector {
+    /// Instructions shown before and after the one at PC
+    pub disassembly_context: usize,
+    pub memory_rows: usize,
+    // First address of the hex dump, or None to keep PC in view
+    memory_start: Option<usize>,
+}
+
+impl Default for Inspector {
+    fn default() -> Self {
+        Inspector::new()
+    }
+}
+
+impl Inspector {
+    pub fn new() -> Self {
+        Inspector {
+            disassembly_context: 4,
+            memory_rows: 8,
+            memory_start: None,
+        }
+    }
+
+    /// Scrolls the hex dump by `rows`, which stops following PC.
+    pub fn scroll(&mut self, chip8: &Chip8, rows: isize) {
+        let start = self.memory_start(chip8) as isize + rows * ROW_BYTES as isize;
+        let last = chip8
+            .memory
+            .len()
+            .saturating_sub(self.memory_rows * ROW_BYTES);
+        self.memory_start = Some(start.clamp(0, last as isize) as usize);
+    }
+
+    /// Makes the hex dump follow PC again.
+    pub fn follow_pc(&mut self) {
+        self.memory_start = None;
+    }
+
+    fn memory_start(&self, chip8: &Chip8) -> usize {
+        let last = chip8
+            .memory
+            .len()
+            .saturating_sub(self.memory_rows * ROW_BYTES);
+        let start = self.memory_start.unwrap_or_else(|| {
+            // PC sits on the second row, so the bytes just before it are visible too
+            let row = chip8.program_counter as usize / ROW_BYTES;
+            row.saturating_sub(1) * ROW_BYTES
+        });
+        start.min(last)
+    }
+
+    /// Lays out the panel for the machine's current state. The number of lines only depends
+    /// on the inspector's settings, so the panel keeps its size as the machine runs.
+    pub fn lines(&self, chip8: &Chip8) -> Vec<Line> {
+        let mut lines = Vec::new();
+        lines.push(vec![
+            span("PC ", Style::Label),
+            span(
+                format!("{:04X}", chip8.program_counter),
+                Style::ProgramCounter,
+            ),
+            span(" I ", Style::Label),
+            span(format!("{:04X}", chip8.index_register), Style::Index),
+            span(" DT ", Style::Label),
+            span(format!("{:02X}", chip8.delay_timer), Style::Normal),
+            span(" ST ", Style::Label),
+            span(format!("{:02X}", chip8.sound_timer), Style::Normal),
+        ]);
+        for row in chip8.registers.chunks(4).enumerate() {
+            let (row, values) = row;
+            let mut line = Vec::new();
+            for (column, value) in values.iter().enumerate() {
+                let separator = if column == 0 { "" } else { " " };
+                line.push(span(
+                    format!("{}V{:X} ", separator, row * 4 + column),
+                    Style::Label,
+                ));
+                line.push(span(format!("{:02X}", value), Style::Normal));
+            }
+            lines.push(line);
+        }
+
+        // Five return addresses fit a line; the stack gets the lines a full one would need
+        let stack = &chip8.stack[..(chip8.stack_pointer as usize).min(chip8.stack.len())];
+        let stack_lines = chip8.stack.len().div_ceil(5);
+        for index in 0..stack_lines {
+            let label = if index == 0 {
+                format!("SP {:X} ", chip8.stack_pointer)
+            } else {
+                "     ".to_string()
+            };
+            let entries: Vec<String> = stack
+                .iter()
+                .skip(index * 5)
+                .take(5)
+                .map(|entry| format!("{:04X}", entry))
+                .collect();
+            lines.push(vec![
+                span(label, Style::Label),
+                span(entries.join(" "), Style::Normal),
+            ]);
+        }
+
+        for (row, keys) in KEYPAD.iter().enumerate() {
+            let label = if row == 0 { "KEYS " } else { "     " };
+            let mut line = vec![span(label, Style::Label)];
+            for &key in keys {
+                let style = if chip8.keys[key as usize] != 0 {
+                    Style::Pressed
+                } else {
+                    Style::Label
+                };
+                line.push(span(format!("{:X} ", key), style));
+            }
+            lines.push(line);
+        }
+
+        lines.push(Vec::new());
+        lines.extend(self.disassembly(chip8));
+        lines.push(Vec::new());
+        lines.extend(self.hex_dump(chip8));
+        lines
+    }
+
+    // Decodes forward from a few instructions before PC. Code isn't self-describing, so the
+    // lines before PC are a guess that assumes two-byte instructions
+    fn disassembly(&self, chip8: &Chip8) -> Vec<Line> {
+        let pc = chip8.program_counter as usize;
+        let mut address = pc.saturating_sub(self.disassembly_context * 2);
+        let mut lines = Vec::new();
+        for _ in 0..self.disassembly_context * 2 + 1 {
+            let opcode = chip8
+                .memory
+                .get(address..address + 2)
+                .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+            let Some(opcode) = opcode else {
+                lines.push(Vec::new());
+                continue;
+            };
+            let mnemonic = trace::mnemonic(chip8, address as u16, opcode);
+            let (marker, style) = if address == pc {
+                ("> ", Style::ProgramCounter)
+            } else {
+                ("  ", Style::Normal)
+            };
+            let mut text = format!("{:04X} {}", opcode, mnemonic);
+            text.truncate(PANEL_COLUMNS - 7);
+            lines.push(vec![
+                span(format!("{}{:04X} ", marker, address), Style::Label),
+                span(text, style),
+            ]);
+            address +=
+                Instruction::decode(opcode).map_or(2, |instruction| instruction.length()) as usize;
+        }
+        lines
+    }
+
+    fn hex_dump(&self, chip8: &Chip8) -> Vec<Line> {
+        let start = self.memory_start(chip8);
+        let pc = chip8.program_counter as usize;
+        let index = chip8.index_register as usize;
+        let mut lines = Vec::new();
+        for row in 0..self.memory_rows {
+            let address = start + row * ROW_BYTES;
+            let Some(bytes) = chip8.memory.get(address..address + ROW_BYTES) else {
+                lines.push(Vec::new());
+                continue;
+            };
+            let mut line = vec![span(format!("{:04X}:", address), Style::Label)];
+            for (offset, byte) in bytes.iter().enumerate() {
+                let address = address + offset;
+                let style = if address == pc || address == pc + 1 {
+                    Style::ProgramCounter
+                } else if address == index {
+                    Style::Index
+                } else {
+                    Style::Normal
+                };
+                line.push(span(" ", Style::Normal));
+                line.push(span(format!("{:02X}", byte), style));
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    fn text(line: &Line) -> String {
+        line.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    fn machine() -> Chip8 {
+        Chip8::builder()
+            .quirks(Quirks::SUPER_CHIP)
+            .instructions(&[0x6005, 0xA208, 0x2206, 0x1200])
+            .register(0xB, 0xBB)
+            .index_register(0x20A)
+            .call_stack(0x204)
+            .key(0xE)
+            .delay_timer(0x3C)
+            .build()
+    }
+
+    #[test]
+    fn shows_registers_stack_and_keys() {
+        let chip8 = machine();
+        let lines = Inspector::new().lines(&chip8);
+        assert_eq!(text(&lines[0]), "PC 0200 I 020A DT 3C ST 00");
+        assert_eq!(text(&lines[3]), "V8 00 V9 00 VA 00 VB BB");
+        assert_eq!(text(&lines[5]), "SP 1 0204");
+        assert_eq!(text(&lines[11]), "     7 8 9 E ");
+        let pressed: Vec<&str> = lines[11]
+            .iter()
+            .filter(|span| span.style == Style::Pressed)
+            .map(|span| span.text.as_str())
+            .collect();
+        assert_eq!(pressed, ["E "]);
+        assert!(lines.iter().all(|line| text(line).len() <= PANEL_COLUMNS));
+    }
+
+    #[test]
+    fn disassembles_around_pc_and_highlights_memory() {
+        let mut chip8 = machine();
+        chip8.program_counter = 0x204;
+        let lines = Inspector::new().lines(&chip8);
+        let disassembly: Vec<String> = lines[14..23].iter().map(text).collect();
+        assert_eq!(disassembly[4], "> 0204 2206 CALL 0x206");
+        assert_eq!(disassembly[3], "  0202 A208 LD I, 0x208");
+        assert_eq!(lines[14 + 4][1].style, Style::ProgramCounter);
+
+        let dump = &lines[24..];
+        assert_eq!(text(&dump[1]), "0200: 60 05 A2 08 22 06 12 00");
+        let highlighted: Vec<(&str, Style)> = dump[1]
+            .iter()
+            .chain(&dump[2])
+            .filter(|span| span.style != Style::Normal && span.style != Style::Label)
+            .map(|span| (span.text.as_str(), span.style))
+            .collect();
+        assert_eq!(
+            highlighted,
+            [
+                ("22", Style::ProgramCounter),
+                ("06", Style::ProgramCounter),
+                ("00", Style::Index)
+            ]
+        );
+    }
+
+    #[test]
+    fn hex_dump_scrolls_within_memory() {
+        let chip8 = machine();
+        let mut inspector = Inspector::new();
+        let lines_before = inspector.lines(&chip8).len();
+        inspector.scroll(&chip8, -1000);
+        assert!(text(&inspector.lines(&chip8)[24]).starts_with("0000:"));
+        inspector.scroll(&chip8, 10_000);
+        let lines = inspector.lines(&chip8);
+        assert!(text(lines.last().unwrap()).starts_with("0FF8:"));
+        assert_eq!(lines.len(), lines_before);
+        inspector.follow_pc();
+        assert!(text(&inspector.lines(&chip8)[24]).starts_with("01F8:"));
+    }
+}
@@ -0,0 +1,568 @@
+//! Typed CHIP-8 instructions, decoded once and shared by the interpreter and the tools.
+//!
+//! Variants are named after the Cowgod-style mnemonic and operands of each opcode, e.g.
+//! [`Instruction::SeVxByte`] is `SE Vx, byte` (3XNN). Decoding doesn't depend on quirks:
+//! the XO-CHIP instructions always decode, and [`Instruction::is_xo_chip`] tells whether a
+//! machine without the extensions should reject them.
+
+use crate::quirks::Quirks;
+use std::error::Error;
+use std::fmt;
+
+/// An opcode that isn't any CHIP-8, SUPER-CHIP or XO-CHIP instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown opcode {:04X}", self.opcode)
+    }
+}
+
+impl Error for DecodeError {}
+
+/// One decoded instruction. `x` and `y` are register numbers, `addr` is 12 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// CLS - 00E0
+    Cls,
+    /// RET - 00EE
+    Ret,
+    /// JP addr - 1NNN
+    Jp { addr: u16 },
+    /// CALL addr - 2NNN
+    Call { addr: u16 },
+    /// SE Vx, byte - 3XNN
+    SeVxByte { x: u8, byte: u8 },
+    /// SNE Vx, byte - 4XNN
+    SneVxByte { x: u8, byte: u8 },
+    /// SE Vx, Vy - 5XY0
+    SeVxVy { x: u8, y: u8 },
+    /// LD Vx, byte - 6XNN
+    LdVxByte { x: u8, byte: u8 },
+    /// ADD Vx, byte - 7XNN
+    AddVxByte { x: u8, byte: u8 },
+    /// LD Vx, Vy - 8XY0
+    LdVxVy { x: u8, y: u8 },
+    /// OR Vx, Vy - 8XY1
+    OrVxVy { x: u8, y: u8 },
+    /// AND Vx, Vy - 8XY2
+    AndVxVy { x: u8, y: u8 },
+    /// XOR Vx, Vy - 8XY3
+    XorVxVy { x: u8, y: u8 },
+    /// ADD Vx, Vy - 8XY4
+    AddVxVy { x: u8, y: u8 },
+    /// SUB Vx, Vy - 8XY5
+    SubVxVy { x: u8, y: u8 },
+    /// SHR Vx {, Vy} - 8XY6
+    ShrVx { x: u8, y: u8 },
+    /// SUBN Vx, Vy - 8XY7
+    SubnVxVy { x: u8, y: u8 },
+    /// SHL Vx {, Vy} - 8XYE
+    ShlVx { x: u8, y: u8 },
+    /// SNE Vx, Vy - 9XY0
+    SneVxVy { x: u8, y: u8 },
+    /// LD I, addr - ANNN
+    LdIAddr { addr: u16 },
+    /// JP V0, addr - BNNN
+    JpV0Addr { addr: u16 },
+    /// RND Vx, byte - CXNN
+    RndVxByte { x: u8, byte: u8 },
+    /// DRW Vx, Vy, nibble - DXYN
+    DrwVxVyNibble { x: u8, y: u8, nibble: u8 },
+    /// SKP Vx - EX9E
+    SkpVx { x: u8 },
+    /// SKNP Vx - EXA1
+    SknpVx { x: u8 },
+    /// LD Vx, DT - FX07
+    LdVxDt { x: u8 },
+    /// LD Vx, K - FX0A
+    LdVxK { x: u8 },
+    /// LD DT, Vx - FX15
+    LdDtVx { x: u8 },
+    /// LD ST, Vx - FX18
+    LdStVx { x: u8 },
+    /// ADD I, Vx - FX1E
+    AddIVx { x: u8 },
+    /// LD F, Vx - FX29
+    LdFVx { x: u8 },
+    /// LD HF, Vx - FX30 (SUPER-CHIP)
+    LdHfVx { x: u8 },
+    /// LD B, Vx - FX33
+    LdBVx { x: u8 },
+    /// LD [I], Vx - FX55
+    LdIVx { x: u8 },
+    /// LD Vx, [I] - FX65
+    LdVxI { x: u8 },
+    /// LD R, Vx - FX75 (SUPER-CHIP)
+    LdRVx { x: u8 },
+    /// LD Vx, R - FX85 (SUPER-CHIP)
+    LdVxR { x: u8 },
+    /// SCD nibble - 00CN (SUPER-CHIP)
+    ScdNibble { nibble: u8 },
+    /// SCU nibble - 00DN (XO-CHIP)
+    ScuNibble { nibble: u8 },
+    /// SCR - 00FB (SUPER-CHIP)
+    Scr,
+    /// SCL - 00FC (SUPER-CHIP)
+    Scl,
+    /// EXIT - 00FD (SUPER-CHIP)
+    Exit,
+    /// LOW - 00FE (SUPER-CHIP)
+    Low,
+    /// HIGH - 00FF (SUPER-CHIP)
+    High,
+    /// LD I, long - F000 NNNN (XO-CHIP), the address is the word following the opcode
+    LdILong,
+    /// LD [I], Vx - Vy - 5XY2 (XO-CHIP)
+    LdIVxVy { x: u8, y: u8 },
+    /// LD Vx - Vy, [I] - 5XY3 (XO-CHIP)
+    LdVxVyI { x: u8, y: u8 },
+    /// PLANE n - FN01 (XO-CHIP)
+    PlaneN { n: u8 },
+    /// AUDIO - F002 (XO-CHIP)
+    Audio,
+    /// PITCH Vx - FX3A (XO-CHIP)
+    PitchVx { x: u8 },
+}
+
+impl Instruction {
+    /// Decodes a 16-bit opcode.
+    pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+        use Instruction::*;
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let nibble = (opcode & 0x000F) as u8;
+        let byte = (opcode & 0x00FF) as u8;
+        let addr = opcode & 0x0FFF;
+
+        let instruction = match opcode & 0xF000 {
+            0x0000 => match opcode {
+                0x00C0..=0x00CF => ScdNibble { nibble },
+                0x00D0..=0x00DF => ScuNibble { nibble },
+                0x00E0 => Cls,
+                0x00EE => Ret,
+                0x00FB => Scr,
+                0x00FC => Scl,
+                0x00FD => Exit,
+                0x00FE => Low,
+                0x00FF => High,
+                _ => return Err(DecodeError { opcode }),
+            },
+            0x1000 => Jp { addr },
+            0x2000 => Call { addr },
+            0x3000 => SeVxByte { x, byte },
+            0x4000 => SneVxByte { x, byte },
+            0x5000 => match nibble {
+                0x0 => SeVxVy { x, y },
+                0x2 => LdIVxVy { x, y },
+                0x3 => LdVxVyI { x, y },
+                _ => return Err(DecodeError { opcode }),
+            },
+            0x6000 => LdVxByte { x, byte },
+            0x7000 => AddVxByte { x, byte },
+            0x8000 => match nibble {
+                0x0 => LdVxVy { x, y },
+                0x1 => OrVxVy { x, y },
+                0x2 => AndVxVy { x, y },
+                0x3 => XorVxVy { x, y },
+                0x4 => AddVxVy { x, y },
+                0x5 => SubVxVy { x, y },
+                0x6 => ShrVx { x, y },
+                0x7 => SubnVxVy { x, y },
+                0xE => ShlVx { x, y },
+                _ => return Err(DecodeError { opcode }),
+            },
+            0x9000 if nibble == 0 => SneVxVy { x, y },
+            0xA000 => LdIAddr { addr },
+            0xB000 => JpV0Addr { addr },
+            0xC000 => RndVxByte { x, byte },
+            0xD000 => DrwVxVyNibble { x, y, nibble },
+            0xE000 => match byte {
+                0x9E => SkpVx { x },
+                0xA1 => SknpVx { x },
+                _ => return Err(DecodeError { opcode }),
+            },
+            0xF000 => match byte {
+                0x00 if x == 0 => LdILong,
+                0x01 => PlaneN { n: x },
+                0x02 if x == 0 => Audio,
+                0x07 => LdVxDt { x },
+                0x0A => LdVxK { x },
+                0x15 => LdDtVx { x },
+                0x18 => LdStVx { x },
+                0x1E => AddIVx { x },
+                0x29 => LdFVx { x },
+                0x30 => LdHfVx { x },
+                0x33 => LdBVx { x },
+                0x3A => PitchVx { x },
+                0x55 => LdIVx { x },
+                0x65 => LdVxI { x },
+                0x75 => LdRVx { x },
+                0x85 => LdVxR { x },
+                _ => return Err(DecodeError { opcode }),
+            },
+            _ => return Err(DecodeError { opcode }),
+        };
+        Ok(instruction)
+    }
+
+    /// Encodes the instruction back into its opcode, the inverse of [`Instruction::decode`].
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+        let xy = |opcode: u16, x: u8, y: u8| opcode | (x as u16) << 8 | (y as u16) << 4;
+        let xnn = |opcode: u16, x: u8, byte: u8| opcode | (x as u16) << 8 | byte as u16;
+        let vx = |opcode: u16, x: u8| opcode | (x as u16) << 8;
+        match *self {
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            Jp { addr } => 0x1000 | addr,
+            Call { addr } => 0x2000 | addr,
+            SeVxByte { x, byte } => xnn(0x3000, x, byte),
+            SneVxByte { x, byte } => xnn(0x4000, x, byte),
+            SeVxVy { x, y } => xy(0x5000, x, y),
+            LdVxByte { x, byte } => xnn(0x6000, x, byte),
+            AddVxByte { x, byte } => xnn(0x7000, x, byte),
+            LdVxVy { x, y } => xy(0x8000, x, y),
+            OrVxVy { x, y } => xy(0x8001, x, y),
+            AndVxVy { x, y } => xy(0x8002, x, y),
+            XorVxVy { x, y } => xy(0x8003, x, y),
+            AddVxVy { x, y } => xy(0x8004, x, y),
+            SubVxVy { x, y } => xy(0x8005, x, y),
+            ShrVx { x, y } => xy(0x8006, x, y),
+            SubnVxVy { x, y } => xy(0x8007, x, y),
+            ShlVx { x, y } => xy(0x800E, x, y),
+            SneVxVy { x, y } => xy(0x9000, x, y),
+            LdIAddr { addr } => 0xA000 | addr,
+            JpV0Addr { addr } => 0xB000 | addr,
+            RndVxByte { x, byte } => xnn(0xC000, x, byte),
+            DrwVxVyNibble { x, y, nibble } => xy(0xD000, x, y) | nibble as u16,
+            SkpVx { x } => vx(0xE09E, x),
+            SknpVx { x } => vx(0xE0A1, x),
+            LdVxDt { x } => vx(0xF007, x),
+            LdVxK { x } => vx(0xF00A, x),
+            LdDtVx { x } => vx(0xF015, x),
+            LdStVx { x } => vx(0xF018, x),
+            AddIVx { x } => vx(0xF01E, x),
+            LdFVx { x } => vx(0xF029, x),
+            LdHfVx { x } => vx(0xF030, x),
+            LdBVx { x } => vx(0xF033, x),
+            LdIVx { x } => vx(0xF055, x),
+            LdVxI { x } => vx(0xF065, x),
+            LdRVx { x } => vx(0xF075, x),
+            LdVxR { x } => vx(0xF085, x),
+            ScdNibble { nibble } => 0x00C0 | nibble as u16,
+            ScuNibble { nibble } => 0x00D0 | nibble as u16,
+            Scr => 0x00FB,
+            Scl => 0x00FC,
+            Exit => 0x00FD,
+            Low => 0x00FE,
+            High => 0x00FF,
+            LdILong => 0xF000,
+            LdIVxVy { x, y } => xy(0x5002, x, y),
+            LdVxVyI { x, y } => xy(0x5003, x, y),
+            PlaneN { n } => vx(0xF001, n),
+            Audio => 0xF002,
+            PitchVx { x } => vx(0xF03A, x),
+        }
+    }
+
+    /// Length in bytes, 4 for F000 NNNN and 2 for everything else.
+    pub fn length(&self) -> u16 {
+        match self {
+            Instruction::LdILong => 4,
+            _ => 2,
+        }
+    }
+
+    /// Whether the instruction only exists with the XO-CHIP extensions.
+    pub fn is_xo_chip(&self) -> bool {
+        use Instruction::*;
+        matches!(
+            self,
+            ScuNibble { .. }
+                | LdILong
+                | LdIVxVy { .. }
+                | LdVxVyI { .. }
+                | PlaneN { .. }
+                | Audio
+                | PitchVx { .. }
+        )
+    }
+
+    /// Whether the instruction may continue anywhere but the next instruction: jumps, calls,
+    /// returns and conditional skips.
+    pub fn is_branch(&self) -> bool {
+        use Instruction::*;
+        self.is_skip() || matches!(self, Jp { .. } | Call { .. } | Ret | JpV0Addr { .. })
+    }
+
+    /// Whether the instruction conditionally skips the next one.
+    pub fn is_skip(&self) -> bool {
+        use Instruction::*;
+        matches!(
+            self,
+            SeVxByte { .. }
+                | SneVxByte { .. }
+                | SeVxVy { .. }
+                | SneVxVy { .. }
+                | SkpVx { .. }
+                | SknpVx { .. }
+        )
+    }
+
+    /// The jump, call or index target held in the opcode, if any.
+    pub fn target(&self) -> Option<u16> {
+        use Instruction::*;
+        match *self {
+            Jp { addr } | Call { addr } | LdIAddr { addr } | JpV0Addr { addr } => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// Whether the instruction stores to memory (FX33, FX55 and 5XY2).
+    pub fn writes_memory(&self) -> bool {
+        use Instruction::*;
+        matches!(self, LdBVx { .. } | LdIVx { .. } | LdIVxVy { .. })
+    }
+
+    /// Whether the instruction changes the display: clearing, drawing, scrolling or switching
+    /// resolution.
+    pub fn writes_screen(&self) -> bool {
+        use Instruction::*;
+        matches!(
+            self,
+            Cls | DrwVxVyNibble { .. }
+                | ScdNibble { .. }
+                | ScuNibble { .. }
+                | Scr
+                | Scl
+                | Low
+                | High
+        )
+    }
+
+    /// Bitmask of the V registers the instruction may read, bit N for VN. Instructions whose
+    /// operands depend on quirks report every register they could read.
+    pub fn registers_read(&self) -> u16 {
+        use Instruction::*;
+        match *self {
+            SeVxByte { x, .. }
+            | SneVxByte { x, .. }
+            | AddVxByte { x, .. }
+            | SkpVx { x }
+            | SknpVx { x }
+            | LdDtVx { x }
+            | LdStVx { x }
+            | AddIVx { x }
+            | LdFVx { x }
+            | LdHfVx { x }
+            | LdBVx { x }
+            | PitchVx { x } => bit(x),
+            LdVxVy { y, .. } => bit(y),
+            SeVxVy { x, y }
+            | SneVxVy { x, y }
+            | OrVxVy { x, y }
+            | AndVxVy { x, y }
+            | XorVxVy { x, y }
+            | AddVxVy { x, y }
+            | SubVxVy { x, y }
+            | ShrVx { x, y }
+            | SubnVxVy { x, y }
+            | ShlVx { x, y }
+            | DrwVxVyNibble { x, y, .. } => bit(x) | bit(y),
+            JpV0Addr { addr } => bit(0) | bit((addr >> 8) as u8),
+            LdIVx { x } | LdRVx { x } => span(0, x),
+            LdIVxVy { x, y } => span(x, y),
+            _ => 0,
+        }
+    }
+
+    /// Bitmask of the V registers the instruction may write, bit N for VN. Includes VF for
+    /// instructions that only reset it under a quirk.
+    pub fn registers_written(&self) -> u16 {
+        use Instruction::*;
+        match *self {
+            LdVxByte { x, .. }
+            | AddVxByte { x, .. }
+            | LdVxVy { x, .. }
+            | RndVxByte { x, .. }
+            | LdVxDt { x }
+            | LdVxK { x } => bit(x),
+            OrVxVy { x, .. }
+            | AndVxVy { x, .. }
+            | XorVxVy { x, .. }
+            | AddVxVy { x, .. }
+            | SubVxVy { x, .. }
+            | ShrVx { x, .. }
+            | SubnVxVy { x, .. }
+            | ShlVx { x, .. } => bit(x) | bit(0xF),
+            DrwVxVyNibble { .. } => bit(0xF),
+            LdVxI { x } | LdVxR { x } => span(0, x),
+            LdVxVyI { x, y } => span(x, y),
+            _ => 0,
+        }
+    }
+
+    /// Whether the instruction may read VF.
+    pub fn reads_vf(&self) -> bool {
+        self.registers_read() & bit(0xF) != 0
+    }
+
+    /// Whether the instruction may write VF, as a flag or as an ordinary register.
+    pub fn writes_vf(&self) -> bool {
+        self.registers_written() & bit(0xF) != 0
+    }
+
+    /// Formats the instruction as a machine with `quirks` runs it, naming 12-bit addresses with
+    /// `address_name`: shifts drop the Vy they ignore and BNNN names the register it adds.
+    /// The disassembler uses this to print labels.
+    pub fn format_with(&self, quirks: &Quirks, address_name: &dyn Fn(u16) -> String) -> String {
+        use Instruction::*;
+        match *self {
+            Cls => "CLS".to_string(),
+            Ret => "RET".to_string(),
+            Jp { addr } => format!("JP {}", address_name(addr)),
+            Call { addr } => format!("CALL {}", address_name(addr)),
+            SeVxByte { x, byte } => format!("SE V{:X}, 0x{:02X}", x, byte),
+            SneVxByte { x, byte } => format!("SNE V{:X}, 0x{:02X}", x, byte),
+            SeVxVy { x, y } => format!("SE V{:X}, V{:X}", x, y),
+            LdVxByte { x, byte } => format!("LD V{:X}, 0x{:02X}", x, byte),
+            AddVxByte { x, byte } => format!("ADD V{:X}, 0x{:02X}", x, byte),
+            LdVxVy { x, y } => format!("LD V{:X}, V{:X}", x, y),
+            OrVxVy { x, y } => format!("OR V{:X}, V{:X}", x, y),
+            AndVxVy { x, y } => format!("AND V{:X}, V{:X}", x, y),
+            XorVxVy { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+            AddVxVy { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+            SubVxVy { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+            ShrVx { x, y } if quirks.shift_uses_vy => format!("SHR V{:X}, V{:X}", x, y),
+            ShrVx { x, .. } => format!("SHR V{:X}", x),
+            SubnVxVy { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+            ShlVx { x, y } if quirks.shift_uses_vy => format!("SHL V{:X}, V{:X}", x, y),
+            ShlVx { x, .. } => format!("SHL V{:X}", x),
+            SneVxVy { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+            LdIAddr { addr } => format!("LD I, {}", address_name(addr)),
+            JpV0Addr { addr } if quirks.jump_uses_vx => {
+                format!("JP V{:X}, {}", addr >> 8, address_name(addr))
+            }
+            JpV0Addr { addr } => format!("JP V0, {}", address_name(addr)),
+            RndVxByte { x, byte } => format!("RND V{:X}, 0x{:02X}", x, byte),
+            DrwVxVyNibble { x, y, nibble } => format!("DRW V{:X}, V{:X}, {}", x, y, nibble),
+            SkpVx { x } => format!("SKP V{:X}", x),
+            SknpVx { x } => format!("SKNP V{:X}", x),
+            LdVxDt { x } => format!("LD V{:X}, DT", x),
+            LdVxK { x } => format!("LD V{:X}, K", x),
+            LdDtVx { x } => format!("LD DT, V{:X}", x),
+            LdStVx { x } => format!("LD ST, V{:X}", x),
+            AddIVx { x } => format!("ADD I, V{:X}", x),
+            LdFVx { x } => format!("LD F, V{:X}", x),
+            LdHfVx { x } => format!("LD HF, V{:X}", x),
+            LdBVx { x } => format!("LD B, V{:X}", x),
+            LdIVx { x } => format!("LD [I], V{:X}", x),
+            LdVxI { x } => format!("LD V{:X}, [I]", x),
+            LdRVx { x } => format!("LD R, V{:X}", x),
+            LdVxR { x } => format!("LD V{:X}, R", x),
+            ScdNibble { nibble } => format!("SCD {}", nibble),
+            ScuNibble { nibble } => format!("SCU {}", nibble),
+            Scr => "SCR".to_string(),
+            Scl => "SCL".to_string(),
+            Exit => "EXIT".to_string(),
+            Low => "LOW".to_string(),
+            High => "HIGH".to_string(),
+            LdILong => "LD I, long".to_string(),
+            LdIVxVy { x, y } => format!("LD [I], V{:X} - V{:X}", x, y),
+            LdVxVyI { x, y } => format!("LD V{:X} - V{:X}, [I]", x, y),
+            PlaneN { n } => format!("PLANE {}", n),
+            Audio => "AUDIO".to_string(),
+            PitchVx { x } => format!("PITCH V{:X}", x),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Without a machine to go by, every operand is shown as the original CHIP-8 used it
+        f.write_str(&self.format_with(&Quirks::COSMAC_VIP, &|addr| format!("0x{:03X}", addr)))
+    }
+}
+
+fn bit(register: u8) -> u16 {
+    1 << register
+}
+
+// Registers from x to y inclusive, in either direction
+fn span(x: u8, y: u8) -> u16 {
+    (x.min(y)..=x.max(y)).fold(0, |mask, register| mask | bit(register))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_opcode_round_trips_or_is_rejected() {
+        let mut decoded = 0;
+        for opcode in 0..=u16::MAX {
+            match Instruction::decode(opcode) {
+                Ok(instruction) => {
+                    assert_eq!(
+                        instruction.encode(),
+                        opcode,
+                        "{:04X} ({})",
+                        opcode,
+                        instruction
+                    );
+                    decoded += 1;
+                }
+                Err(err) => assert_eq!(err.opcode, opcode),
+            }
+        }
+        // 0NNN is mostly unused, everything else is dense
+        assert!(decoded > 40_000, "only {} opcodes decoded", decoded);
+    }
+
+    #[test]
+    fn unknown_opcodes_are_rejected() {
+        for opcode in [
+            0x0000, 0x0123, 0x00EF, 0x5001, 0x8008, 0x9001, 0xE000, 0xF100, 0xF1FF,
+        ] {
+            assert_eq!(Instruction::decode(opcode), Err(DecodeError { opcode }));
+        }
+    }
+
+    #[test]
+    fn display_uses_cowgod_mnemonics() {
+        let cases = [
+            (0x00E0, "CLS"),
+            (0x1234, "JP 0x234"),
+            (0x3A2B, "SE VA, 0x2B"),
+            (0x8126, "SHR V1, V2"),
+            (0xD125, "DRW V1, V2, 5"),
+            (0xF533, "LD B, V5"),
+            (0x5132, "LD [I], V1 - V3"),
+        ];
+        for (opcode, text) in cases {
+            assert_eq!(Instruction::decode(opcode).unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn metadata() {
+        let decode = |opcode| Instruction::decode(opcode).unwrap();
+        assert!(decode(0x2300).is_branch());
+        assert!(decode(0xE19E).is_skip());
+        assert!(!decode(0x6100).is_branch());
+        assert!(decode(0x8F14).reads_vf());
+        assert!(decode(0x8124).writes_vf());
+        assert!(!decode(0x8124).reads_vf());
+        assert!(decode(0xFF55).reads_vf());
+        assert!(decode(0xF233).writes_memory());
+        assert!(decode(0x5012).writes_memory());
+        assert!(!decode(0xF265).writes_memory());
+        assert_eq!(decode(0xF000).length(), 4);
+        assert!(decode(0xF201).is_xo_chip());
+        assert!(!decode(0x00C1).is_xo_chip());
+    }
+}
@@ -0,0 +1,323 @@
+//! Which host keyboard keys press which CHIP-8 keys.
+//!
+//! Bindings live in a small TOML file. `[keys]` sets the global layout and `[rom."NAME"]`
+//! sections override single keys for the ROM with that file name. Each CHIP-8 key, a hex
+//! digit, takes one host key name or a list of them; anything not mentioned keeps the layer
+//! below, down to the QWERTY 1234/QWER/ASDF/ZXCV default. Host keys go by the names SDL gives
+//! them, compared without case:
+//!
+//! ```toml
+//! # AZERTY
+//! [keys]
+//! 4 = "A"
+//! 5 = "Z"
+//! 7 = ["Q", "Left"]
+//! A = "W"
+//!
+//! [rom."pong.ch8"]
+//! 1 = ["1", "Up"]
+//! ```
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+/// The hex keypad as laid out on the COSMAC VIP, row by row.
+pub const KEYPAD: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+// The host keys in the same spots on a QWERTY keyboard
+const QWERTY: [[&str; 4]; 4] = [
+    ["1", "2", "3", "4"],
+    ["Q", "W", "E", "R"],
+    ["A", "S", "D", "F"],
+    ["Z", "X", "C", "V"],
+];
+
+/// Host key names for some CHIP-8 keys.
+pub type Bindings = BTreeMap<u8, Vec<String>>;
+
+/// The host keys bound to each CHIP-8 key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    keys: [Vec<String>; 16],
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keys: [Vec<String>; 16] = Default::default();
+        for (row, names) in KEYPAD.iter().zip(QWERTY) {
+            for (&key, name) in row.iter().zip(names) {
+                keys[key as usize] = vec![name.to_string()];
+            }
+        }
+        Keymap { keys }
+    }
+}
+
+impl Keymap {
+    /// The CHIP-8 key a host key presses, if any.
+    pub fn key(&self, host_key: &str) -> Option<u8> {
+        (0..16u8).find(|&key| {
+            self.keys[key as usize]
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(host_key))
+        })
+    }
+
+    pub fn host_keys(&self, key: u8) -> &[String] {
+        &self.keys[key as usize & 0xF]
+    }
+
+    // Replaces the bindings of the keys mentioned, and takes their host keys away from the
+    // others so every host key presses one CHIP-8 key
+    fn apply(&mut self, bindings: &Bindings) {
+        for (&key, names) in bindings {
+            for other in self.keys.iter_mut() {
+                other.retain(|bound| !names.iter().any(|name| name.eq_ignore_ascii_case(bound)));
+            }
+            self.keys[key as usize] = names.clone();
+        }
+    }
+}
+
+/// A key bindings file: the global layout and per-ROM overrides.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyConfig {
+    pub global: Bindings,
+    /// Overrides by ROM file name
+    pub roms: BTreeMap<String, Bindings>,
+}
+
+/// Why a key bindings file couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeymapError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for KeymapError {}
+
+impl KeyConfig {
+    /// Parses a key bindings file.
+    pub fn parse(text: &str) -> Result<KeyConfig, KeymapError> {
+        let mut config = KeyConfig::default();
+        // The section being read, None for the ROM name until a header is seen
+        let mut section: Option<Option<String>> = None;
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| KeymapError {
+                line: index + 1,
+                message,
+            };
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header
+                    .strip_suffix(']')
+                    .ok_or_else(|| error("unterminated section header".to_string()))?
+                    .trim();
+                section = Some(if header == "keys" {
+                    None
+                } else if let Some(name) = header.strip_prefix("rom.") {
+                    let (name, rest) = parse_string(name.trim()).map_err(error)?;
+                    if !rest.trim().is_empty() {
+                        return Err(error(format!("unexpected '{}' after the ROM name", rest)));
+                    }
+                    config.roms.entry(name.clone()).or_default();
+                    Some(name)
+                } else {
+                    return Err(error(format!(
+                        "unknown section '{}', expected keys or rom.\"NAME\"",
+                        header
+                    )));
+                });
+                continue;
+            }
+
+            let Some(section) = &section else {
+                return Err(error("binding outside of a section".to_string()));
+            };
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected KEY = \"HOST KEY\"".to_string()))?;
+            let name = name.trim().trim_matches('"');
+            let key = u8::from_str_radix(name, 16)
+                .ok()
+                .filter(|&key| key < 16 && name.len() == 1)
+                .ok_or_else(|| error(format!("'{}' is not a CHIP-8 key (0-F)", name)))?;
+            let names = parse_value(value.trim()).map_err(error)?;
+            let bindings = match section {
+                Some(rom) => config.roms.entry(rom.clone()).or_default(),
+                None => &mut config.global,
+            };
+            bindings.insert(key, names);
+        }
+        Ok(config)
+    }
+
+    /// The layout for a ROM: the defaults, then the global bindings, then the ROM's own.
+    pub fn keymap(&self, rom_name: &str) -> Keymap {
+        let mut keymap = Keymap::default();
+        keymap.apply(&self.global);
+        if let Some(bindings) = self.roms.get(rom_name) {
+            keymap.apply(bindings);
+        }
+        keymap
+    }
+
+    /// Writes the bindings back out in the format [`KeyConfig::parse`] reads.
+    pub fn to_toml(&self) -> String {
+        let mut text = String::from("[keys]\n");
+        write_bindings(&mut text, &self.global);
+        for (rom, bindings) in &self.roms {
+            text.push_str(&format!("\n[rom.{}]\n", quote(rom)));
+            write_bindings(&mut text, bindings);
+        }
+        text
+    }
+}
+
+fn write_bindings(text: &mut String, bindings: &Bindings) {
+    for (key, names) in bindings {
+        let names: Vec<String> = names.iter().map(|name| quote(name)).collect();
+        let value = match names.as_slice() {
+            [name] => name.clone(),
+            _ => format!("[{}]", names.join(", ")),
+        };
+        text.push_str(&format!("{:X} = {}\n", key, value));
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Drops a # comment, unless the # is inside a string like the host key "#"
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+// Reads a quoted string from the start of `text`, returning it and what follows
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let rest = text
+        .strip_prefix('"')
+        .ok_or_else(|| format!("expected a quoted string, found '{}'", text))?;
+    let mut value = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &rest[index + 1..])),
+            '\\' => match chars.next() {
+                Some((_, c @ ('"' | '\\'))) => value.push(c),
+                _ => return Err("only \\\" and \\\\ escapes are supported".to_string()),
+            },
+            c => value.push(c),
+        }
+    }
+    Err("unterminated string".to_string())
+}
+
+// A host key name or a list of them
+fn parse_value(text: &str) -> Result<Vec<String>, String> {
+    let Some(mut rest) = text.strip_prefix('[') else {
+        let (name, rest) = parse_string(text)?;
+        if !rest.trim().is_empty() {
+            return Err(format!("unexpected '{}' after the host key", rest.trim()));
+        }
+        return Ok(vec![name]);
+    };
+    let mut names = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix(']') {
+            if !after.trim().is_empty() {
+                return Err(format!("unexpected '{}' after the list", after.trim()));
+            }
+            return Ok(names);
+        }
+        let (name, after) = parse_string(rest)?;
+        names.push(name);
+        rest = after.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_qwerty() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.key("Q"), Some(0x4));
+        assert_eq!(keymap.key("v"), Some(0xF));
+        assert_eq!(keymap.key("X"), Some(0x0));
+        assert_eq!(keymap.key("Space"), None);
+        assert_eq!(keymap.host_keys(0xC), ["4"]);
+    }
+
+    #[test]
+    fn layers_global_and_rom_bindings() {
+        let config = KeyConfig::parse(
+            "# AZERTY\n\
+             [keys]\n\
+             4 = \"A\"   # top row\n\
+             7 = [\"Q\", \"Left\"]\n\
+             \n\
+             [rom.\"pong.ch8\"]\n\
+             1 = [\"1\", \"Up\"]\n\
+             c = \"#\"\n",
+        )
+        .unwrap();
+
+        let global = config.keymap("other.ch8");
+        assert_eq!(global.key("A"), Some(0x4));
+        assert_eq!(global.key("q"), Some(0x7));
+        assert_eq!(global.key("Left"), Some(0x7));
+        // Rebinding A and Q moved them off the keys they had by default
+        assert!(global.host_keys(0x7).iter().all(|name| name != "A"));
+        assert!(global.host_keys(0x4).iter().all(|name| name != "Q"));
+        assert_eq!(global.key("Up"), None);
+
+        let pong = config.keymap("pong.ch8");
+        assert_eq!(pong.key("Up"), Some(0x1));
+        assert_eq!(pong.key("1"), Some(0x1));
+        assert_eq!(pong.key("#"), Some(0xC));
+        assert_eq!(pong.key("A"), Some(0x4));
+
+        assert_eq!(KeyConfig::parse(&config.to_toml()), Ok(config));
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        let error = |text: &str| KeyConfig::parse(text).unwrap_err();
+        assert_eq!(error("1 = \"Q\"").line, 1);
+        assert_eq!(error("[keys]\n\nG = \"Q\"").line, 3);
+        assert!(error("[keys]\n1 = Q").message.contains("quoted"));
+        assert!(error("[keys]\n1 = [\"Q\"").message.contains("quoted"));
+        assert!(error("[roms]").message.contains("unknown section"));
+    }
+}
@@ -0,0 +1,42 @@
+//! A CHIP-8 interpreter core with SUPER-CHIP and XO-CHIP extensions.
+//!
+//! The core has no I/O of its own: load a program, feed key state into [`Chip8::keys`], call
+//! [`Chip8::run_frame`] 60 times a second and draw [`Chip8::screen`]. The SDL frontend lives in
+//! the `chip_8` binary behind the `sdl` feature, so the library builds without SDL2 installed.
+//!
+//! ```
+//! use chip_8::{Chip8, Quirks};
+//!
+//! let mut chip8 = Chip8::new(Quirks::default());
+//! chip8.load_program(&[0x60, 0x2A])?; // LD V0, 0x2A
+//! chip8.emulate_cycle()?;
+//! assert_eq!(chip8.registers[0], 0x2A);
+//! # Ok::<(), chip_8::Chip8Error>(())
+//! ```
+
+pub mod assembler;
+pub mod chip8;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod framebuffer;
+pub mod gdb;
+pub mod hash;
+pub mod headless;
+pub mod inspector;
+pub mod instruction;
+pub mod keymap;
+pub mod movie;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod savestate;
+pub mod trace;
+pub mod tracediff;
+pub mod undo;
+
+pub use chip8::{Chip8, Chip8Builder};
+pub use error::Chip8Error;
+pub use instruction::{DecodeError, Instruction};
+pub use quirks::{IndexIncrement, Quirks};
+pub use savestate::SaveStateError;
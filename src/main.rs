@@ -1,153 +1,315 @@
-extern crate sdl2;
-mod chip8;
+mod audio;
+mod cli;
+#[cfg(feature = "sdl")]
+mod frontend;
+#[cfg(feature = "sdl")]
+mod overlay;
+#[cfg(feature = "sdl")]
+mod repl;
 // comment here for git stuff
-use crate::chip8::SCREEN_HEIGHT;
-use crate::chip8::SCREEN_WIDTH;
-use chip8::Chip8;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use chip_8::gdb::GdbServer;
+use chip_8::headless::{self, KeyScript, RunLimit};
+use chip_8::movie::Movie;
+#[cfg(feature = "sdl")]
+use chip_8::movie::Recorder;
+use chip_8::trace::{RotatingFile, Tracer};
+use chip_8::tracediff;
+use chip_8::Chip8;
+use cli::{AsmOptions, Command, DisasmOptions, DumpFormat, Options, TraceDiffOptions};
 use std::env;
 use std::error::Error;
-use std::fs::File;
-use std::io::{self, Read};
-use std::time::Duration;
-use std::time::Instant;
-
-const WINDOW_SCALE: u32 = 10;
-const WINDOW_WIDTH: u32 = (SCREEN_WIDTH as u32) * WINDOW_SCALE;
-const WINDOW_HEIGHT: u32 = (SCREEN_HEIGHT as u32) * WINDOW_SCALE;
-
-fn main() -> Result<(), Box<dyn Error>> {
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-
-    let window = video_subsystem
-        .window("CHIP-8 Emulator", WINDOW_WIDTH, WINDOW_HEIGHT)
-        .position_centered()
-        .build()
-        .unwrap();
-    let mut canvas = window.into_canvas().build().unwrap();
-    canvas.set_draw_color(Color::RGB(0, 0, 0));
-    canvas.clear();
-    canvas.present();
-
-    let mut event_pump = sdl_context.event_pump().unwrap();
-
-    // Create a new CHIP-8 emulator instance
-    let mut chip8 = Chip8::new();
-    let rom_path = "tetris.ch8";
-    let current_dir = env::current_dir()?;
-    println!("Current working directory: {:?}", current_dir);
-
-    // Print the file path to ensure it's correct
-    println!("Loading ROM from path: {}", rom_path);
-
-    // Read the .ch8 file
-    let mut file = File::open(rom_path)
-        .map_err(|e| format!("Failed to open file: {} - Error: {}", rom_path, e))?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)
-        .map_err(|e| format!("Failed to read file: {} - Error: {}", rom_path, e))?;
-    // Read the program file into a byte vector
-
-    // Load the program into the CHIP-8 emulator
-    chip8.load_program(&buffer);
-
-    let mut last_cycle_time = Instant::now();
-
-    // Main emulation loop (simplified for this example)
-    'running: loop {
-        // Poll for events and handle key presses/releases
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => {
-                    break 'running;
-                }
-                Event::KeyDown { keycode, .. } => {
-                    if let Some(key) = map_keycode_to_chip8_key(keycode) {
-                        chip8.keys[key as usize] = 1;
-                    }
-                }
-                Event::KeyUp { keycode, .. } => {
-                    if let Some(key) = map_keycode_to_chip8_key(keycode) {
-                        chip8.keys[key as usize] = 0;
-                    }
-                }
-                _ => {}
-            }
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+fn main() {
+    let command = match cli::parse(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, cli::USAGE);
+            process::exit(2);
         }
+    };
 
-        // Run CPU cycles
-        if last_cycle_time.elapsed().as_millis() >= 2 as u128 {
-            chip8.emulate_cycle();
-            last_cycle_time = Instant::now();
+    let result = match command {
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
         }
+        Command::Run(options) => run(&options),
+        Command::Disasm(options) => disasm(&options),
+        Command::Asm(options) => asm(&options),
+        Command::TraceDiff(options) => trace_diff(&options),
+    };
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
 
-        draw_screen(&chip8, &mut canvas);
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let program = read_program_file(&options.rom_path)?;
+    let movie = match &options.play {
+        Some(path) => Some(read_movie(path)?),
+        None => None,
+    };
 
-        ::std::thread::sleep(Duration::from_millis(2)); // Sleep to avoid high CPU usage
+    // Create a new CHIP-8 emulator instance and load the program into it
+    let (mut chip8, options) = match &movie {
+        Some(movie) => {
+            let options = Options {
+                quirks: movie.quirks,
+                seed: Some(movie.seed),
+                instructions_per_frame: movie.instructions_per_frame as usize,
+                ..options.clone()
+            };
+            (movie.prepare(&program)?, options)
+        }
+        None => {
+            let mut chip8 = Chip8::new(options.quirks);
+            if let Some(seed) = options.seed {
+                chip8.set_seed(seed);
+            }
+            chip8.load_program(&program)?;
+            (chip8, options.clone())
+        }
+    };
+
+    if let Some(path) = &options.trace {
+        chip8.set_tracer(Some(create_tracer(path, &options)?));
+    }
+    let result = if options.headless {
+        run_headless(&mut chip8, &options, movie.as_ref())
+    } else {
+        run_windowed(&mut chip8, &options, movie.as_ref())
+    };
+    // A trace cut short by a write error is still worth reporting after a good run
+    if let Some(tracer) = chip8.take_tracer() {
+        tracer
+            .finish()
+            .map_err(|e| format!("Failed to write trace: {}", e))?;
     }
+    result
+}
+
+fn create_tracer(path: &Path, options: &Options) -> Result<Tracer, String> {
+    let error = |e: io::Error| format!("Failed to create file: {} - Error: {}", path.display(), e);
+    let writer: Box<dyn Write + Send> = match options.trace_rotate {
+        Some(max_bytes) => Box::new(RotatingFile::create(path, max_bytes, 3).map_err(error)?),
+        None => Box::new(BufWriter::new(File::create(path).map_err(error)?)),
+    };
+    Ok(Tracer::new(
+        writer,
+        options.trace_format,
+        options.trace_filter.clone(),
+    ))
+}
+
+fn read_movie(path: &Path) -> Result<Movie, String> {
+    let data = fs::read(path)
+        .map_err(|e| format!("Failed to read file: {} - Error: {}", path.display(), e))?;
+    Movie::from_bytes(&data).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn write_movie(path: &Path, movie: &Movie) -> Result<(), Box<dyn Error>> {
+    fs::write(path, movie.to_bytes())?;
+    eprintln!(
+        "Wrote {} frames of input to {}",
+        movie.frames,
+        path.display()
+    );
+    Ok(())
+}
 
+// Prints the disassembly of a ROM to stdout
+fn disasm(options: &DisasmOptions) -> Result<(), Box<dyn Error>> {
+    let program = read_program_file(&options.rom_path)?;
+    print!("{}", chip_8::disasm::disassemble(&program, options.quirks));
     Ok(())
 }
 
-fn draw_screen(chip8: &Chip8, canvas: &mut Canvas<Window>) {
-    canvas.set_draw_color(Color::RGB(0, 0, 0));
-    canvas.clear();
+// Assembles an Octo source file into a ROM
+fn asm(options: &AsmOptions) -> Result<(), Box<dyn Error>> {
+    let source = fs::read_to_string(&options.source_path).map_err(|e| {
+        format!(
+            "Failed to read file: {} - Error: {}",
+            options.source_path.display(),
+            e
+        )
+    })?;
+    let program = chip_8::assembler::assemble(&source)
+        .map_err(|e| format!("{}:{}", options.source_path.display(), e))?;
+    fs::write(&options.output_path, &program)?;
+    eprintln!(
+        "Wrote {} bytes to {}",
+        program.len(),
+        options.output_path.display()
+    );
+    Ok(())
+}
 
-    for (i, &pixel) in chip8.screen.iter().enumerate() {
-        if pixel == 1 {
-            let x = (i % SCREEN_WIDTH) as u32 * WINDOW_SCALE;
-            let y = (i / SCREEN_WIDTH) as u32 * WINDOW_SCALE;
+// Runs a ROM against a reference trace and reports where they diverge
+fn trace_diff(options: &TraceDiffOptions) -> Result<(), Box<dyn Error>> {
+    let program = read_program_file(&options.rom_path)?;
+    let path = &options.trace_path;
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read file: {} - Error: {}", path.display(), e))?;
+    let reference = tracediff::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
 
-            canvas.set_draw_color(Color::RGB(255, 255, 255));
-            let _ = canvas.fill_rect(Rect::new(x as i32, y as i32, WINDOW_SCALE, WINDOW_SCALE));
+    let (mut chip8, instructions_per_frame, keys) = match &options.play {
+        Some(path) => {
+            let movie = read_movie(path)?;
+            let chip8 = movie.prepare(&program)?;
+            (chip8, movie.instructions_per_frame as usize, movie.keys)
         }
+        None => {
+            let mut chip8 = Chip8::new(options.quirks);
+            if let Some(seed) = options.seed {
+                chip8.set_seed(seed);
+            }
+            chip8.load_program(&program)?;
+            let keys = match &options.key_script {
+                Some(path) => {
+                    let text = fs::read_to_string(path).map_err(|e| {
+                        format!("Failed to read file: {} - Error: {}", path.display(), e)
+                    })?;
+                    KeyScript::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+                }
+                None => KeyScript::default(),
+            };
+            (chip8, options.instructions_per_frame, keys)
+        }
+    };
+
+    let report = tracediff::compare(
+        &mut chip8,
+        instructions_per_frame,
+        &keys,
+        &reference,
+        options.context,
+    );
+    print!("{}", report);
+    if report.divergence.is_some() {
+        return Err("the run diverged from the trace".into());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "sdl")]
+fn run_windowed(
+    chip8: &mut Chip8,
+    options: &Options,
+    playback: Option<&Movie>,
+) -> Result<(), Box<dyn Error>> {
+    let mut recorder = options
+        .record
+        .as_ref()
+        .map(|_| Recorder::new(chip8, options.instructions_per_frame));
+    frontend::run(chip8, options, playback, recorder.as_mut())?;
+    if let (Some(path), Some(recorder)) = (&options.record, recorder) {
+        write_movie(path, &recorder.finish())?;
     }
+    Ok(())
+}
 
-    canvas.present();
+#[cfg(not(feature = "sdl"))]
+fn run_windowed(
+    _chip8: &mut Chip8,
+    _options: &Options,
+    _playback: Option<&Movie>,
+) -> Result<(), Box<dyn Error>> {
+    Err("this build has no SDL support, run with --headless".into())
 }
 
-fn map_keycode_to_chip8_key(keycode: Option<Keycode>) -> Option<u8> {
-    match keycode {
-        Some(Keycode::Num1) => Some(0x1),
-        Some(Keycode::Num2) => Some(0x2),
-        Some(Keycode::Num3) => Some(0x3),
-        Some(Keycode::Num4) => Some(0xC),
-        Some(Keycode::Q) => Some(0x4),
-        Some(Keycode::W) => Some(0x5),
-        Some(Keycode::E) => Some(0x6),
-        Some(Keycode::R) => Some(0xD),
-        Some(Keycode::A) => Some(0x7),
-        Some(Keycode::S) => Some(0x8),
-        Some(Keycode::D) => Some(0x9),
-        Some(Keycode::F) => Some(0xE),
-        Some(Keycode::Z) => Some(0xA),
-        Some(Keycode::X) => Some(0x0),
-        Some(Keycode::C) => Some(0xB),
-        Some(Keycode::V) => Some(0xF),
-        _ => None,
+// Runs in real time with a GDB server until the debugger kills the machine or `frames` run out
+fn run_gdb(
+    chip8: &mut Chip8,
+    options: &Options,
+    port: u16,
+    frames: Option<u64>,
+    keys: &KeyScript,
+) -> Result<(), Box<dyn Error>> {
+    let mut server = GdbServer::bind(("127.0.0.1", port))?;
+    eprintln!("Waiting for GDB on {}", server.local_addr()?);
+    let mut next_frame = Instant::now();
+    for frame in 0..frames.unwrap_or(u64::MAX) {
+        keys.apply(chip8, frame);
+        server.run_frame(chip8, options.instructions_per_frame)?;
+        if server.is_killed() {
+            break;
+        }
+        next_frame += FRAME_DURATION;
+        thread::sleep(next_frame.saturating_duration_since(Instant::now()));
     }
+    Ok(())
 }
 
-// Function to read a chip-8 program file into a byte vector
-//fn read_program_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
-//    println!("fuck you");
-//    if !std::path::Path::new(path).exists() {
-//        eprintln!("File does not exist: {}", path);
-//        return Ok(());
-//    }
-//    let mut file = File::open(path)?;
-//    println!("fuck you again");
-//    let mut buffer = Vec::new();
-//    file.read_to_end(&mut buffer)?;
-//    Ok(buffer)
-//}
+// Read a chip-8 program file into a byte vector
+fn read_program_file(path: &Path) -> Result<Vec<u8>, String> {
+    eprintln!("Loading ROM from path: {}", path.display());
+
+    let mut file = File::open(path)
+        .map_err(|e| format!("Failed to open file: {} - Error: {}", path.display(), e))?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)
+        .map_err(|e| format!("Failed to read file: {} - Error: {}", path.display(), e))?;
+    Ok(buffer)
+}
+
+// Runs the configured number of frames or cycles without a window, then dumps the screen
+fn run_headless(
+    chip8: &mut Chip8,
+    options: &Options,
+    playback: Option<&Movie>,
+) -> Result<(), Box<dyn Error>> {
+    let keys = match (&options.key_script, playback) {
+        (_, Some(movie)) => movie.keys.clone(),
+        (Some(path), None) => {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read file: {} - Error: {}", path.display(), e))?;
+            KeyScript::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        (None, None) => KeyScript::default(),
+    };
+    let frames = options
+        .frames
+        .or(playback.map(|movie| movie.frames))
+        .unwrap_or(600);
+    let limit = match options.cycles {
+        Some(cycles) => RunLimit::Cycles(cycles),
+        None => RunLimit::Frames(frames),
+    };
+    // The recording can be written up front, the input is known before the run
+    if let Some(path) = &options.record {
+        let movie = Movie {
+            rom_hash: chip8.rom_hash,
+            quirks: chip8.quirks,
+            seed: chip8.seed,
+            instructions_per_frame: options.instructions_per_frame as u32,
+            frames,
+            keys: keys.clone(),
+        };
+        write_movie(path, &movie)?;
+    }
+    match options.gdb {
+        Some(port) => run_gdb(chip8, options, port, options.frames, &keys)?,
+        None => headless::run(chip8, options.instructions_per_frame, limit, &keys)?,
+    }
+
+    let dump = match options.dump {
+        DumpFormat::Ascii => chip8.screen_ascii().into_bytes(),
+        DumpFormat::Pbm => chip8.screen_pbm(),
+        DumpFormat::Png => chip8.screen_png(),
+        DumpFormat::Hash => format!("{:016x}\n", chip8.screen_hash()).into_bytes(),
+    };
+    match &options.output {
+        Some(path) => fs::write(path, dump)?,
+        None => io::stdout().write_all(&dump)?,
+    }
+    Ok(())
+}
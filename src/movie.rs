@@ -0,0 +1,312 @@
+//! Movies: recorded keypad input that replays a session exactly.
+//!
+//! Besides the ROM, a run is decided by the quirks, the random seed, the instructions per frame
+//! and the keypad. A movie stores all of them, the keypad as every change to
+//! [`Chip8::keys`](crate::Chip8::keys) with the frame it was made before. Playing it back on a
+//! fresh machine with the same ROM reproduces the session bit for bit, in the SDL frontend or
+//! headless. All integers are little-endian.
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use crate::headless::{self, KeyEvent, KeyScript, RunLimit};
+use crate::quirks::Quirks;
+use crate::savestate::{decode_quirks, encode_quirks, Reader, SaveStateError};
+use std::error::Error;
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"C8MV";
+/// Format version written by [`Movie::to_bytes`].
+pub const MOVIE_VERSION: u16 = 1;
+
+/// A recorded session, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    /// FNV-1a hash of the ROM the movie was recorded with
+    pub rom_hash: u64,
+    pub quirks: Quirks,
+    pub seed: u64,
+    pub instructions_per_frame: u32,
+    /// Number of frames the session ran for
+    pub frames: u64,
+    pub keys: KeyScript,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieError {
+    /// The data doesn't start with the movie magic bytes
+    BadMagic,
+    /// The movie was written by a newer or unknown format version
+    UnsupportedVersion(u16),
+    /// The movie was recorded with a different ROM than the one given
+    RomMismatch { expected: u64, found: u64 },
+    /// The data ends before the movie does
+    Truncated,
+    /// A field holds a value no recording could contain
+    Invalid(&'static str),
+    /// The machine faulted while setting up or replaying the movie
+    Chip8(Chip8Error),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            MovieError::BadMagic => write!(f, "not a CHIP-8 movie"),
+            MovieError::UnsupportedVersion(version) => {
+                write!(f, "unsupported movie version {}", version)
+            }
+            MovieError::RomMismatch { expected, found } => write!(
+                f,
+                "movie was recorded with ROM {:016x}, but ROM {:016x} is loaded",
+                found, expected
+            ),
+            MovieError::Truncated => write!(f, "movie is truncated"),
+            MovieError::Invalid(field) => write!(f, "movie has an invalid {}", field),
+            MovieError::Chip8(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl From<SaveStateError> for MovieError {
+    fn from(err: SaveStateError) -> Self {
+        match err {
+            SaveStateError::Truncated => MovieError::Truncated,
+            SaveStateError::Invalid(field) => MovieError::Invalid(field),
+            _ => MovieError::Invalid("data"),
+        }
+    }
+}
+
+impl From<Chip8Error> for MovieError {
+    fn from(err: Chip8Error) -> Self {
+        MovieError::Chip8(err)
+    }
+}
+
+impl Movie {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let events = self.keys.events();
+        let mut out = Vec::with_capacity(40 + events.len() * 10);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&MOVIE_VERSION.to_le_bytes());
+        out.extend_from_slice(&self.rom_hash.to_le_bytes());
+        out.extend_from_slice(&encode_quirks(&self.quirks));
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        out.extend_from_slice(&self.instructions_per_frame.to_le_bytes());
+        out.extend_from_slice(&self.frames.to_le_bytes());
+        out.extend_from_slice(&(events.len() as u32).to_le_bytes());
+        for event in events {
+            out.extend_from_slice(&event.frame.to_le_bytes());
+            out.push(event.key);
+            out.push(event.pressed as u8);
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, MovieError> {
+        let mut reader = Reader::new(data);
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let rom_hash = reader.u64()?;
+        let quirks = decode_quirks(reader.array()?)?;
+        let seed = reader.u64()?;
+        let instructions_per_frame = reader.u32()?;
+        if instructions_per_frame == 0 {
+            return Err(MovieError::Invalid("instructions per frame"));
+        }
+        let frames = reader.u64()?;
+        let count = reader.u32()?;
+        let mut events = Vec::new();
+        for _ in 0..count {
+            let frame = reader.u64()?;
+            let key = reader.u8()?;
+            if key >= 16 {
+                return Err(MovieError::Invalid("key"));
+            }
+            let pressed = reader.bool()?;
+            events.push(KeyEvent {
+                frame,
+                key,
+                pressed,
+            });
+        }
+        Ok(Movie {
+            rom_hash,
+            quirks,
+            seed,
+            instructions_per_frame,
+            frames,
+            keys: KeyScript::new(events),
+        })
+    }
+
+    /// Creates a machine with the movie's quirks and seed and `program` loaded, ready to play
+    /// the first frame. Refuses a program other than the one the movie was recorded with.
+    pub fn prepare(&self, program: &[u8]) -> Result<Chip8, MovieError> {
+        let mut chip8 = Chip8::new(self.quirks);
+        chip8.set_seed(self.seed);
+        chip8.load_program(program)?;
+        if chip8.rom_hash != self.rom_hash {
+            return Err(MovieError::RomMismatch {
+                expected: chip8.rom_hash,
+                found: self.rom_hash,
+            });
+        }
+        Ok(chip8)
+    }
+
+    /// Plays the whole movie headless and returns the machine as the session left it.
+    pub fn replay(&self, program: &[u8]) -> Result<Chip8, MovieError> {
+        let mut chip8 = self.prepare(program)?;
+        headless::run(
+            &mut chip8,
+            self.instructions_per_frame as usize,
+            RunLimit::Frames(self.frames),
+            &self.keys,
+        )?;
+        Ok(chip8)
+    }
+}
+
+/// Builds a [`Movie`] from a session as it runs.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    movie: Movie,
+    events: Vec<KeyEvent>,
+    keys: [u8; 16],
+}
+
+impl Recorder {
+    /// Starts recording a machine that has just been created and had its program loaded.
+    pub fn new(chip8: &Chip8, instructions_per_frame: usize) -> Self {
+        Recorder {
+            movie: Movie {
+                rom_hash: chip8.rom_hash,
+                quirks: chip8.quirks,
+                seed: chip8.seed,
+                instructions_per_frame: instructions_per_frame as u32,
+                frames: 0,
+                keys: KeyScript::default(),
+            },
+            events: Vec::new(),
+            keys: [0; 16],
+        }
+    }
+
+    /// Records the keypad as it is about to be seen by the next frame. Call it right before
+    /// every frame that runs.
+    pub fn record_frame(&mut self, chip8: &Chip8) {
+        let frame = self.movie.frames;
+        for (key, (&now, before)) in chip8.keys.iter().zip(self.keys.iter_mut()).enumerate() {
+            let pressed = now != 0;
+            if pressed != (*before != 0) {
+                self.events.push(KeyEvent {
+                    frame,
+                    key: key as u8,
+                    pressed,
+                });
+                *before = now;
+            }
+        }
+        self.movie.frames += 1;
+    }
+
+    pub fn finish(self) -> Movie {
+        Movie {
+            keys: KeyScript::new(self.events),
+            ..self.movie
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draws a random digit on every frame key 5 is held, at the position in V1
+    const PROGRAM: [u8; 16] = [
+        0x65, 0x05, // LD V5, 5
+        0xE5, 0xA1, // SKNP V5
+        0x12, 0x08, // JP 0x208
+        0x12, 0x00, // JP 0x200
+        0xC0, 0x0F, // RND V0, 0x0F
+        0xF0, 0x29, // LD F, V0
+        0xD1, 0x15, // DRW V1, V1, 5
+        0x12, 0x00, // JP 0x200
+    ];
+
+    fn record() -> (Movie, Chip8) {
+        let mut chip8 = Chip8::new(Quirks::SUPER_CHIP);
+        chip8.load_program(&PROGRAM).unwrap();
+        let mut recorder = Recorder::new(&chip8, 20);
+        for frame in 0..120 {
+            chip8.keys[5] = (frame % 30 < 12) as u8;
+            chip8.keys[7] = (frame > 100) as u8;
+            recorder.record_frame(&chip8);
+            chip8.run_frame(20).unwrap();
+        }
+        (recorder.finish(), chip8)
+    }
+
+    #[test]
+    fn recorder_stores_key_changes() {
+        let (movie, _) = record();
+        assert_eq!(movie.frames, 120);
+        assert_eq!(movie.instructions_per_frame, 20);
+        let events = movie.keys.events();
+        // Key 5 goes down at 0, 30, 60 and 90 and up 12 frames later, key 7 goes down at 101
+        assert_eq!(events.len(), 9);
+        assert_eq!(
+            events[0],
+            KeyEvent {
+                frame: 0,
+                key: 5,
+                pressed: true
+            }
+        );
+        assert_eq!(
+            events[7],
+            KeyEvent {
+                frame: 101,
+                key: 7,
+                pressed: true
+            }
+        );
+    }
+
+    #[test]
+    fn movies_round_trip() {
+        let (movie, _) = record();
+        assert_eq!(Movie::from_bytes(&movie.to_bytes()), Ok(movie.clone()));
+
+        let bytes = movie.to_bytes();
+        assert_eq!(
+            Movie::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(MovieError::Truncated)
+        );
+        assert_eq!(Movie::from_bytes(b"C8SS"), Err(MovieError::BadMagic));
+    }
+
+    #[test]
+    fn replay_reproduces_the_session() {
+        let (movie, recorded) = record();
+        let replayed = movie.replay(&PROGRAM).unwrap();
+        assert_eq!(replayed.screen, recorded.screen);
+        assert_eq!(replayed.save_state(), recorded.save_state());
+    }
+
+    #[test]
+    fn replay_refuses_another_rom() {
+        let (movie, _) = record();
+        assert!(matches!(
+            movie.replay(&[0x12, 0x00]),
+            Err(MovieError::RomMismatch { .. })
+        ));
+    }
+}
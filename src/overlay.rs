@@ -0,0 +1,153 @@
+use chip_8::inspector::{Line, Style};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+// Glyph cell in font pixels: 5x7 glyphs with a column and a row of spacing
+pub const CELL_WIDTH: u32 = 6;
+pub const CELL_HEIGHT: u32 = 8;
+
+const BACKGROUND: Color = Color::RGB(24, 24, 24);
+
+// 5x7 glyphs for ' ' to '_', one byte per column with the top row in bit 0
+const FONT: [[u8; 5]; 64] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // )
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], // *
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // 0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // @
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], // G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], // M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // V
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7F, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7F, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+];
+
+fn color(style: Style) -> Color {
+    match style {
+        Style::Normal => Color::RGB(220, 220, 220),
+        Style::Label => Color::RGB(120, 120, 120),
+        Style::ProgramCounter => Color::RGB(255, 200, 60),
+        Style::Index => Color::RGB(90, 200, 255),
+        Style::Pressed => Color::RGB(110, 230, 110),
+    }
+}
+
+fn glyph(c: char) -> &'static [u8; 5] {
+    // Lowercase shares the uppercase glyphs, anything else outside the table shows as '?'
+    let index = match c.to_ascii_uppercase() {
+        c @ ' '..='_' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    &FONT[index]
+}
+
+// Size in window pixels of a panel of `columns` x `rows` characters, margin included
+pub fn panel_size(columns: usize, rows: usize, text_scale: u32) -> (u32, u32) {
+    (
+        (columns as u32 * CELL_WIDTH + CELL_WIDTH) * text_scale,
+        (rows as u32 * CELL_HEIGHT + CELL_HEIGHT) * text_scale,
+    )
+}
+
+// Fills `area` and draws the lines into it, leaving half a cell of margin. Every glyph pixel
+// of a color is gathered first so each color costs one draw call
+pub fn draw_panel(canvas: &mut Canvas<Window>, area: Rect, lines: &[Line], text_scale: u32) {
+    canvas.set_draw_color(BACKGROUND);
+    let _ = canvas.fill_rect(area);
+
+    let styles = [
+        Style::Normal,
+        Style::Label,
+        Style::ProgramCounter,
+        Style::Index,
+        Style::Pressed,
+    ];
+    let mut pixels: [Vec<Rect>; 5] = Default::default();
+    let left = area.x() + (CELL_WIDTH * text_scale / 2) as i32;
+    let mut y = area.y() + (CELL_HEIGHT * text_scale / 2) as i32;
+    for line in lines {
+        let mut x = left;
+        for span in line {
+            let rects = &mut pixels[styles.iter().position(|&s| s == span.style).unwrap_or(0)];
+            for c in span.text.chars() {
+                for (column, bits) in glyph(c).iter().enumerate() {
+                    for row in 0..7 {
+                        if bits & (1 << row) != 0 {
+                            rects.push(Rect::new(
+                                x + (column as u32 * text_scale) as i32,
+                                y + (row * text_scale) as i32,
+                                text_scale,
+                                text_scale,
+                            ));
+                        }
+                    }
+                }
+                x += (CELL_WIDTH * text_scale) as i32;
+            }
+        }
+        y += (CELL_HEIGHT * text_scale) as i32;
+    }
+
+    for (style, rects) in styles.iter().zip(&pixels) {
+        if !rects.is_empty() {
+            canvas.set_draw_color(color(*style));
+            let _ = canvas.fill_rects(rects);
+        }
+    }
+}
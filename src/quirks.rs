@@ -0,0 +1,112 @@
+//! Behaviors that differ between CHIP-8 interpreters. Each flag picks one side of an
+//! ambiguity in the original instruction set; the presets below bundle them per platform.
+
+/// What FX55/FX65 leave in the index register afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// I is left as it was (SUPER-CHIP 1.1)
+    Unchanged,
+    /// I = I + X (CHIP-48)
+    X,
+    /// I = I + X + 1 (COSMAC VIP, XO-CHIP)
+    XPlusOne,
+}
+
+/// The quirk settings a [`Chip8`](crate::Chip8) runs with; see the platform presets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift Vy into Vx instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    /// effect of FX55/FX65 on I
+    pub index_increment: IndexIncrement,
+    /// BXNN jumps to XNN + Vx instead of NNN + V0
+    pub jump_uses_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0
+    pub logic_resets_vf: bool,
+    /// DXYN wraps sprites around the screen edges instead of clipping them
+    pub wrap_sprites: bool,
+    /// DXYN waits for the next vblank before drawing
+    pub display_wait: bool,
+    /// enables the XO-CHIP extensions: 64kb memory, bitplanes and the audio pattern buffer
+    pub xo_chip: bool,
+}
+
+impl Quirks {
+    /// What this interpreter did before quirks were configurable: shifts work on Vx in place,
+    /// FX55/FX65 leave I alone, BNNN adds V0, the logic ops keep VF and sprites are clipped
+    /// without waiting for vblank.
+    pub const LEGACY: Quirks = Quirks {
+        shift_uses_vy: false,
+        index_increment: IndexIncrement::Unchanged,
+        jump_uses_vx: false,
+        logic_resets_vf: false,
+        wrap_sprites: false,
+        display_wait: false,
+        xo_chip: false,
+    };
+
+    /// The original COSMAC VIP interpreter.
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        index_increment: IndexIncrement::XPlusOne,
+        jump_uses_vx: false,
+        logic_resets_vf: true,
+        wrap_sprites: false,
+        display_wait: true,
+        xo_chip: false,
+    };
+
+    /// CHIP-48 on the HP-48 calculators.
+    pub const CHIP_48: Quirks = Quirks {
+        shift_uses_vy: false,
+        index_increment: IndexIncrement::X,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        wrap_sprites: false,
+        display_wait: false,
+        xo_chip: false,
+    };
+
+    /// SUPER-CHIP 1.1.
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift_uses_vy: false,
+        index_increment: IndexIncrement::Unchanged,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        wrap_sprites: false,
+        display_wait: false,
+        xo_chip: false,
+    };
+
+    /// XO-CHIP as implemented by Octo, with the XO-CHIP extensions enabled.
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        index_increment: IndexIncrement::XPlusOne,
+        jump_uses_vx: false,
+        logic_resets_vf: false,
+        wrap_sprites: true,
+        display_wait: false,
+        xo_chip: true,
+    };
+}
+
+impl Quirks {
+    /// Looks up a preset by name: `legacy`, `vip`, `chip48`, `schip` or `xochip`.
+    pub fn from_preset_name(name: &str) -> Option<Quirks> {
+        match name {
+            "legacy" => Some(Quirks::LEGACY),
+            "vip" => Some(Quirks::COSMAC_VIP),
+            "chip48" => Some(Quirks::CHIP_48),
+            "schip" => Some(Quirks::SUPER_CHIP),
+            "xochip" => Some(Quirks::XO_CHIP),
+            _ => None,
+        }
+    }
+}
+
+/// [`Quirks::LEGACY`], so existing callers keep the behavior they had before quirks existed.
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::LEGACY
+    }
+}
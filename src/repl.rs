@@ -0,0 +1,59 @@
+use chip_8::debugger::{Command, Debugger, StopReason};
+use chip_8::Chip8;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// Reads debugger commands from stdin on a separate thread so the SDL loop never blocks on input
+pub struct Repl {
+    lines: Receiver<String>,
+}
+
+impl Repl {
+    pub fn spawn() -> Repl {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        print!("Debugger ready, type 'help' for commands\n> ");
+        let _ = io::stdout().flush();
+        Repl { lines }
+    }
+
+    // Runs every command typed since the last call. Returns false once the user asked to quit
+    pub fn poll(&mut self, debugger: &mut Debugger, chip8: &mut Chip8) -> bool {
+        loop {
+            let line = match self.lines.try_recv() {
+                Ok(line) => line,
+                // Nothing typed yet, or stdin closed and the window keeps running on its own
+                Err(_) => return true,
+            };
+            let line = line.trim();
+            if line == "q" || line == "quit" {
+                return false;
+            }
+            if !line.is_empty() {
+                match Command::parse(line) {
+                    Ok(command) => println!("{}", debugger.execute(command, chip8)),
+                    Err(err) => println!("{}", err),
+                }
+            }
+            prompt();
+        }
+    }
+
+    pub fn report(&self, reason: StopReason, debugger: &Debugger, chip8: &Chip8) {
+        println!("\nstopped: {}\n{}", reason, debugger.state(chip8));
+        prompt();
+    }
+}
+
+fn prompt() {
+    print!("> ");
+    let _ = io::stdout().flush();
+}
@@ -0,0 +1,246 @@
+//! Rewind: a history of recent machine states to step backwards through.
+//!
+//! Every few frames the machine is snapshotted with [`Chip8::save_state`]. Only the newest
+//! snapshot is kept whole; each older one is stored as the runs of bytes where it differs from
+//! the snapshot after it, which between nearby frames is usually a handful of registers and
+//! memory bytes. Once the history outgrows its memory budget the oldest snapshots are dropped.
+
+use crate::chip8::Chip8;
+use crate::savestate::SaveStateError;
+use std::collections::VecDeque;
+
+// A delta either holds the older snapshot whole, when its size differs, or the changed runs
+const FULL: u8 = 0;
+const RUNS: u8 = 1;
+// Runs closer together than a run header are merged into one
+const RUN_HEADER_SIZE: usize = 6;
+
+/// Snapshot history for rewinding a [`Chip8`].
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    budget: usize,
+    interval: u32,
+    frames_until_snapshot: u32,
+    latest: Option<Vec<u8>>,
+    // Newest first; each turns the snapshot in front of it into the one before that
+    deltas: VecDeque<Vec<u8>>,
+    deltas_size: usize,
+}
+
+impl Rewind {
+    /// Keeps up to `budget` bytes of history, snapshotting every `interval` frames.
+    pub fn new(budget: usize, interval: u32) -> Self {
+        Rewind {
+            budget,
+            interval: interval.max(1),
+            frames_until_snapshot: 0,
+            latest: None,
+            deltas: VecDeque::new(),
+            deltas_size: 0,
+        }
+    }
+
+    /// Call once per frame that runs; takes a snapshot every `interval` calls.
+    pub fn record(&mut self, chip8: &Chip8) {
+        if self.frames_until_snapshot > 0 {
+            self.frames_until_snapshot -= 1;
+            return;
+        }
+        self.frames_until_snapshot = self.interval - 1;
+
+        let snapshot = chip8.save_state();
+        if let Some(previous) = self.latest.take() {
+            let delta = diff(&snapshot, &previous);
+            self.deltas_size += delta.len();
+            self.deltas.push_front(delta);
+        }
+        self.latest = Some(snapshot);
+        while self.memory_used() > self.budget {
+            let Some(oldest) = self.deltas.pop_back() else {
+                break;
+            };
+            self.deltas_size -= oldest.len();
+        }
+    }
+
+    /// Restores the newest snapshot and makes the one before it the next to restore. The keypad
+    /// is left as it is, it belongs to the host. Returns false once the history is used up.
+    pub fn step_back(&mut self, chip8: &mut Chip8) -> Result<bool, SaveStateError> {
+        let Some(latest) = self.latest.take() else {
+            return Ok(false);
+        };
+        let keys = chip8.keys;
+        chip8.load_state(&latest)?;
+        chip8.keys = keys;
+
+        if let Some(delta) = self.deltas.pop_front() {
+            self.deltas_size -= delta.len();
+            self.latest = Some(apply(&latest, &delta));
+        }
+        self.frames_until_snapshot = 0;
+        Ok(true)
+    }
+
+    /// Number of snapshots that can still be restored.
+    pub fn len(&self) -> usize {
+        self.latest.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// Bytes held by the history.
+    pub fn memory_used(&self) -> usize {
+        self.latest.as_ref().map_or(0, Vec::len) + self.deltas_size
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.deltas_size = 0;
+        self.frames_until_snapshot = 0;
+    }
+}
+
+// Encodes how to get `older` back from `newer`
+fn diff(newer: &[u8], older: &[u8]) -> Vec<u8> {
+    if newer.len() != older.len() {
+        let mut delta = Vec::with_capacity(older.len() + 1);
+        delta.push(FULL);
+        delta.extend_from_slice(older);
+        return delta;
+    }
+
+    let mut delta = vec![RUNS];
+    let mut i = 0;
+    while i < older.len() {
+        if newer[i] == older[i] {
+            i += 1;
+            continue;
+        }
+        // Extend the run until the bytes agree for longer than a new header would cost
+        let start = i;
+        let mut end = i + 1;
+        let mut j = end;
+        while j < older.len() && j - end <= RUN_HEADER_SIZE && j - start < u16::MAX as usize {
+            if newer[j] != older[j] {
+                end = j + 1;
+            }
+            j += 1;
+        }
+        delta.extend_from_slice(&(start as u32).to_le_bytes());
+        delta.extend_from_slice(&((end - start) as u16).to_le_bytes());
+        delta.extend_from_slice(&older[start..end]);
+        i = end;
+    }
+    delta
+}
+
+fn apply(newer: &[u8], delta: &[u8]) -> Vec<u8> {
+    if delta[0] == FULL {
+        return delta[1..].to_vec();
+    }
+    let mut older = newer.to_vec();
+    let mut runs = &delta[1..];
+    while !runs.is_empty() {
+        let start = u32::from_le_bytes([runs[0], runs[1], runs[2], runs[3]]) as usize;
+        let len = u16::from_le_bytes([runs[4], runs[5]]) as usize;
+        older[start..start + len].copy_from_slice(&runs[RUN_HEADER_SIZE..RUN_HEADER_SIZE + len]);
+        runs = &runs[RUN_HEADER_SIZE + len..];
+    }
+    older
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    // Counts V0 up once per frame of three instructions and switches to hi-res at 3
+    const PROGRAM: [u8; 10] = [
+        0x70, 0x01, // ADD V0, 1
+        0x30, 0x03, // SE V0, 3
+        0x12, 0x00, // JP 0x200
+        0x00, 0xFF, // HIGH
+        0x12, 0x08, // JP 0x208
+    ];
+
+    // Counts V0 up once per frame of two instructions
+    fn machine() -> Chip8 {
+        let mut chip8 = Chip8::new(Quirks::SUPER_CHIP);
+        chip8.load_program(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn steps_back_through_snapshots_newest_first() {
+        let mut chip8 = machine();
+        let mut rewind = Rewind::new(1 << 20, 1);
+        for _ in 0..10 {
+            rewind.record(&chip8);
+            chip8.run_frame(2).unwrap();
+        }
+        assert_eq!(chip8.registers[0], 10);
+        assert_eq!(rewind.len(), 10);
+
+        chip8.keys[3] = 1;
+        for expected in (0..10).rev() {
+            assert_eq!(rewind.step_back(&mut chip8), Ok(true));
+            assert_eq!(chip8.registers[0], expected);
+            assert_eq!(chip8.keys[3], 1);
+        }
+        assert_eq!(rewind.step_back(&mut chip8), Ok(false));
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn deltas_are_compact_and_budget_drops_the_oldest() {
+        let mut chip8 = machine();
+        let mut rewind = Rewind::new(usize::MAX, 1);
+        rewind.record(&chip8);
+        let full = rewind.memory_used();
+        chip8.run_frame(2).unwrap();
+        rewind.record(&chip8);
+        assert!(rewind.memory_used() - full < 64);
+
+        let mut rewind = Rewind::new(full + 100, 1);
+        for _ in 0..100 {
+            rewind.record(&chip8);
+            chip8.run_frame(2).unwrap();
+        }
+        assert!(rewind.memory_used() <= full + 100);
+        assert!(rewind.len() > 1 && rewind.len() < 100);
+        // The newest snapshots survive
+        rewind.step_back(&mut chip8).unwrap();
+        assert_eq!(chip8.registers[0], 100);
+    }
+
+    #[test]
+    fn snapshots_of_different_sizes() {
+        let mut chip8 = Chip8::new(Quirks::SUPER_CHIP);
+        chip8.load_program(&PROGRAM).unwrap();
+        let mut rewind = Rewind::new(1 << 20, 1);
+        for _ in 0..5 {
+            rewind.record(&chip8);
+            chip8.run_frame(3).unwrap();
+        }
+        assert!(chip8.hires);
+        while rewind.step_back(&mut chip8).unwrap() {}
+        assert!(!chip8.hires);
+        assert_eq!(chip8.registers[0], 0);
+    }
+
+    #[test]
+    fn interval_skips_frames() {
+        let mut chip8 = machine();
+        let mut rewind = Rewind::new(1 << 20, 4);
+        for _ in 0..10 {
+            rewind.record(&chip8);
+            chip8.run_frame(2).unwrap();
+        }
+        assert_eq!(rewind.len(), 3);
+        rewind.step_back(&mut chip8).unwrap();
+        assert_eq!(chip8.registers[0], 8);
+    }
+}
@@ -0,0 +1,398 @@
+//! Save states: a versioned binary snapshot of a whole [`Chip8`] machine.
+//!
+//! A snapshot records the hash of the ROM it was taken with, and loading it into a machine
+//! running a different ROM is refused. All integers are little-endian.
+//!
+//! Version 2 added the random seed and generator position at the end; version 1 snapshots
+//! still load and leave the random source as it was. Version 3 added whether F002 has loaded
+//! an audio pattern; older snapshots count an all-zero pattern as never loaded.
+
+use crate::chip8::Chip8;
+use crate::quirks::{IndexIncrement, Quirks};
+use std::error::Error;
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"C8SS";
+/// Format version written by [`Chip8::save_state`].
+pub const SAVE_STATE_VERSION: u16 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveStateError {
+    /// The data doesn't start with the save state magic bytes
+    BadMagic,
+    /// The snapshot was written by a newer or unknown format version
+    UnsupportedVersion(u16),
+    /// The snapshot was taken with a different ROM than the one loaded
+    RomMismatch { expected: u64, found: u64 },
+    /// The data ends before the snapshot does
+    Truncated,
+    /// A field holds a value no machine could be in
+    Invalid(&'static str),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SaveStateError::BadMagic => write!(f, "not a CHIP-8 save state"),
+            SaveStateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {}", version)
+            }
+            SaveStateError::RomMismatch { expected, found } => write!(
+                f,
+                "save state belongs to ROM {:016x}, but ROM {:016x} is loaded",
+                found, expected
+            ),
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::Invalid(field) => write!(f, "save state has an invalid {}", field),
+        }
+    }
+}
+
+impl Error for SaveStateError {}
+
+impl Chip8 {
+    /// Serializes the complete machine state, including quirks and the loaded ROM's hash.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.memory.len() + self.screen.len() + 128);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&SAVE_STATE_VERSION.to_le_bytes());
+        out.extend_from_slice(&self.rom_hash.to_le_bytes());
+        out.extend_from_slice(&encode_quirks(&self.quirks));
+
+        out.extend_from_slice(&(self.memory.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.memory);
+        out.extend_from_slice(&self.registers);
+        out.extend_from_slice(&self.index_register.to_le_bytes());
+        out.extend_from_slice(&self.program_counter.to_le_bytes());
+        out.push(self.hires as u8);
+        out.push(self.selected_planes);
+        out.extend_from_slice(&(self.screen.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.screen);
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        for entry in self.stack {
+            out.extend_from_slice(&entry.to_le_bytes());
+        }
+        out.push(self.stack_pointer);
+        out.extend_from_slice(&self.keys);
+        out.extend_from_slice(&self.rpl_flags);
+        out.push(self.exited as u8);
+        out.extend_from_slice(&self.audio_pattern.unwrap_or_default());
+        out.push(self.pitch);
+        out.push(self.vblank as u8);
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        out.extend_from_slice(&self.random.state().to_le_bytes());
+        out.push(self.audio_pattern.is_some() as u8);
+        out
+    }
+
+    /// Restores a snapshot taken by [`Chip8::save_state`]. The machine is left untouched if
+    /// the snapshot is invalid or was taken with a different ROM.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = Reader::new(data);
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(SaveStateError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version == 0 || version > SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+        let rom_hash = reader.u64()?;
+        if rom_hash != self.rom_hash {
+            return Err(SaveStateError::RomMismatch {
+                expected: self.rom_hash,
+                found: rom_hash,
+            });
+        }
+
+        // Build the whole machine first so a bad snapshot can't leave it half-restored
+        let quirks = decode_quirks(reader.array()?)?;
+        let mut state = Chip8::new(quirks);
+        state.rom_hash = rom_hash;
+
+        let memory_len = reader.u32()? as usize;
+        if memory_len != state.memory.len() {
+            return Err(SaveStateError::Invalid("memory size"));
+        }
+        state.memory.copy_from_slice(reader.bytes(memory_len)?);
+        state.registers = reader.array()?;
+        state.index_register = reader.u16()?;
+        state.program_counter = reader.u16()?;
+        state.hires = reader.bool()?;
+        state.selected_planes = reader.u8()?;
+        if state.selected_planes > 0b11 {
+            return Err(SaveStateError::Invalid("plane selection"));
+        }
+        let screen_len = reader.u32()? as usize;
+        if screen_len != state.screen_width() * state.screen_height() {
+            return Err(SaveStateError::Invalid("screen size"));
+        }
+        state.screen = reader.bytes(screen_len)?.to_vec();
+        state.delay_timer = reader.u8()?;
+        state.sound_timer = reader.u8()?;
+        for entry in state.stack.iter_mut() {
+            *entry = reader.u16()?;
+        }
+        state.stack_pointer = reader.u8()?;
+        if state.stack_pointer as usize > state.stack.len() {
+            return Err(SaveStateError::Invalid("stack pointer"));
+        }
+        state.keys = reader.array()?;
+        state.rpl_flags = reader.array()?;
+        state.exited = reader.bool()?;
+        let audio_pattern: [u8; 16] = reader.array()?;
+        state.pitch = reader.u8()?;
+        state.vblank = reader.bool()?;
+
+        let random = if version >= 2 {
+            Some((reader.u64()?, reader.u64()?))
+        } else {
+            None
+        };
+        let audio_pattern_loaded = if version >= 3 {
+            reader.bool()?
+        } else {
+            audio_pattern != [0; 16]
+        };
+        state.audio_pattern = Some(audio_pattern).filter(|_| audio_pattern_loaded);
+
+        // The random source is kept, only its position is restored
+        std::mem::swap(&mut state.random, &mut self.random);
+        // So is the tracer, it isn't machine state
+        std::mem::swap(&mut state.tracer, &mut self.tracer);
+        state.seed = self.seed;
+        if let Some((seed, position)) = random {
+            state.seed = seed;
+            state.random.restore(position);
+        }
+
+        *self = state;
+        Ok(())
+    }
+}
+
+pub(crate) fn encode_quirks(quirks: &Quirks) -> [u8; 2] {
+    let flags = [
+        quirks.shift_uses_vy,
+        quirks.jump_uses_vx,
+        quirks.logic_resets_vf,
+        quirks.wrap_sprites,
+        quirks.display_wait,
+        quirks.xo_chip,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0u8, |flags, (bit, &set)| flags | ((set as u8) << bit));
+    let index_increment = match quirks.index_increment {
+        IndexIncrement::Unchanged => 0,
+        IndexIncrement::X => 1,
+        IndexIncrement::XPlusOne => 2,
+    };
+    [flags, index_increment]
+}
+
+pub(crate) fn decode_quirks([flags, index_increment]: [u8; 2]) -> Result<Quirks, SaveStateError> {
+    let flag = |bit: u8| flags & (1 << bit) != 0;
+    Ok(Quirks {
+        shift_uses_vy: flag(0),
+        jump_uses_vx: flag(1),
+        logic_resets_vf: flag(2),
+        wrap_sprites: flag(3),
+        display_wait: flag(4),
+        xo_chip: flag(5),
+        index_increment: match index_increment {
+            0 => IndexIncrement::Unchanged,
+            1 => IndexIncrement::X,
+            2 => IndexIncrement::XPlusOne,
+            _ => return Err(SaveStateError::Invalid("quirk setting")),
+        },
+    })
+}
+
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        let end = self.pos + len;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or(SaveStateError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Invalid("flag")),
+        }
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LD V0, 0x2A; RND V1, 0xFF; CALL 0x208; JP 0x206; then LD F, V0; DRW V0, V0, 5; RET
+    const PROGRAM: [u8; 14] = [
+        0x60, 0x2A, 0xC1, 0xFF, 0x22, 0x08, 0x12, 0x06, 0xF0, 0x29, 0xD0, 0x05, 0x00, 0xEE,
+    ];
+
+    fn machine() -> Chip8 {
+        let mut chip8 = Chip8::new(Quirks::SUPER_CHIP);
+        chip8.load_program(&PROGRAM).unwrap();
+        chip8.set_seed(7);
+        chip8
+    }
+
+    fn saved() -> Vec<u8> {
+        let mut chip8 = machine();
+        chip8.run_frame(5).unwrap();
+        chip8.save_state()
+    }
+
+    #[test]
+    fn round_trips_byte_for_byte() {
+        let mut original = machine();
+        original.run_frame(5).unwrap();
+        let data = original.save_state();
+        assert_eq!(data, saved());
+
+        let mut chip8 = machine();
+        chip8.load_state(&data).unwrap();
+        assert_eq!(chip8.save_state(), data);
+        assert_eq!(chip8.registers[0], 0x2A);
+        assert_eq!(chip8.stack_pointer, 1);
+        assert!(chip8.screen.iter().any(|&pixel| pixel != 0));
+
+        // The restored random source continues where the original one was
+        original.program_counter = 0x202;
+        chip8.program_counter = 0x202;
+        original.emulate_cycle().unwrap();
+        chip8.emulate_cycle().unwrap();
+        assert_eq!(chip8.registers[1], original.registers[1]);
+    }
+
+    #[test]
+    fn rejects_bad_headers_and_data() {
+        let data = saved();
+        let load = |data: &[u8]| {
+            let mut chip8 = machine();
+            let result = chip8.load_state(data);
+            // A failed load leaves the machine as it was
+            if result.is_err() {
+                assert_eq!(chip8.save_state(), machine().save_state());
+            }
+            result
+        };
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(load(&bad_magic), Err(SaveStateError::BadMagic));
+
+        for version in [0, SAVE_STATE_VERSION + 1] {
+            let mut unsupported = data.clone();
+            unsupported[4..6].copy_from_slice(&version.to_le_bytes());
+            assert_eq!(
+                load(&unsupported),
+                Err(SaveStateError::UnsupportedVersion(version))
+            );
+        }
+
+        for len in [0, 3, 20, data.len() - 1] {
+            assert_eq!(load(&data[..len]), Err(SaveStateError::Truncated));
+        }
+
+        // The index increment setting follows the magic, version, ROM hash and quirk flags
+        let mut invalid = data.clone();
+        invalid[15] = 3;
+        assert_eq!(
+            load(&invalid),
+            Err(SaveStateError::Invalid("quirk setting"))
+        );
+    }
+
+    #[test]
+    fn refuses_a_state_from_another_rom() {
+        let data = saved();
+        let mut other = Chip8::new(Quirks::SUPER_CHIP);
+        other.load_program(&[0x00, 0xE0]).unwrap();
+        let expected = other.rom_hash;
+        assert_eq!(
+            other.load_state(&data),
+            Err(SaveStateError::RomMismatch {
+                expected,
+                found: machine().rom_hash
+            })
+        );
+    }
+
+    #[test]
+    fn loads_version_1_without_the_random_state() {
+        // Version 1 is the current layout without the trailing seed, generator position and
+        // audio pattern flag
+        let mut data = saved();
+        data[4..6].copy_from_slice(&1u16.to_le_bytes());
+        data.truncate(data.len() - 17);
+
+        let mut chip8 = machine();
+        chip8.set_seed(99);
+        chip8.load_state(&data).unwrap();
+        assert_eq!(chip8.registers[0], 0x2A);
+        assert_eq!(chip8.seed, 99);
+        // Everything after the version matches, up to where version 2 appends the random state
+        assert_eq!(chip8.save_state()[6..data.len()], data[6..]);
+    }
+
+    #[test]
+    fn keeps_whether_an_audio_pattern_was_loaded() {
+        // I points at zeroed memory, so F002 loads a silent pattern
+        let mut original = Chip8::new(Quirks::XO_CHIP);
+        original.load_program(&[0xA4, 0x00, 0xF0, 0x02]).unwrap();
+        original.run_frame(2).unwrap();
+        assert_eq!(original.audio_pattern, Some([0; 16]));
+
+        let mut chip8 = Chip8::new(Quirks::XO_CHIP);
+        chip8.load_program(&[0xA4, 0x00, 0xF0, 0x02]).unwrap();
+        chip8.load_state(&original.save_state()).unwrap();
+        assert_eq!(chip8.audio_pattern, Some([0; 16]));
+
+        // Before version 3 only a pattern with a bit set counts as loaded
+        let mut data = original.save_state();
+        data[4..6].copy_from_slice(&2u16.to_le_bytes());
+        data.pop();
+        chip8.load_state(&data).unwrap();
+        assert_eq!(chip8.audio_pattern, None);
+    }
+}
@@ -0,0 +1,488 @@
+//! Execution traces: one record per instruction with the machine state around it.
+//!
+//! A [`Tracer`] attached with [`Chip8::set_tracer`] is handed every instruction
+//! [`Chip8::emulate_cycle`] runs, with PC, opcode, mnemonic and the registers, I, timers and
+//! stack pointer before and after it. Records are written as text or JSON lines:
+//!
+//! ```text
+//! PC:0200 OP:602A I:0000 V0:00 V1:00 V2:00 V3:00 V4:00 V5:00 V6:00 V7:00 V8:00 V9:00 VA:00 VB:00 VC:00 VD:00 VE:00 VF:00 DT:00 ST:00 SP:00 ; LD V0, 0x2A -> PC:0202 V0:2A
+//! ```
+//!
+//! Everything before ` ; ` is the state before the instruction in the layout common reference
+//! emulators log, so traces from them can be diffed against the prefix. The mnemonic and the
+//! values the instruction changed follow. JSON records also carry the call stack and a hash of
+//! the screen, for [`tracediff`](crate::tracediff) to compare.
+//!
+//! A [`TraceFilter`] limits tracing to an address range and to opcode classes, and
+//! [`RotatingFile`] keeps long traces from filling the disk.
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use crate::instruction::Instruction;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+/// How trace records are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    /// One line per instruction, see the [module docs](self)
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Which instructions get traced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Only instructions at these addresses, or everywhere when None
+    pub addresses: Option<RangeInclusive<u16>>,
+    /// Opcode classes to trace, bit N for opcodes NXXX
+    pub opcode_classes: u16,
+}
+
+impl Default for TraceFilter {
+    fn default() -> Self {
+        TraceFilter {
+            addresses: None,
+            opcode_classes: 0xFFFF,
+        }
+    }
+}
+
+impl TraceFilter {
+    pub fn matches(&self, pc: u16, opcode: u16) -> bool {
+        let in_range = self
+            .addresses
+            .as_ref()
+            .is_none_or(|addresses| addresses.contains(&pc));
+        in_range && self.opcode_classes & (1 << (opcode >> 12)) != 0
+    }
+}
+
+/// The part of the machine a trace shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceState {
+    pub program_counter: u16,
+    pub registers: [u8; 16],
+    pub index_register: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub stack_pointer: u8,
+    pub stack: [u16; 16],
+    /// [`Chip8::screen_hash`] of the display
+    pub screen_hash: u64,
+}
+
+impl TraceState {
+    pub fn capture(chip8: &Chip8) -> TraceState {
+        TraceState {
+            program_counter: chip8.program_counter,
+            registers: chip8.registers,
+            index_register: chip8.index_register,
+            delay_timer: chip8.delay_timer,
+            sound_timer: chip8.sound_timer,
+            stack_pointer: chip8.stack_pointer,
+            stack: chip8.stack,
+            screen_hash: chip8.screen_hash(),
+        }
+    }
+
+    /// The return addresses on the stack, oldest first.
+    pub fn call_stack(&self) -> &[u16] {
+        &self.stack[..(self.stack_pointer as usize).min(self.stack.len())]
+    }
+}
+
+/// One executed instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// Instructions executed before this one since the tracer was attached, traced or not
+    pub cycle: u64,
+    pub opcode: u16,
+    pub mnemonic: String,
+    pub before: TraceState,
+    pub after: TraceState,
+    /// Set when the instruction faulted
+    pub fault: Option<String>,
+}
+
+/// Writes [`TraceRecord`]s for the instructions its filter lets through.
+///
+/// Write errors don't stop the machine; the first one ends tracing and is returned by
+/// [`Tracer::finish`].
+pub struct Tracer {
+    writer: Box<dyn Write + Send>,
+    format: TraceFormat,
+    filter: TraceFilter,
+    cycle: u64,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new(
+        writer: impl Write + Send + 'static,
+        format: TraceFormat,
+        filter: TraceFilter,
+    ) -> Self {
+        Tracer {
+            writer: Box::new(writer),
+            format,
+            filter,
+            cycle: 0,
+            error: None,
+        }
+    }
+
+    /// Counts an instruction about to run and says whether to trace it.
+    pub(crate) fn begin(&mut self, pc: u16, opcode: u16) -> bool {
+        self.cycle += 1;
+        self.error.is_none() && self.filter.matches(pc, opcode)
+    }
+
+    /// Records the instruction [`Tracer::begin`] let through.
+    pub(crate) fn record(
+        &mut self,
+        chip8: &Chip8,
+        opcode: u16,
+        before: TraceState,
+        result: &Result<(), Chip8Error>,
+    ) {
+        let record = TraceRecord {
+            cycle: self.cycle - 1,
+            opcode,
+            mnemonic: mnemonic(chip8, before.program_counter, opcode),
+            before,
+            after: TraceState::capture(chip8),
+            fault: result.as_ref().err().map(Chip8Error::to_string),
+        };
+        let line = match self.format {
+            TraceFormat::Text => text_line(&record),
+            TraceFormat::Json => json_line(&record),
+        };
+        if let Err(err) = self.writer.write_all(line.as_bytes()) {
+            self.error = Some(err);
+        }
+    }
+
+    /// Flushes the output and reports the first write error, if any.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.writer.flush()
+    }
+}
+
+// Like Instruction's Display, but with the address of F000 NNNN filled in and the operands
+// as the machine's quirks use them
+pub(crate) fn mnemonic(chip8: &Chip8, pc: u16, opcode: u16) -> String {
+    match Instruction::decode(opcode) {
+        Ok(Instruction::LdILong) => {
+            let address = pc as usize + 2;
+            match chip8.memory.get(address..address + 2) {
+                Some(&[high, low]) => format!("LD I, 0x{:04X}", u16::from_be_bytes([high, low])),
+                _ => Instruction::LdILong.to_string(),
+            }
+        }
+        Ok(instruction) => {
+            instruction.format_with(&chip8.quirks, &|addr| format!("0x{:03X}", addr))
+        }
+        Err(_) => "???".to_string(),
+    }
+}
+
+/// Formats the state an instruction runs in the way reference emulators log it: the part of a
+/// text line before ` ; `.
+pub fn text_state(state: &TraceState, opcode: u16) -> String {
+    let mut text = format!(
+        "PC:{:04X} OP:{:04X} I:{:04X}",
+        state.program_counter, opcode, state.index_register
+    );
+    for (x, value) in state.registers.iter().enumerate() {
+        let _ = write!(text, " V{:X}:{:02X}", x, value);
+    }
+    let _ = write!(
+        text,
+        " DT:{:02X} ST:{:02X} SP:{:02X}",
+        state.delay_timer, state.sound_timer, state.stack_pointer
+    );
+    text
+}
+
+/// Formats a record as a line of the text format, newline included.
+pub fn text_line(record: &TraceRecord) -> String {
+    let before = &record.before;
+    let after = &record.after;
+    let mut line = text_state(before, record.opcode);
+    let _ = write!(line, " ; {}", record.mnemonic);
+
+    line.push_str(" ->");
+    if after.program_counter != before.program_counter {
+        let _ = write!(line, " PC:{:04X}", after.program_counter);
+    }
+    if after.index_register != before.index_register {
+        let _ = write!(line, " I:{:04X}", after.index_register);
+    }
+    for (x, (old, new)) in before.registers.iter().zip(&after.registers).enumerate() {
+        if old != new {
+            let _ = write!(line, " V{:X}:{:02X}", x, new);
+        }
+    }
+    let scalars = [
+        ("DT", before.delay_timer, after.delay_timer),
+        ("ST", before.sound_timer, after.sound_timer),
+        ("SP", before.stack_pointer, after.stack_pointer),
+    ];
+    for (name, old, new) in scalars {
+        if old != new {
+            let _ = write!(line, " {}:{:02X}", name, new);
+        }
+    }
+    if let Some(fault) = &record.fault {
+        let _ = write!(line, " fault: {}", fault);
+    }
+    line.push('\n');
+    line
+}
+
+/// Formats a record as a JSON object on one line, newline included.
+pub fn json_line(record: &TraceRecord) -> String {
+    let mut line = format!(
+        "{{\"cycle\":{},\"pc\":{},\"opcode\":{},\"mnemonic\":\"{}\",\"before\":{},\"after\":{}",
+        record.cycle,
+        record.before.program_counter,
+        record.opcode,
+        json_escape(&record.mnemonic),
+        json_state(&record.before),
+        json_state(&record.after)
+    );
+    if let Some(fault) = &record.fault {
+        let _ = write!(line, ",\"fault\":\"{}\"", json_escape(fault));
+    }
+    line.push_str("}\n");
+    line
+}
+
+fn json_state(state: &TraceState) -> String {
+    let registers: Vec<String> = state.registers.iter().map(u8::to_string).collect();
+    let stack: Vec<String> = state.call_stack().iter().map(u16::to_string).collect();
+    format!(
+        "{{\"pc\":{},\"v\":[{}],\"i\":{},\"dt\":{},\"st\":{},\"sp\":{},\"stack\":[{}],\"screen\":\"{:016x}\"}}",
+        state.program_counter,
+        registers.join(","),
+        state.index_register,
+        state.delay_timer,
+        state.sound_timer,
+        state.stack_pointer,
+        stack.join(","),
+        state.screen_hash
+    )
+}
+
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A file that's rotated once it reaches a size: `trace.log` becomes `trace.log.1`, the old
+/// `trace.log.1` becomes `trace.log.2` and so on, keeping up to `keep` old files. Each write
+/// lands whole in one file, so a trace line is never split across two.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: u32,
+    file: BufWriter<File>,
+    written: u64,
+}
+
+impl RotatingFile {
+    pub fn create(path: impl AsRef<Path>, max_bytes: u64, keep: u32) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = BufWriter::new(File::create(&path)?);
+        Ok(RotatingFile {
+            path,
+            max_bytes,
+            keep,
+            file,
+            written: 0,
+        })
+    }
+
+    fn rotated_path(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        for index in (1..self.keep).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        if self.keep > 0 {
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = BufWriter::new(File::create(&self.path)?);
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.written += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+    use std::sync::{Arc, Mutex};
+
+    // Lets a test read what the tracer wrote
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const PROGRAM: [u8; 8] = [
+        0x60, 0x2A, // LD V0, 0x2A
+        0xA3, 0x00, // LD I, 0x300
+        0xF0, 0x15, // LD DT, V0
+        0x12, 0x00, // JP 0x200
+    ];
+
+    fn trace(format: TraceFormat, filter: TraceFilter, cycles: usize) -> Vec<String> {
+        let output = Shared::default();
+        let mut chip8 = Chip8::new(Quirks::default());
+        chip8.load_program(&PROGRAM).unwrap();
+        chip8.set_tracer(Some(Tracer::new(output.clone(), format, filter)));
+        for _ in 0..cycles {
+            chip8.emulate_cycle().unwrap();
+        }
+        chip8.take_tracer().unwrap().finish().unwrap();
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn text_lines_show_state_before_and_changes_after() {
+        let lines = trace(TraceFormat::Text, TraceFilter::default(), 3);
+        assert_eq!(
+            lines[0],
+            "PC:0200 OP:602A I:0000 V0:00 V1:00 V2:00 V3:00 V4:00 V5:00 V6:00 V7:00 V8:00 \
+             V9:00 VA:00 VB:00 VC:00 VD:00 VE:00 VF:00 DT:00 ST:00 SP:00 ; LD V0, 0x2A \
+             -> PC:0202 V0:2A"
+        );
+        assert!(lines[2].starts_with("PC:0204 OP:F015 I:0300 V0:2A"));
+        assert!(lines[2].ends_with("; LD DT, V0 -> PC:0206 DT:2A"));
+    }
+
+    #[test]
+    fn json_lines() {
+        let lines = trace(TraceFormat::Json, TraceFilter::default(), 1);
+        let screen = Chip8::new(Quirks::default()).screen_hash();
+        assert_eq!(
+            lines[0],
+            format!(
+                "{{\"cycle\":0,\"pc\":512,\"opcode\":24618,\"mnemonic\":\"LD V0, 0x2A\",\
+                 \"before\":{{\"pc\":512,\"v\":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],\"i\":0,\"dt\":0,\"st\":0,\"sp\":0,\"stack\":[],\"screen\":\"{0:016x}\"}},\
+                 \"after\":{{\"pc\":514,\"v\":[42,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],\"i\":0,\"dt\":0,\"st\":0,\"sp\":0,\"stack\":[],\"screen\":\"{0:016x}\"}}}}",
+                screen
+            )
+        );
+    }
+
+    #[test]
+    fn filters_by_address_and_opcode_class() {
+        let by_address = TraceFilter {
+            addresses: Some(0x202..=0x204),
+            ..TraceFilter::default()
+        };
+        let lines = trace(TraceFormat::Json, by_address, 8);
+        let cycles: Vec<&str> = lines.iter().map(|line| &line[9..11]).collect();
+        assert_eq!(cycles, ["1,", "2,", "5,", "6,"]);
+
+        let jumps_and_loads = TraceFilter {
+            opcode_classes: (1 << 0x1) | (1 << 0xA),
+            ..TraceFilter::default()
+        };
+        let lines = trace(TraceFormat::Text, jumps_and_loads, 8);
+        assert_eq!(lines.len(), 4);
+        assert!(lines
+            .iter()
+            .all(|line| line.contains("; JP") || line.contains("; LD I")));
+    }
+
+    #[test]
+    fn faults_are_recorded() {
+        let output = Shared::default();
+        let mut chip8 = Chip8::new(Quirks::default());
+        chip8.load_program(&[0x00, 0xEE]).unwrap(); // RET with an empty stack
+        chip8.set_tracer(Some(Tracer::new(
+            output.clone(),
+            TraceFormat::Text,
+            TraceFilter::default(),
+        )));
+        assert!(chip8.emulate_cycle().is_err());
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert!(text.contains("; RET -> "), "{}", text);
+        assert!(text.contains("fault: "), "{}", text);
+    }
+
+    #[test]
+    fn rotating_file_keeps_whole_writes_and_old_files() {
+        let dir = std::env::temp_dir().join(format!("chip8-trace-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("trace.log");
+        let mut file = RotatingFile::create(&path, 10, 2).unwrap();
+        for line in [
+            "aaaa\n", "bbbb\n", "cccc\n", "dddd\n", "eeee\n", "ffff\n", "gggg\n",
+        ] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("trace.log"), "gggg\n");
+        assert_eq!(read("trace.log.1"), "eeee\nffff\n");
+        assert_eq!(read("trace.log.2"), "cccc\ndddd\n");
+        assert!(!dir.join("trace.log.3").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,770 @@
+//! Differential testing against execution traces from other emulators or older builds.
+//!
+//! A reference trace lists the machine state before each instruction. [`parse`] reads the text
+//! and JSON lines formats [`trace`](crate::trace) writes, and also other emulators' text logs
+//! made of `KEY:VALUE` (or `KEY=VALUE`) fields in hex: `PC`, `OP`, `I`, `V0`-`VF`, `DT`, `ST`,
+//! `SP`, `STACK` (comma separated, oldest first) and `SCREEN` (a [`Chip8::screen_hash`]). Fields
+//! a trace doesn't have aren't compared, unknown ones are ignored and a ` ; ` ends a line.
+//!
+//! [`compare`] then runs the ROM with the same key input and checks every traced state against
+//! the machine, stopping at the first instruction where they diverge. JSON records carry the
+//! cycle they were taken at, so filtered traces with gaps compare too; text records are taken
+//! to be consecutive instructions.
+
+use crate::chip8::Chip8;
+use crate::headless::KeyScript;
+use crate::trace::{self, TraceState};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+/// The state a reference trace recorded before one instruction.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Expected {
+    /// Line of the trace the record came from, counting from 1
+    pub line: usize,
+    /// The record's text, for showing in reports
+    pub text: String,
+    /// Instructions executed before this one
+    pub cycle: u64,
+    pub program_counter: u16,
+    pub opcode: Option<u16>,
+    pub index_register: Option<u16>,
+    pub registers: [Option<u8>; 16],
+    pub delay_timer: Option<u8>,
+    pub sound_timer: Option<u8>,
+    pub stack_pointer: Option<u8>,
+    pub stack: Option<Vec<u16>>,
+    pub screen_hash: Option<u64>,
+}
+
+/// A line of a reference trace that couldn't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TraceParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for TraceParseError {}
+
+/// Parses a reference trace, one record per line. Blank lines and lines starting with `#` are
+/// skipped; lines starting with `{` are JSON.
+pub fn parse(text: &str) -> Result<Vec<Expected>, TraceParseError> {
+    let mut records: Vec<Expected> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: String| TraceParseError {
+            line: index + 1,
+            message,
+        };
+        let next_cycle = records.last().map_or(0, |previous| previous.cycle + 1);
+        let mut record = if line.starts_with('{') {
+            parse_json_record(line, next_cycle).map_err(error)?
+        } else {
+            parse_text_record(line, next_cycle).map_err(error)?
+        };
+        if next_cycle > 0 && record.cycle < next_cycle {
+            return Err(error(format!(
+                "cycle {} doesn't come after the previous record's",
+                record.cycle
+            )));
+        }
+        record.line = index + 1;
+        record.text = line.to_string();
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn parse_text_record(line: &str, cycle: u64) -> Result<Expected, String> {
+    let fields = line.split(';').next().unwrap_or_default();
+    let mut record = Expected {
+        cycle,
+        ..Expected::default()
+    };
+    let mut program_counter = None;
+    for field in fields.split_whitespace() {
+        let Some((key, value)) = field.split_once([':', '=']) else {
+            continue;
+        };
+        let key = key.to_ascii_uppercase();
+        let invalid = || format!("invalid value '{}' for {}", value, key);
+        let hex = || parse_hex(value).ok_or_else(invalid);
+        let byte = || hex().and_then(|value| u8::try_from(value).map_err(|_| invalid()));
+        let word = || hex().and_then(|value| u16::try_from(value).map_err(|_| invalid()));
+        match key.as_str() {
+            "PC" => program_counter = Some(word()?),
+            "OP" | "OPCODE" => record.opcode = Some(word()?),
+            "I" => record.index_register = Some(word()?),
+            "DT" => record.delay_timer = Some(byte()?),
+            "ST" => record.sound_timer = Some(byte()?),
+            "SP" => record.stack_pointer = Some(byte()?),
+            "SCREEN" => record.screen_hash = Some(hex()?),
+            "STACK" => {
+                let entries = value
+                    .split(',')
+                    .filter(|entry| !entry.is_empty())
+                    .map(|entry| parse_hex(entry).and_then(|entry| u16::try_from(entry).ok()));
+                record.stack = Some(entries.collect::<Option<_>>().ok_or_else(invalid)?);
+            }
+            _ => {
+                if let Some(x) = key
+                    .strip_prefix('V')
+                    .filter(|digit| digit.len() == 1)
+                    .and_then(|digit| u8::from_str_radix(digit, 16).ok())
+                {
+                    record.registers[x as usize] = Some(byte()?);
+                }
+            }
+        }
+    }
+    record.program_counter = program_counter.ok_or("no PC field")?;
+    Ok(record)
+}
+
+fn parse_hex(text: &str) -> Option<u64> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u64::from_str_radix(digits, 16).ok()
+}
+
+// Reads a record written by the tracer's JSON format, or a flat object with the same keys
+fn parse_json_record(line: &str, cycle: u64) -> Result<Expected, String> {
+    let value = Json::parse(line)?;
+    let state = value.get("before").unwrap_or(&value);
+    let field = |key: &str| state.get(key).or_else(|| value.get(key));
+    let number = |key: &str, max: u64| -> Result<Option<u64>, String> {
+        match field(key) {
+            None | Some(Json::Null) => Ok(None),
+            Some(json) => json
+                .as_integer()
+                .filter(|&number| number <= max)
+                .map(Some)
+                .ok_or_else(|| format!("invalid value for \"{}\"", key)),
+        }
+    };
+    let byte = |key: &str| number(key, u8::MAX as u64).map(|value| value.map(|v| v as u8));
+    let word = |key: &str| number(key, u16::MAX as u64).map(|value| value.map(|v| v as u16));
+
+    let mut record = Expected {
+        cycle: number("cycle", u64::MAX)?.unwrap_or(cycle),
+        program_counter: word("pc")?.ok_or("no \"pc\" field")?,
+        opcode: word("opcode")?,
+        index_register: word("i")?,
+        delay_timer: byte("dt")?,
+        sound_timer: byte("st")?,
+        stack_pointer: byte("sp")?,
+        ..Expected::default()
+    };
+    if let Some(registers) = field("v") {
+        let registers = registers.as_array().ok_or("\"v\" isn't an array")?;
+        if registers.len() > 16 {
+            return Err("\"v\" has more than 16 registers".to_string());
+        }
+        for (x, register) in registers.iter().enumerate() {
+            let value = register.as_integer().filter(|&value| value <= 0xFF);
+            record.registers[x] = Some(value.ok_or("invalid register in \"v\"")? as u8);
+        }
+    }
+    if let Some(stack) = field("stack") {
+        let entries = stack.as_array().ok_or("\"stack\" isn't an array")?;
+        let entries = entries.iter().map(|entry| {
+            entry
+                .as_integer()
+                .and_then(|entry| u16::try_from(entry).ok())
+        });
+        record.stack = Some(
+            entries
+                .collect::<Option<_>>()
+                .ok_or("invalid entry in \"stack\"")?,
+        );
+    }
+    if let Some(screen) = field("screen") {
+        record.screen_hash = Some(screen.as_integer().ok_or("invalid value for \"screen\"")?);
+    }
+    Ok(record)
+}
+
+/// A value that differs between the reference and this emulator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub field: String,
+    pub expected: String,
+    pub found: String,
+}
+
+impl Expected {
+    /// The fields of this record that don't match `state`.
+    pub fn mismatches(&self, state: &TraceState, opcode: Option<u16>) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        let mut check = |field: &str, expected: Option<String>, found: String| {
+            if let Some(expected) = expected.filter(|expected| *expected != found) {
+                mismatches.push(Mismatch {
+                    field: field.to_string(),
+                    expected,
+                    found,
+                });
+            }
+        };
+        check(
+            "PC",
+            Some(format!("{:04X}", self.program_counter)),
+            format!("{:04X}", state.program_counter),
+        );
+        check(
+            "OP",
+            self.opcode.map(|opcode| format!("{:04X}", opcode)),
+            opcode.map_or("none".to_string(), |opcode| format!("{:04X}", opcode)),
+        );
+        check(
+            "I",
+            self.index_register.map(|i| format!("{:04X}", i)),
+            format!("{:04X}", state.index_register),
+        );
+        for (x, (expected, found)) in self.registers.iter().zip(&state.registers).enumerate() {
+            check(
+                &format!("V{:X}", x),
+                expected.map(|value| format!("{:02X}", value)),
+                format!("{:02X}", found),
+            );
+        }
+        check(
+            "DT",
+            self.delay_timer.map(|dt| format!("{:02X}", dt)),
+            format!("{:02X}", state.delay_timer),
+        );
+        check(
+            "ST",
+            self.sound_timer.map(|st| format!("{:02X}", st)),
+            format!("{:02X}", state.sound_timer),
+        );
+        check(
+            "SP",
+            self.stack_pointer.map(|sp| format!("{:02X}", sp)),
+            format!("{:02X}", state.stack_pointer),
+        );
+        check(
+            "stack",
+            self.stack.as_deref().map(format_stack),
+            format_stack(state.call_stack()),
+        );
+        check(
+            "screen",
+            self.screen_hash.map(|hash| format!("{:016x}", hash)),
+            format!("{:016x}", state.screen_hash),
+        );
+        mismatches
+    }
+}
+
+fn format_stack(stack: &[u16]) -> String {
+    let entries: Vec<String> = stack.iter().map(|entry| format!("{:04X}", entry)).collect();
+    format!("[{}]", entries.join(","))
+}
+
+/// Where the machine first stopped matching the reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Index of the diverging record in the reference
+    pub record: usize,
+    pub line: usize,
+    pub mismatches: Vec<Mismatch>,
+    /// The records before the diverging one and the diverging one itself, as the reference
+    /// logged them and in this emulator's text format
+    pub context: Vec<(String, String)>,
+    /// Reference records following the diverging one
+    pub following: Vec<String>,
+    /// The display when the screen diverged
+    pub screen: Option<String>,
+}
+
+/// The outcome of [`compare`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffReport {
+    /// Records that matched
+    pub matched: usize,
+    pub divergence: Option<Divergence>,
+}
+
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(divergence) = &self.divergence else {
+            return writeln!(f, "All {} records match", self.matched);
+        };
+        writeln!(
+            f,
+            "Diverged at record {} (line {}) after {} matching records:",
+            divergence.record + 1,
+            divergence.line,
+            self.matched
+        )?;
+        for mismatch in &divergence.mismatches {
+            writeln!(
+                f,
+                "  {}: expected {}, found {}",
+                mismatch.field, mismatch.expected, mismatch.found
+            )?;
+        }
+        writeln!(f, "\nReference, then this emulator:")?;
+        let last = divergence.context.len().saturating_sub(1);
+        for (index, (reference, ours)) in divergence.context.iter().enumerate() {
+            let marker = if index == last { '>' } else { ' ' };
+            writeln!(f, "{} {}\n  {}", marker, reference, ours)?;
+        }
+        if !divergence.following.is_empty() {
+            writeln!(f, "\nReference continues:")?;
+            for reference in &divergence.following {
+                writeln!(f, "  {}", reference)?;
+            }
+        }
+        if let Some(screen) = &divergence.screen {
+            writeln!(f, "\nScreen in this emulator:\n{}", screen)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `chip8` alongside `reference`, feeding it `keys` at frame starts and ticking timers
+/// after every `instructions_per_frame` instructions like [`headless::run`](crate::headless::run),
+/// and reports the first record that doesn't match. `context` records before and after the
+/// divergence are included in the report.
+pub fn compare(
+    chip8: &mut Chip8,
+    instructions_per_frame: usize,
+    keys: &KeyScript,
+    reference: &[Expected],
+    context: usize,
+) -> DiffReport {
+    let instructions_per_frame = instructions_per_frame.max(1) as u64;
+    let mut cycle: u64 = 0;
+    // This emulator's side of the last `context` matching records
+    let mut history: VecDeque<(String, String)> = VecDeque::new();
+
+    for (index, expected) in reference.iter().enumerate() {
+        let mut mismatches = Vec::new();
+        while cycle < expected.cycle && mismatches.is_empty() {
+            if cycle.is_multiple_of(instructions_per_frame) {
+                keys.apply(chip8, cycle / instructions_per_frame);
+            }
+            let result = if chip8.exited {
+                Err("the program exited".to_string())
+            } else {
+                chip8.emulate_cycle().map_err(|err| err.to_string())
+            };
+            if let Err(err) = result {
+                mismatches.push(Mismatch {
+                    field: "execution".to_string(),
+                    expected: format!("{} more instructions", expected.cycle - cycle),
+                    found: err,
+                });
+            }
+            cycle += 1;
+            if cycle.is_multiple_of(instructions_per_frame) {
+                chip8.tick_timers();
+            }
+        }
+
+        let state = TraceState::capture(chip8);
+        let opcode = chip8.fetch_opcode().ok();
+        let ours = trace::text_state(&state, opcode.unwrap_or(0));
+        if mismatches.is_empty() {
+            mismatches = expected.mismatches(&state, opcode);
+        }
+        if !mismatches.is_empty() {
+            let screen = mismatches
+                .iter()
+                .any(|mismatch| mismatch.field == "screen")
+                .then(|| chip8.screen_ascii());
+            history.push_back((expected.text.clone(), ours));
+            return DiffReport {
+                matched: index,
+                divergence: Some(Divergence {
+                    record: index,
+                    line: expected.line,
+                    mismatches,
+                    context: history.into(),
+                    following: reference[index + 1..]
+                        .iter()
+                        .take(context)
+                        .map(|record| record.text.clone())
+                        .collect(),
+                    screen,
+                }),
+            };
+        }
+
+        if history.len() == context {
+            history.pop_front();
+        }
+        if context > 0 {
+            history.push_back((expected.text.clone(), ours));
+        }
+    }
+    DiffReport {
+        matched: reference.len(),
+        divergence: None,
+    }
+}
+
+// Just enough JSON for trace records
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = JsonParser {
+            bytes: text.as_bytes(),
+            position: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.position != parser.bytes.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    // Integers are numbers, or strings of hex digits like "0x200"
+    fn as_integer(&self) -> Option<u64> {
+        match self {
+            Json::Number(number) if *number >= 0.0 && number.fract() == 0.0 => Some(*number as u64),
+            Json::String(text) => parse_hex(text),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("invalid JSON at column {}: {}", self.position + 1, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.position)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.bytes.get(self.position) != Some(&byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if !self.bytes[self.position..].starts_with(word.as_bytes()) {
+            return Err(self.error("unexpected character"));
+        }
+        self.position += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.bytes.get(self.position) {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of line")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.bytes.get(self.position) == Some(&b'}') {
+            self.position += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(b':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.bytes.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(fields));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.bytes.get(self.position) == Some(&b']') {
+            self.position += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.bytes.get(self.position) {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(values));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut text = String::new();
+        loop {
+            let start = self.position;
+            while !matches!(self.bytes.get(self.position), Some(b'"' | b'\\') | None) {
+                self.position += 1;
+            }
+            // The input is a &str and quotes and backslashes are ASCII, so this is a char boundary
+            text.push_str(
+                std::str::from_utf8(&self.bytes[start..self.position]).unwrap_or_default(),
+            );
+            match self.bytes.get(self.position) {
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(text);
+                }
+                Some(b'\\') => {
+                    let escape = self.bytes.get(self.position + 1).copied();
+                    self.position += 2;
+                    match escape {
+                        Some(b'"') => text.push('"'),
+                        Some(b'\\') => text.push('\\'),
+                        Some(b'/') => text.push('/'),
+                        Some(b'n') => text.push('\n'),
+                        Some(b't') => text.push('\t'),
+                        Some(b'r') => text.push('\r'),
+                        Some(b'u') => {
+                            let digits = self
+                                .bytes
+                                .get(self.position..self.position + 4)
+                                .and_then(|digits| std::str::from_utf8(digits).ok())
+                                .and_then(|digits| u32::from_str_radix(digits, 16).ok());
+                            let c = digits.and_then(char::from_u32);
+                            text.push(c.ok_or_else(|| self.error("invalid \\u escape"))?);
+                            self.position += 4;
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                _ => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.position;
+        while self
+            .bytes
+            .get(self.position)
+            .is_some_and(|byte| matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'))
+        {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .map(Json::Number)
+            .ok_or_else(|| self.error("invalid number"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::{TraceFilter, TraceFormat, Tracer};
+    use crate::Quirks;
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Draws a digit that moves right every frame, with a subroutine call in between
+    const PROGRAM: [u8; 16] = [
+        0xF1, 0x29, // LD F, V1
+        0xD0, 0x05, // DRW V0, V0, 5
+        0x22, 0x0A, // CALL 0x20A
+        0x70, 0x01, // ADD V0, 1
+        0x12, 0x00, // JP 0x200
+        0x71, 0x01, // ADD V1, 1
+        0x00, 0xEE, // RET
+        0x00, 0x00,
+    ];
+
+    fn machine() -> Chip8 {
+        let mut chip8 = Chip8::new(Quirks::CHIP_48);
+        chip8.load_program(&PROGRAM).unwrap();
+        chip8
+    }
+
+    fn reference(format: TraceFormat, filter: TraceFilter, frames: usize) -> String {
+        let output = Shared::default();
+        let mut chip8 = machine();
+        chip8.set_tracer(Some(Tracer::new(output.clone(), format, filter)));
+        for _ in 0..frames {
+            chip8.run_frame(7).unwrap();
+        }
+        let text = output.0.lock().unwrap().clone();
+        String::from_utf8(text).unwrap()
+    }
+
+    fn diff(reference: &str) -> DiffReport {
+        compare(
+            &mut machine(),
+            7,
+            &KeyScript::default(),
+            &parse(reference).unwrap(),
+            2,
+        )
+    }
+
+    #[test]
+    fn own_traces_match() {
+        let text = reference(TraceFormat::Text, TraceFilter::default(), 10);
+        assert_eq!(diff(&text).divergence, None);
+        assert_eq!(diff(&text).matched, 70);
+
+        let json = reference(TraceFormat::Json, TraceFilter::default(), 10);
+        assert_eq!(diff(&json).divergence, None);
+    }
+
+    #[test]
+    fn reports_the_first_diverging_register_with_context() {
+        let text = reference(TraceFormat::Text, TraceFilter::default(), 3);
+        let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+        // Claim ADD V0, 1 at record 11 produced 02 instead of 01
+        lines[11] = lines[11].replace("V0:01", "V0:02");
+        lines[12] = lines[12].replace("V0:01", "V0:02");
+        let report = diff(&lines.join("\n"));
+        let divergence = report.divergence.clone().unwrap();
+        assert_eq!((report.matched, divergence.record), (11, 11));
+        assert_eq!(
+            divergence.mismatches,
+            [Mismatch {
+                field: "V0".to_string(),
+                expected: "02".to_string(),
+                found: "01".to_string(),
+            }]
+        );
+        assert_eq!(divergence.context.len(), 3);
+        assert_eq!(divergence.following, [lines[12].clone(), lines[13].clone()]);
+        assert!(report.to_string().contains("V0: expected 02, found 01"));
+    }
+
+    #[test]
+    fn filtered_json_traces_compare_screen_and_stack() {
+        let filter = TraceFilter {
+            opcode_classes: 1 << 0x7,
+            ..TraceFilter::default()
+        };
+        let json = reference(TraceFormat::Json, filter, 5);
+        assert_eq!(diff(&json).divergence, None);
+
+        // Blank out the screen hash the second record starts with
+        let start = json.lines().next().unwrap().len() + 1;
+        let start = start + json[start..].find("\"screen\":\"").unwrap() + 10;
+        let tampered = format!("{}{:016x}{}", &json[..start], 0, &json[start + 16..]);
+        let divergence = diff(&tampered).divergence.unwrap();
+        assert_eq!(divergence.record, 1);
+        assert_eq!(divergence.mismatches[0].field, "screen");
+        assert!(divergence.screen.is_some());
+
+        let record = "{\"cycle\":4,\"pc\":\"0x20C\",\"sp\":1,\"stack\":[520]}";
+        let divergence = diff(record).divergence.unwrap();
+        assert_eq!(divergence.mismatches[0].field, "stack");
+        assert_eq!(divergence.mismatches[0].found, "[0206]");
+    }
+
+    #[test]
+    fn parses_other_emulators_text_logs() {
+        let records = parse("# header\npc=0x200 v0=00 I=0 cycles=12 ; comment\n\nPC:0202").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].line, records[1].cycle), (2, 1));
+        assert_eq!(records[0].registers[0], Some(0));
+        assert_eq!(records[0].index_register, Some(0));
+        assert_eq!(records[1].registers[0], None);
+
+        assert_eq!(
+            parse("V0:00 I:0000"),
+            Err(TraceParseError {
+                line: 1,
+                message: "no PC field".to_string()
+            })
+        );
+        assert!(parse("PC:0200 V3:100").is_err());
+        assert!(parse("{\"pc\":512,}").is_err());
+    }
+}
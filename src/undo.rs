@@ -0,0 +1,367 @@
+//! Undo logs: what each instruction changed, so it can be taken back.
+//!
+//! [`emulate_cycle`] and [`tick_timers`] wrap their [`Chip8`] counterparts and return an
+//! [`UndoEntry`] listing the old value of every register, memory byte, pixel, timer and stack
+//! entry that changed. Undoing entries newest first walks the machine backwards one instruction
+//! at a time, which is what the debugger's reverse stepping is built on.
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use crate::instruction::Instruction;
+use std::ops::Range;
+
+/// A piece of machine state as it was before an instruction changed it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Register {
+        x: u8,
+        old: u8,
+    },
+    IndexRegister(u16),
+    ProgramCounter(u16),
+    StackPointer(u8),
+    Stack {
+        slot: u8,
+        old: u16,
+    },
+    DelayTimer(u8),
+    SoundTimer(u8),
+    Memory {
+        address: u16,
+        old: u8,
+    },
+    /// Screen indices and their old pixel values
+    Pixels(Vec<(u32, u8)>),
+    /// The whole display, when the resolution switched
+    Screen {
+        hires: bool,
+        screen: Vec<u8>,
+    },
+    SelectedPlanes(u8),
+    Pitch(u8),
+    AudioPattern(Option<[u8; 16]>),
+    RplFlags([u8; 16]),
+    Exited(bool),
+    Vblank(bool),
+    /// Position of the random source
+    Random(u64),
+}
+
+/// Everything one instruction or timer tick changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndoEntry {
+    /// Address of the instruction, or None for a timer tick
+    pub pc: Option<u16>,
+    pub changes: Vec<Change>,
+}
+
+impl UndoEntry {
+    /// Restores every change. Entries have to be undone newest first.
+    pub fn undo(&self, chip8: &mut Chip8) {
+        for change in &self.changes {
+            match change {
+                Change::Register { x, old } => chip8.registers[*x as usize] = *old,
+                Change::IndexRegister(old) => chip8.index_register = *old,
+                Change::ProgramCounter(old) => chip8.program_counter = *old,
+                Change::StackPointer(old) => chip8.stack_pointer = *old,
+                Change::Stack { slot, old } => chip8.stack[*slot as usize] = *old,
+                Change::DelayTimer(old) => chip8.delay_timer = *old,
+                Change::SoundTimer(old) => chip8.sound_timer = *old,
+                Change::Memory { address, old } => chip8.memory[*address as usize] = *old,
+                Change::Pixels(pixels) => {
+                    for &(index, old) in pixels {
+                        chip8.screen[index as usize] = old;
+                    }
+                }
+                Change::Screen { hires, screen } => {
+                    chip8.hires = *hires;
+                    chip8.screen = screen.clone();
+                }
+                Change::SelectedPlanes(old) => chip8.selected_planes = *old,
+                Change::Pitch(old) => chip8.pitch = *old,
+                Change::AudioPattern(old) => chip8.audio_pattern = *old,
+                Change::RplFlags(old) => chip8.rpl_flags = *old,
+                Change::Exited(old) => chip8.exited = *old,
+                Change::Vblank(old) => chip8.vblank = *old,
+                Change::Random(old) => chip8.random.restore(*old),
+            }
+        }
+    }
+
+    pub fn is_timer_tick(&self) -> bool {
+        self.pc.is_none()
+    }
+}
+
+/// Runs [`Chip8::emulate_cycle`] and logs what it changed. A faulting instruction may already
+/// have changed something, so its entry is returned too.
+pub fn emulate_cycle(chip8: &mut Chip8) -> (UndoEntry, Result<(), Chip8Error>) {
+    let pc = chip8.program_counter;
+    let instruction = chip8
+        .fetch_opcode()
+        .ok()
+        .and_then(|opcode| Instruction::decode(opcode).ok());
+    let before = Before::capture(chip8, instruction);
+    let result = chip8.emulate_cycle();
+    let entry = UndoEntry {
+        pc: Some(pc),
+        changes: before.changes(chip8),
+    };
+    (entry, result)
+}
+
+/// Runs [`Chip8::tick_timers`] and logs what it changed.
+pub fn tick_timers(chip8: &mut Chip8) -> UndoEntry {
+    let before = Before::capture(chip8, None);
+    chip8.tick_timers();
+    UndoEntry {
+        pc: None,
+        changes: before.changes(chip8),
+    }
+}
+
+/// The memory an instruction stores to, starting at I. Past the end of memory the instruction
+/// faults before writing anything.
+pub(crate) fn memory_writes(chip8: &Chip8, instruction: Instruction) -> Option<Range<usize>> {
+    let start = chip8.index_register as usize;
+    let len = match instruction {
+        Instruction::LdBVx { .. } => 3,
+        Instruction::LdIVx { x } => x as usize + 1,
+        Instruction::LdIVxVy { x, y } => x.abs_diff(y) as usize + 1,
+        _ => return None,
+    };
+    Some(start..start + len)
+}
+
+// The state an instruction might change. Scalars are always copied; memory and the screen
+// only when the instruction can write them
+struct Before {
+    registers: [u8; 16],
+    index_register: u16,
+    program_counter: u16,
+    stack_pointer: u8,
+    stack: [u16; 16],
+    delay_timer: u8,
+    sound_timer: u8,
+    selected_planes: u8,
+    pitch: u8,
+    audio_pattern: Option<[u8; 16]>,
+    rpl_flags: [u8; 16],
+    exited: bool,
+    vblank: bool,
+    random: u64,
+    memory: Option<(usize, Vec<u8>)>,
+    screen: Option<(bool, Vec<u8>)>,
+}
+
+impl Before {
+    fn capture(chip8: &Chip8, instruction: Option<Instruction>) -> Before {
+        let memory = instruction
+            .and_then(|instruction| memory_writes(chip8, instruction))
+            .and_then(|range| Some((range.start, chip8.memory.get(range)?.to_vec())));
+        let screen = instruction
+            .filter(Instruction::writes_screen)
+            .map(|_| (chip8.hires, chip8.screen.clone()));
+        Before {
+            registers: chip8.registers,
+            index_register: chip8.index_register,
+            program_counter: chip8.program_counter,
+            stack_pointer: chip8.stack_pointer,
+            stack: chip8.stack,
+            delay_timer: chip8.delay_timer,
+            sound_timer: chip8.sound_timer,
+            selected_planes: chip8.selected_planes,
+            pitch: chip8.pitch,
+            audio_pattern: chip8.audio_pattern,
+            rpl_flags: chip8.rpl_flags,
+            exited: chip8.exited,
+            vblank: chip8.vblank,
+            random: chip8.random.state(),
+            memory,
+            screen,
+        }
+    }
+
+    fn changes(self, chip8: &Chip8) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for (x, (&old, &new)) in self.registers.iter().zip(&chip8.registers).enumerate() {
+            if old != new {
+                changes.push(Change::Register { x: x as u8, old });
+            }
+        }
+        for (slot, (&old, &new)) in self.stack.iter().zip(&chip8.stack).enumerate() {
+            if old != new {
+                changes.push(Change::Stack {
+                    slot: slot as u8,
+                    old,
+                });
+            }
+        }
+        let mut scalar = |changed: bool, change: Change| {
+            if changed {
+                changes.push(change);
+            }
+        };
+        scalar(
+            self.index_register != chip8.index_register,
+            Change::IndexRegister(self.index_register),
+        );
+        scalar(
+            self.program_counter != chip8.program_counter,
+            Change::ProgramCounter(self.program_counter),
+        );
+        scalar(
+            self.stack_pointer != chip8.stack_pointer,
+            Change::StackPointer(self.stack_pointer),
+        );
+        scalar(
+            self.delay_timer != chip8.delay_timer,
+            Change::DelayTimer(self.delay_timer),
+        );
+        scalar(
+            self.sound_timer != chip8.sound_timer,
+            Change::SoundTimer(self.sound_timer),
+        );
+        scalar(
+            self.selected_planes != chip8.selected_planes,
+            Change::SelectedPlanes(self.selected_planes),
+        );
+        scalar(self.pitch != chip8.pitch, Change::Pitch(self.pitch));
+        scalar(
+            self.audio_pattern != chip8.audio_pattern,
+            Change::AudioPattern(self.audio_pattern),
+        );
+        scalar(
+            self.rpl_flags != chip8.rpl_flags,
+            Change::RplFlags(self.rpl_flags),
+        );
+        scalar(self.exited != chip8.exited, Change::Exited(self.exited));
+        scalar(self.vblank != chip8.vblank, Change::Vblank(self.vblank));
+        scalar(
+            self.random != chip8.random.state(),
+            Change::Random(self.random),
+        );
+
+        if let Some((start, bytes)) = self.memory {
+            for (offset, old) in bytes.into_iter().enumerate() {
+                let address = start + offset;
+                if chip8.memory[address] != old {
+                    changes.push(Change::Memory {
+                        address: address as u16,
+                        old,
+                    });
+                }
+            }
+        }
+        if let Some((hires, screen)) = self.screen {
+            if hires != chip8.hires || screen.len() != chip8.screen.len() {
+                changes.push(Change::Screen { hires, screen });
+            } else {
+                let pixels: Vec<(u32, u8)> = screen
+                    .into_iter()
+                    .zip(&chip8.screen)
+                    .enumerate()
+                    .filter(|&(_, (old, &new))| old != new)
+                    .map(|(index, (old, _))| (index as u32, old))
+                    .collect();
+                if !pixels.is_empty() {
+                    changes.push(Change::Pixels(pixels));
+                }
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    // Runs `cycles` instructions logging each, then undoes them all
+    fn round_trip(quirks: Quirks, program: &[u8], cycles: usize) -> (Vec<UndoEntry>, Chip8) {
+        let mut chip8 = Chip8::new(quirks);
+        chip8.set_seed(1);
+        chip8.load_program(program).unwrap();
+        let start = chip8.save_state();
+        let mut log = Vec::new();
+        for _ in 0..cycles {
+            let (entry, result) = emulate_cycle(&mut chip8);
+            result.unwrap();
+            log.push(entry);
+            log.push(tick_timers(&mut chip8));
+        }
+        let end = chip8.save_state();
+        for entry in log.iter().rev() {
+            entry.undo(&mut chip8);
+        }
+        assert_eq!(chip8.save_state(), start);
+        for entry in &log {
+            if !entry.is_timer_tick() {
+                let _ = emulate_cycle(&mut chip8);
+            } else {
+                tick_timers(&mut chip8);
+            }
+        }
+        assert_eq!(chip8.save_state(), end);
+        (log, chip8)
+    }
+
+    #[test]
+    fn entries_list_only_what_changed() {
+        // LD V3, 0x2A
+        let (log, _) = round_trip(Quirks::default(), &[0x63, 0x2A], 1);
+        assert_eq!(
+            log[0],
+            UndoEntry {
+                pc: Some(0x200),
+                changes: vec![
+                    Change::Register { x: 3, old: 0 },
+                    Change::ProgramCounter(0x200)
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn undoes_memory_screen_stack_and_timers() {
+        let program = [
+            0x60, 0x7B, // LD V0, 123
+            0xA3, 0x00, // LD I, 0x300
+            0xF0, 0x33, // LD B, V0
+            0xF1, 0x29, // LD F, V1
+            0xD1, 0x15, // DRW V1, V1, 5
+            0xF0, 0x15, // LD DT, V0
+            0xC2, 0xFF, // RND V2, 0xFF
+            0x22, 0x12, // CALL 0x212
+            0x00, 0xE0, // CLS
+            0x00, 0xFF, // HIGH
+            0x00, 0xEE, // RET
+        ];
+        let (log, chip8) = round_trip(Quirks::SUPER_CHIP, &program, 11);
+        assert_eq!(chip8.stack_pointer, 0);
+        assert!(log.iter().any(|entry| entry
+            .changes
+            .iter()
+            .any(|change| matches!(change, Change::Memory { address: 0x300, .. }))));
+        assert!(log[8]
+            .changes
+            .iter()
+            .any(|change| matches!(change, Change::Pixels(_))));
+    }
+
+    #[test]
+    fn undoes_xo_chip_state() {
+        let program = [
+            0xF2, 0x01, // PLANE 2
+            0xF0, 0x00, 0x03, 0x00, // LD I, long 0x300
+            0xF0, 0x02, // AUDIO
+            0x60, 0x70, // LD V0, 0x70
+            0xF0, 0x3A, // PITCH V0
+            0xF0, 0x75, // LD R, V0
+            0x00, 0xD2, // SCU 2
+            0x00, 0xFD, // EXIT
+        ];
+        round_trip(Quirks::XO_CHIP, &program, 8);
+    }
+}
@@ -0,0 +1,301 @@
+//! Runs test ROMs headlessly and checks what they leave on screen.
+//!
+//! Screens are compared against the ASCII images in `tests/golden`. Set `UPDATE_GOLDEN=1` to
+//! rewrite them from the current output instead. Timendus' test suite and its goldens aren't
+//! checked in yet; it's ignored by default and needs the ROMs in `tests/roms`, see the README
+//! in that directory.
+
+use chip_8::assembler::assemble;
+use chip_8::headless::{self, KeyScript, RunLimit};
+use chip_8::{Chip8, IndexIncrement, Quirks};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const INSTRUCTIONS_PER_FRAME: usize = 100;
+
+const PRESETS: [(&str, Quirks); 5] = [
+    ("legacy", Quirks::LEGACY),
+    ("vip", Quirks::COSMAC_VIP),
+    ("chip48", Quirks::CHIP_48),
+    ("schip", Quirks::SUPER_CHIP),
+    ("xochip", Quirks::XO_CHIP),
+];
+
+// "OK" as drawn by corax' test ROM, which prints it in every cell that passed
+const OK_GLYPH: [&str; 4] = ["###.#.#", "#.#.##.", "#.#.#.#", "###.#.#"];
+
+// Cells of corax' 6x3 result grid as (opcode, row, column), in the order they are drawn
+const CORAX_CELLS: [(&str, usize, usize); 18] = [
+    ("3XNN", 0, 0),
+    ("4XNN", 1, 0),
+    ("5XY0", 2, 0),
+    ("7XNN", 3, 0),
+    ("9XY0", 4, 0),
+    ("ANNN", 5, 0),
+    ("00EE", 0, 1),
+    ("8XY0", 1, 1),
+    ("8XY1", 2, 1),
+    ("8XY2", 3, 1),
+    ("8XY3", 4, 1),
+    ("8XY4", 5, 1),
+    ("8XY5", 0, 2),
+    ("8XY6", 1, 2),
+    ("8XYE", 2, 2),
+    ("FX55", 3, 2),
+    ("FX33", 4, 2),
+    ("1NNN", 5, 2),
+];
+const CORAX_COLUMNS: [std::ops::Range<usize>; 3] = [0..22, 22..44, 44..64];
+
+fn run(program: &[u8], quirks: Quirks, limit: RunLimit, keys: &KeyScript) -> Chip8 {
+    let mut chip8 = Chip8::new(quirks);
+    chip8.load_program(program).expect("program fits in memory");
+    headless::run(&mut chip8, INSTRUCTIONS_PER_FRAME, limit, keys).expect("program runs");
+    chip8
+}
+
+fn run_source(source: &str, quirks: Quirks) -> Chip8 {
+    let program = assemble(source).unwrap_or_else(|err| panic!("{}\n{}", err, source));
+    run(
+        &program,
+        quirks,
+        RunLimit::Frames(10),
+        &KeyScript::default(),
+    )
+}
+
+// Compares the screen with `tests/golden/<name>.txt`, or rewrites it with UPDATE_GOLDEN set
+fn assert_golden(name: &str, chip8: &Chip8) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.txt", name));
+    let actual = chip8.screen_ascii();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &actual).expect("golden image is writable");
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "missing {}, run with UPDATE_GOLDEN=1 to create it",
+            path.display()
+        )
+    });
+    assert!(
+        actual == expected,
+        "{} differs from {}\n\nexpected:\n{}\nactual:\n{}",
+        name,
+        path.display(),
+        expected,
+        actual
+    );
+}
+
+// Whether the OK glyph appears anywhere in the given rows and columns of the screen
+fn shows_ok(screen: &str, rows: std::ops::Range<usize>, columns: &std::ops::Range<usize>) -> bool {
+    let lines: Vec<&str> = screen.lines().collect();
+    let width = OK_GLYPH[0].len();
+    (rows.start..=rows.end - OK_GLYPH.len()).any(|row| {
+        (columns.start..=columns.end - width).any(|column| {
+            OK_GLYPH
+                .iter()
+                .enumerate()
+                .all(|(offset, glyph)| &lines[row + offset][column..column + width] == *glyph)
+        })
+    })
+}
+
+fn timendus_rom(name: &str) -> Vec<u8> {
+    let path: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/roms")
+        .join(name);
+    fs::read(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+}
+
+#[test]
+fn corax_test_opcode() {
+    let program = include_bytes!("../src/programs/test_opcode.ch8");
+    let chip8 = run(
+        program,
+        Quirks::default(),
+        RunLimit::Frames(60),
+        &KeyScript::default(),
+    );
+    let screen = chip8.screen_ascii();
+
+    let failed: Vec<&str> = CORAX_CELLS
+        .iter()
+        .filter(|&&(_, row, column)| {
+            !shows_ok(&screen, row * 5 + 1..row * 5 + 5, &CORAX_COLUMNS[column])
+        })
+        .map(|&(opcode, _, _)| opcode)
+        .collect();
+    for &(opcode, _, _) in &CORAX_CELLS {
+        let result = if failed.contains(&opcode) {
+            "FAIL"
+        } else {
+            "ok"
+        };
+        eprintln!("{}  {}", opcode, result);
+    }
+    assert!(failed.is_empty(), "failed opcodes: {}", failed.join(", "));
+    assert_golden("test_opcode", &chip8);
+}
+
+#[test]
+#[ignore = "needs Timendus ROMs in tests/roms"]
+fn timendus_suite() {
+    // The quirks test asks for a platform unless one is already stored at 0x1FF
+    let suite: [(&str, &str, Quirks, Option<u8>); 8] = [
+        ("1-chip8-logo.ch8", "chip8-logo", Quirks::default(), None),
+        ("2-ibm-logo.ch8", "ibm-logo", Quirks::default(), None),
+        ("3-corax+.ch8", "corax+", Quirks::default(), None),
+        ("4-flags.ch8", "flags", Quirks::default(), None),
+        ("5-quirks.ch8", "quirks-vip", Quirks::COSMAC_VIP, Some(1)),
+        ("5-quirks.ch8", "quirks-schip", Quirks::SUPER_CHIP, Some(4)),
+        (
+            "5-quirks.ch8",
+            "quirks-schip-modern",
+            Quirks::CHIP_48,
+            Some(2),
+        ),
+        ("5-quirks.ch8", "quirks-xochip", Quirks::XO_CHIP, Some(3)),
+    ];
+    for (rom, golden, quirks, platform) in suite {
+        let program = timendus_rom(rom);
+        let mut chip8 = Chip8::new(quirks);
+        chip8
+            .load_program(&program)
+            .expect("program fits in memory");
+        if let Some(platform) = platform {
+            chip8.memory[0x1FF] = platform;
+        }
+        headless::run(
+            &mut chip8,
+            INSTRUCTIONS_PER_FRAME,
+            RunLimit::Frames(600),
+            &KeyScript::default(),
+        )
+        .unwrap_or_else(|err| panic!("{}: {}", rom, err));
+        assert_golden(golden, &chip8);
+    }
+}
+
+#[test]
+fn flags() {
+    // (operation, Vx, Vy, result, VF), run once with v1 and once with vF as the destination.
+    // Shifts use the same value for both registers so the shift quirk doesn't matter
+    let cases = [
+        ("+=", 1, 2, 3, 0),
+        ("+=", 200, 100, 44, 1),
+        ("-=", 5, 3, 2, 1),
+        ("-=", 5, 5, 0, 1),
+        ("-=", 3, 5, 254, 0),
+        ("=-", 3, 5, 2, 1),
+        ("=-", 5, 3, 254, 0),
+        ("=-", 5, 5, 0, 1),
+        (">>=", 0x04, 0x04, 0x02, 0),
+        (">>=", 0x05, 0x05, 0x02, 1),
+        ("<<=", 0x41, 0x41, 0x82, 0),
+        ("<<=", 0x81, 0x81, 0x02, 1),
+    ];
+    for (operation, vx, vy, result, flag) in cases {
+        let source = format!(
+            "v1 := {vx} v2 := {vy} v1 {op} v2 v3 := vF\n: halt jump halt",
+            vx = vx,
+            vy = vy,
+            op = operation
+        );
+        let chip8 = run_source(&source, Quirks::default());
+        assert_eq!(
+            (chip8.registers[1], chip8.registers[3]),
+            (result, flag),
+            "v1 := {}, v2 := {}, v1 {} v2",
+            vx,
+            vy,
+            operation
+        );
+
+        // The flag is written last, so it wins over the result
+        let source = format!(
+            "vF := {vx} v2 := {vy} vF {op} v2\n: halt jump halt",
+            vx = vx,
+            vy = vy,
+            op = operation
+        );
+        let chip8 = run_source(&source, Quirks::default());
+        assert_eq!(
+            chip8.registers[0xF], flag,
+            "vF := {}, v2 := {}, vF {} v2",
+            vx, vy, operation
+        );
+    }
+}
+
+#[test]
+fn quirk_logic_resets_vf() {
+    for (name, quirks) in PRESETS {
+        let chip8 = run_source("vF := 5 v1 := 3 v0 |= v1\n: halt jump halt", quirks);
+        let expected = if quirks.logic_resets_vf { 0 } else { 5 };
+        assert_eq!(chip8.registers[0xF], expected, "{}", name);
+    }
+}
+
+#[test]
+fn quirk_shift_uses_vy() {
+    for (name, quirks) in PRESETS {
+        let chip8 = run_source("v0 := 0x10 v1 := 3 v0 >>= v1\n: halt jump halt", quirks);
+        let expected = if quirks.shift_uses_vy { 1 } else { 8 };
+        assert_eq!(chip8.registers[0], expected, "{}", name);
+    }
+}
+
+#[test]
+fn quirk_index_increment() {
+    for (name, quirks) in PRESETS {
+        let chip8 = run_source("i := 0x300 save v1\n: halt jump halt", quirks);
+        let expected = match quirks.index_increment {
+            IndexIncrement::Unchanged => 0x300,
+            IndexIncrement::X => 0x301,
+            IndexIncrement::XPlusOne => 0x302,
+        };
+        assert_eq!(chip8.index_register, expected, "{}", name);
+        assert_eq!(&chip8.memory[0x300..0x302], &[0, 0], "{}", name);
+    }
+}
+
+#[test]
+fn quirk_jump_uses_vx() {
+    for (name, quirks) in PRESETS {
+        let program = assemble("v0 := 2 v2 := 4 jump0 0x210").expect("assembles");
+        let chip8 = run(&program, quirks, RunLimit::Cycles(3), &KeyScript::default());
+        let expected = if quirks.jump_uses_vx { 0x214 } else { 0x212 };
+        assert_eq!(chip8.program_counter, expected, "{}", name);
+    }
+}
+
+#[test]
+fn quirk_wrap_sprites() {
+    for (name, quirks) in PRESETS {
+        let chip8 = run_source(
+            "v0 := 60 v1 := 0 i := bar sprite v0 v1 1\n: halt jump halt\n: bar 0xFF",
+            quirks,
+        );
+        assert_eq!(chip8.pixel(63, 0), 1, "{}", name);
+        let expected = if quirks.wrap_sprites { 1 } else { 0 };
+        assert_eq!(chip8.pixel(0, 0), expected, "{}", name);
+    }
+}
+
+#[test]
+fn quirk_display_wait() {
+    for (name, quirks) in PRESETS {
+        let program = assemble(
+            "v0 := 0 i := bar sprite v0 v0 1 sprite v0 v0 1 v1 := 1\n: halt jump halt\n: bar 0x80",
+        )
+        .expect("assembles");
+        let chip8 = run(&program, quirks, RunLimit::Frames(1), &KeyScript::default());
+        // Waiting for vblank leaves the second sprite for the next frame
+        let expected = if quirks.display_wait { 0 } else { 1 };
+        assert_eq!(chip8.registers[1], expected, "{}", name);
+    }
+}